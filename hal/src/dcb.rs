@@ -0,0 +1,412 @@
+//! Data Center Bridging (DCB) configuration from an IEEE 802.1Qaz ETS config.
+//!
+//! With DCB enabled, packets are sorted into 4 or 8 traffic classes (TCs) by the user priority (UP) of their VLAN tag,
+//! and each TC gets its own Rx and Tx queues and its own slice of the Rx and Tx packet buffers.
+//! Three arbiters share the link between the TCs: the Rx packet plane arbiter (RTRPCS/RTRPT4C),
+//! the Tx descriptor plane arbiter (RTTDCS/RTTDT2C) that fetches descriptors from the queues,
+//! and the Tx packet plane arbiter (RTTPCS/RTTPT2C) that sends packets from the Tx packet buffers.
+//!
+//! Every arbiter is a weighted round robin over credits, in 64-byte units: each TC is refilled with
+//! credits in proportion to its ETS bandwidth share and may accumulate up to a maximum.
+//! Strict priority TCs are link strict (LSP): they are served before the ETS TCs, the highest TC first,
+//! and don't take part in the bandwidth split.
+//!
+//! DCB sets MRQC.MRQE to RSS within each TC, so it has to be enabled after RSS is programmed.
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::packet_buffer::{BufferSplit, PacketBufferLayout};
+
+/// Maximum number of traffic classes.
+pub const MAX_TCS: usize = 8;
+/// Number of user priorities.
+pub const NUM_USER_PRIORITIES: usize = 8;
+/// Number of Rx and Tx queues split between the TCs.
+const NUM_QUEUES: u32 = 128;
+
+/// The unit of the arbiters' credits in bytes.
+const CREDIT_QUANTUM: u32 = 64;
+/// The largest credit refill, which is 9 bits wide.
+const MAX_CREDIT_REFILL: u32 = 511;
+/// The largest credit a TC can accumulate.
+const MAX_CREDIT: u32 = 4095;
+
+// Multiple Receive Queues Command Register
+const MRQC_MRQE: Range<usize> = 0..4;
+const MRQC_MRQE_RSS_8TC: u32 = 0x2;
+const MRQC_MRQE_RSS_4TC: u32 = 0x3;
+const MRQC_MRQE_RSS: u32 = 0x1;
+
+// Multiple Transmit Queues Command Register
+const MTQC_RT_ENA: usize = 0;
+const MTQC_NUM_TC_OR_Q: Range<usize> = 2..4;
+const MTQC_NUM_TC_OR_Q_4TC: u32 = 0b10;
+const MTQC_NUM_TC_OR_Q_8TC: u32 = 0b11;
+
+// DCB Receive Packet Plane Control and Status
+const RTRPCS_RRM: usize = 1;
+const RTRPCS_RAC: usize = 2;
+const RTRPCS_ARBDIS: usize = 6;
+
+// DCB Transmit Descriptor Plane Control and Status
+const RTTDCS_TDPAC: usize = 0;
+const RTTDCS_TDRM: usize = 4;
+const RTTDCS_ARBDIS: usize = 6;
+
+// DCB Transmit Packet Plane Control and Status
+const RTTPCS_TPPAC: usize = 5;
+const RTTPCS_ARBDIS: usize = 6;
+const RTTPCS_TPRM: usize = 8;
+const RTTPCS_ARBD: Range<usize> = 22..32;
+/// Arbitration delay of the Tx packet plane arbiter that DCB requires.
+const RTTPCS_ARBD_DCB: u32 = 0x4;
+
+// Credit config of a TC, with the same layout in RTRPT4C, RTTDT2C and RTTPT2C
+const TC_CONFIG_CRQ: Range<usize> = 0..9;
+const TC_CONFIG_BWG: Range<usize> = 9..12;
+const TC_CONFIG_MCL: Range<usize> = 12..24;
+const TC_CONFIG_LSP: usize = 31;
+
+/// Width of a TC in the RTRUP2TC and RTTUP2TC registers.
+const UP2TC_WIDTH: usize = 3;
+
+/// The number of traffic classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumTcs {
+    Tcs4,
+    Tcs8,
+}
+
+impl NumTcs {
+    pub fn count(&self) -> usize {
+        match self {
+            NumTcs::Tcs4 => 4,
+            NumTcs::Tcs8 => 8,
+        }
+    }
+}
+
+/// The transmission selection algorithm of a TC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcSelection {
+    /// Enhanced transmission selection with the given share of the bandwidth, in percent.
+    Ets(u8),
+    /// Strict priority, served before every ETS TC.
+    Strict,
+}
+
+/// An IEEE 802.1Qaz ETS configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcbConfig {
+    pub num_tcs: NumTcs,
+    /// The selection of each TC. Entries of TCs beyond `num_tcs` are ignored.
+    pub selection: [TcSelection; MAX_TCS],
+    /// The TC of each user priority.
+    pub up_to_tc: [u8; NUM_USER_PRIORITIES],
+}
+
+impl DcbConfig {
+    /// Checks that every UP maps to an existing TC and that the ETS shares add up to 100%.
+    /// A configuration where every TC is strict priority has no ETS shares.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let num_tcs = self.num_tcs.count();
+        if self.up_to_tc.iter().any(|&tc| tc as usize >= num_tcs) {
+            return Err("user priority is mapped to a TC that doesn't exist");
+        }
+        let mut total = 0u32;
+        let mut ets_tcs = 0;
+        for selection in &self.selection[..num_tcs] {
+            if let TcSelection::Ets(percent) = *selection {
+                total += percent as u32;
+                ets_tcs += 1;
+            }
+        }
+        if ets_tcs > 0 && total != 100 {
+            return Err("ETS bandwidth shares must add up to 100%");
+        }
+        Ok(())
+    }
+
+    /// The ETS share of a TC, which is 0 for strict priority TCs and TCs that don't exist.
+    fn percent(&self, tc: usize) -> u32 {
+        match self.selection[tc] {
+            TcSelection::Ets(percent) if tc < self.num_tcs.count() => percent as u32,
+            _ => 0,
+        }
+    }
+
+    fn is_strict(&self, tc: usize) -> bool {
+        tc < self.num_tcs.count() && self.selection[tc] == TcSelection::Strict
+    }
+
+    /// Packs the UP to TC map in the layout of RTRUP2TC and RTTUP2TC.
+    fn up_to_tc_register(&self) -> u32 {
+        let mut up2tc = 0;
+        for (up, &tc) in self.up_to_tc.iter().enumerate() {
+            up2tc.set_bits(up * UP2TC_WIDTH..(up + 1) * UP2TC_WIDTH, tc as u32);
+        }
+        up2tc
+    }
+}
+
+/// The arbiter credits of a TC, in 64-byte units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TcCredits {
+    /// Credits added to the TC on every arbitration round.
+    pub refill: u16,
+    /// Credits the TC can accumulate.
+    pub max: u16,
+}
+
+/// Computes the credits of every TC for frames of up to `max_frame_size` bytes.
+///
+/// The refills keep the ratio of the ETS shares while letting the TC with the smallest share send
+/// half a maximum frame per round, and the maximum credit is the TC's share of the largest credit.
+/// Strict priority TCs get the minimum credits, since they aren't limited by them.
+pub fn calculate_credits(config: &DcbConfig, max_frame_size: u16) -> [TcCredits; MAX_TCS] {
+    let min_credit = (max_frame_size as u32 / 2).div_ceil(CREDIT_QUANTUM);
+    let min_percent = (0..MAX_TCS).map(|tc| config.percent(tc)).filter(|&percent| percent > 0).min().unwrap_or(100);
+    let multiplier = min_credit / min_percent + 1;
+
+    let mut credits = [TcCredits::default(); MAX_TCS];
+    for (tc, credit) in credits.iter_mut().enumerate() {
+        let percent = config.percent(tc);
+        let refill = (percent * multiplier).min(MAX_CREDIT_REFILL).max(min_credit);
+        let max = (percent * MAX_CREDIT / 100).max(min_credit);
+        *credit = TcCredits { refill: refill as u16, max: max as u16 };
+    }
+    credits
+}
+
+/// DCB enabled with an ETS configuration.
+pub struct Dcb {
+    config: DcbConfig,
+    credits: [TcCredits; MAX_TCS],
+    packet_buffers: PacketBufferLayout,
+}
+
+impl Dcb {
+    /// Validates the configuration and programs the queue selection, the packet buffer split,
+    /// the UP to TC maps and the three arbiters.
+    /// The packet buffer layout must have one buffer per TC.
+    pub fn enable(
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        config: DcbConfig,
+        packet_buffers: PacketBufferLayout,
+        max_frame_size: u16,
+    ) -> Result<Dcb, &'static str> {
+        config.validate()?;
+        if packet_buffers.num_buffers() != config.num_tcs.count() {
+            return Err("packet buffer layout must have one buffer per TC");
+        }
+        packet_buffers.validate()?;
+        let dcb = Dcb { config, credits: calculate_credits(&config, max_frame_size), packet_buffers };
+
+        let (mrqe, num_tc_or_q) = match config.num_tcs {
+            NumTcs::Tcs4 => (MRQC_MRQE_RSS_4TC, MTQC_NUM_TC_OR_Q_4TC),
+            NumTcs::Tcs8 => (MRQC_MRQE_RSS_8TC, MTQC_NUM_TC_OR_Q_8TC),
+        };
+        mac_regs.multiple_receive_queues_command_register.update(|mrqc| { mrqc.set_bits(MRQC_MRQE, mrqe); });
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, true); });
+        let mut mtqc = 0;
+        mtqc.set_bit(MTQC_RT_ENA, true);
+        mtqc.set_bits(MTQC_NUM_TC_OR_Q, num_tc_or_q);
+        mac_regs.multiple_transmit_queues_command_register.write(mtqc);
+
+        dcb.packet_buffers.write(rx_regs2, mac_regs)?;
+        dcb.set_rx_arbiter(rx_regs2);
+        dcb.set_tx_descriptor_arbiter(rx_regs2);
+        dcb.set_tx_packet_arbiter(mac_regs);
+        Ok(dcb)
+    }
+
+    /// Returns to a single TC with RSS and gives it the whole Rx and Tx packet buffers,
+    /// keeping the Flow Director reservation of the DCB layout.
+    pub fn disable(self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        mac_regs.multiple_receive_queues_command_register.update(|mrqc| { mrqc.set_bits(MRQC_MRQE, MRQC_MRQE_RSS); });
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, true); });
+        mac_regs.multiple_transmit_queues_command_register.write(0);
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.write(0);
+
+        rx_regs2.dcb_receive_packet_plane_control_and_status.write(0);
+        mac_regs.dcb_transmit_packet_plane_control_and_status.write(0);
+        rx_regs2.dcb_receive_user_priority_to_traffic_class.write(0);
+        mac_regs.dcb_transmit_user_priority_to_traffic_class.write(0);
+        for tc in 0..MAX_TCS {
+            rx_regs2.dcb_receive_packet_plane_t4_config[tc].write(0);
+            rx_regs2.dcb_transmit_descriptor_plane_t2_config[tc].write(0);
+            mac_regs.dcb_transmit_packet_plane_t2_config[tc].write(0);
+        }
+        PacketBufferLayout::split(1, BufferSplit::Equal, self.packet_buffers.fdir())?.write(rx_regs2, mac_regs)
+    }
+
+    pub fn config(&self) -> &DcbConfig {
+        &self.config
+    }
+
+    pub fn packet_buffers(&self) -> &PacketBufferLayout {
+        &self.packet_buffers
+    }
+
+    /// The arbiter credits the TCs were programmed with.
+    pub fn credits(&self) -> &[TcCredits; MAX_TCS] {
+        &self.credits
+    }
+
+    /// The TC of a user priority.
+    pub fn tc_for_priority(&self, priority: u8) -> Result<u8, &'static str> {
+        self.config.up_to_tc.get(priority as usize).copied().ok_or("user priority is out of range")
+    }
+
+    /// The Rx queues of a TC, over which its packets are spread with RSS.
+    pub fn rx_queues(&self, tc: u8) -> Result<Range<u8>, &'static str> {
+        self.check_tc(tc)?;
+        let queues_per_tc = (NUM_QUEUES as usize / self.config.num_tcs.count()) as u8;
+        let start = tc * queues_per_tc;
+        Ok(start..start + queues_per_tc)
+    }
+
+    /// The Tx queues of a TC. The hardware doesn't split the Tx queues evenly:
+    /// the first TCs get more queues than the last ones.
+    pub fn tx_queues(&self, tc: u8) -> Result<Range<u8>, &'static str> {
+        self.check_tc(tc)?;
+        let (start, end) = match (self.config.num_tcs, tc) {
+            (NumTcs::Tcs4, 0) => (0, 64),
+            (NumTcs::Tcs4, 1) => (64, 96),
+            (NumTcs::Tcs4, tc) => (96 + (tc - 2) * 16, 112 + (tc - 2) * 16),
+            (NumTcs::Tcs8, 0) => (0, 32),
+            (NumTcs::Tcs8, 1) => (32, 64),
+            (NumTcs::Tcs8, 2) => (64, 80),
+            (NumTcs::Tcs8, 3) => (80, 96),
+            (NumTcs::Tcs8, tc) => (96 + (tc - 4) * 8, 104 + (tc - 4) * 8),
+        };
+        Ok(start..end)
+    }
+
+    fn check_tc(&self, tc: u8) -> Result<(), &'static str> {
+        if tc as usize >= self.config.num_tcs.count() {
+            return Err("TC is out of range");
+        }
+        Ok(())
+    }
+
+    /// The credit config of a TC. Every TC is its own bandwidth group in IEEE mode.
+    fn tc_config(&self, tc: usize) -> u32 {
+        let mut config = 0;
+        config.set_bits(TC_CONFIG_CRQ, self.credits[tc].refill as u32);
+        config.set_bits(TC_CONFIG_BWG, tc as u32);
+        config.set_bits(TC_CONFIG_MCL, self.credits[tc].max as u32);
+        config.set_bit(TC_CONFIG_LSP, self.config.is_strict(tc));
+        config
+    }
+
+    fn set_rx_arbiter(&self, rx_regs2: &mut IntelIxgbeRxRegisters2) {
+        let mut rtrpcs = 0;
+        rtrpcs.set_bit(RTRPCS_ARBDIS, true);
+        rx_regs2.dcb_receive_packet_plane_control_and_status.write(rtrpcs);
+
+        for tc in 0..MAX_TCS {
+            rx_regs2.dcb_receive_packet_plane_t4_config[tc].write(self.tc_config(tc));
+        }
+        rx_regs2.dcb_receive_user_priority_to_traffic_class.write(self.config.up_to_tc_register());
+
+        rtrpcs.set_bit(RTRPCS_RRM, true);
+        rtrpcs.set_bit(RTRPCS_RAC, true);
+        rtrpcs.set_bit(RTRPCS_ARBDIS, false);
+        rx_regs2.dcb_receive_packet_plane_control_and_status.write(rtrpcs);
+    }
+
+    /// Clears the per-queue credits and rate limiters, which DCB doesn't use, and programs the per-TC credits.
+    /// This also re-enables the arbiter that was disabled while MTQC changed.
+    fn set_tx_descriptor_arbiter(&self, rx_regs2: &mut IntelIxgbeRxRegisters2) {
+        for queue in 0..NUM_QUEUES {
+            rx_regs2.dcb_transmit_descriptor_plane_queue_select.write(queue);
+            rx_regs2.dcb_transmit_descriptor_plane_t1_config.write(0);
+            rx_regs2.dcb_transmit_rate_scheduler_config.write(0);
+        }
+
+        for tc in 0..MAX_TCS {
+            rx_regs2.dcb_transmit_descriptor_plane_t2_config[tc].write(self.tc_config(tc));
+        }
+
+        let mut rttdcs = 0;
+        rttdcs.set_bit(RTTDCS_TDPAC, true);
+        rttdcs.set_bit(RTTDCS_TDRM, true);
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.write(rttdcs);
+    }
+
+    fn set_tx_packet_arbiter(&self, mac_regs: &mut IntelIxgbeMacRegisters) {
+        let mut rttpcs = 0;
+        rttpcs.set_bit(RTTPCS_TPPAC, true);
+        rttpcs.set_bit(RTTPCS_TPRM, true);
+        rttpcs.set_bits(RTTPCS_ARBD, RTTPCS_ARBD_DCB);
+        rttpcs.set_bit(RTTPCS_ARBDIS, true);
+        mac_regs.dcb_transmit_packet_plane_control_and_status.write(rttpcs);
+
+        mac_regs.dcb_transmit_user_priority_to_traffic_class.write(self.config.up_to_tc_register());
+        for tc in 0..MAX_TCS {
+            mac_regs.dcb_transmit_packet_plane_t2_config[tc].write(self.tc_config(tc));
+        }
+
+        rttpcs.set_bit(RTTPCS_ARBDIS, false);
+        mac_regs.dcb_transmit_packet_plane_control_and_status.write(rttpcs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(selection: [TcSelection; 4]) -> DcbConfig {
+        let mut full_selection = [TcSelection::Ets(0); MAX_TCS];
+        full_selection[..4].copy_from_slice(&selection);
+        DcbConfig { num_tcs: NumTcs::Tcs4, selection: full_selection, up_to_tc: [0, 0, 1, 1, 2, 2, 3, 3] }
+    }
+
+    #[test]
+    fn equal_shares_get_equal_credits() {
+        let credits = calculate_credits(&config([TcSelection::Ets(25); 4]), 1518);
+        assert_eq!(credits[0], TcCredits { refill: 25, max: 1023 });
+        assert_eq!(credits[3], credits[0]);
+        // TCs that don't exist get the minimum credit of half a frame
+        assert_eq!(credits[4], TcCredits { refill: 12, max: 12 });
+    }
+
+    #[test]
+    fn credits_keep_the_ratio_of_the_shares() {
+        let config = config([TcSelection::Strict, TcSelection::Ets(10), TcSelection::Ets(90), TcSelection::Strict]);
+        let credits = calculate_credits(&config, 1518);
+        assert_eq!(credits[0], TcCredits { refill: 12, max: 12 });
+        assert_eq!(credits[1], TcCredits { refill: 20, max: 409 });
+        assert_eq!(credits[2], TcCredits { refill: 180, max: 3685 });
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert!(config([TcSelection::Ets(25); 4]).validate().is_ok());
+        assert!(config([TcSelection::Strict; 4]).validate().is_ok());
+        assert!(config([TcSelection::Ets(30); 4]).validate().is_err());
+        let mut config = config([TcSelection::Ets(25); 4]);
+        config.up_to_tc[7] = 4;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn up_to_tc_register_layout() {
+        assert_eq!(config([TcSelection::Strict; 4]).up_to_tc_register(), 0x006D_2240);
+    }
+
+    #[test]
+    fn queues_of_each_tc() {
+        let config = config([TcSelection::Ets(25); 4]);
+        let packet_buffers = PacketBufferLayout::split(4, BufferSplit::Equal, None).unwrap();
+        let dcb = Dcb { config, credits: calculate_credits(&config, 1518), packet_buffers };
+        assert_eq!(dcb.rx_queues(3), Ok(96..128));
+        assert_eq!(dcb.tx_queues(1), Ok(64..96));
+        assert_eq!(dcb.tx_queues(3), Ok(112..128));
+        assert!(dcb.tx_queues(4).is_err());
+        assert_eq!(dcb.tc_for_priority(5), Ok(2));
+    }
+}
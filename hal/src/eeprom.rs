@@ -0,0 +1,468 @@
+//! Access to the NVM (EEPROM) through the EERD and EEWR registers.
+//!
+//! The NVM is shared with the firmware and the other LAN function, so every access is done while holding
+//! the EEPROM semaphore in SW_FW_SYNC. SW_FW_SYNC itself is guarded by the SWSM hardware semaphore.
+//! The other resources in SW_FW_SYNC (PHYs, flash, MAC CSRs) are acquired the same way,
+//! so [`acquire_swfw_sync`] and [`release_swfw_sync`] are used by the other NVM and PHY modules too.
+//!
+//! The NVM is made of 16-bit words. The first 64 words hold pointers to the other sections and
+//! the checksum word, which makes the sum of the first 64 words and of the pointed sections 0xBABA.
+
+use bit_field::BitField;
+use crate::IntelIxgbeMacRegisters;
+
+/// Number of times a done or semaphore bit is polled before giving up.
+const EEPROM_POLL_ATTEMPTS: u32 = 100_000;
+/// Number of times SW_FW_SYNC is checked for the resource being free.
+const SWFW_SYNC_ATTEMPTS: u32 = 1_000;
+
+// EEPROM/Flash Control Register
+const EEC_PRES: usize = 8;
+const EEC_ARD: usize = 9;
+const EEC_SIZE: core::ops::Range<usize> = 11..15;
+const EEC_WORD_SIZE_SHIFT: u32 = 6;
+
+// EEPROM Read and Write Registers
+const EERW_START: usize = 0;
+const EERW_DONE: usize = 1;
+const EERW_ADDR: core::ops::Range<usize> = 2..16;
+const EERW_DATA: core::ops::Range<usize> = 16..32;
+/// Number of words that the 14-bit address field of EERD and EEWR can reach.
+const EERW_MAX_WORDS: usize = 1 << 14;
+
+// Software Semaphore Register
+const SWSM_SMBI: usize = 0;
+const SWSM_SWESMBI: usize = 1;
+
+/// Offset of the firmware bits of a resource from its software bit in SW_FW_SYNC.
+const SW_FW_SYNC_FW_SHIFT: usize = 5;
+
+// Words of the NVM
+/// The checksum word, which is the last word covered by the checksum.
+pub const NVM_CHECKSUM_WORD: u16 = 0x3F;
+/// The sum of the words covered by the checksum.
+pub const NVM_CHECKSUM_SUM: u16 = 0xBABA;
+const NVM_PCIE_ANALOG_PTR: u16 = 0x03;
+const NVM_PCIE_GENERAL_PTR: u16 = 0x06;
+const NVM_PCIE_CONFIG0_PTR: u16 = 0x07;
+const NVM_PCIE_CONFIG1_PTR: u16 = 0x08;
+const NVM_CORE0_PTR: u16 = 0x09;
+const NVM_CORE1_PTR: u16 = 0x0A;
+const NVM_MAC0_PTR: u16 = 0x0B;
+const NVM_MAC1_PTR: u16 = 0x0C;
+const NVM_FW_PTR: u16 = 0x0F;
+const NVM_SAN_MAC_PTR: u16 = 0x28;
+const NVM_ETRACK_ID_LOW: u16 = 0x2D;
+const NVM_ETRACK_ID_HIGH: u16 = 0x2E;
+/// Number of words of a section read at a time while computing the checksum.
+const CHECKSUM_CHUNK_WORDS: usize = 64;
+/// Offset of the Ethernet address in the LAN core sections, after the section length word.
+const NVM_CORE_MAC_ADDR_OFFSET: u16 = 1;
+/// Offset of the pass-through patch configuration pointer in the firmware section.
+const NVM_FW_PT_PATCH_CONFIG_PTR: u16 = 0x4;
+/// Offset of the firmware patch version in the pass-through patch configuration section.
+const NVM_FW_PATCH_VERSION: u16 = 0x7;
+
+/// A resource that software and firmware synchronize their accesses to through SW_FW_SYNC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwFwResource {
+    Eeprom = 0,
+    Phy0 = 1,
+    Phy1 = 2,
+    MacCsr = 3,
+    Flash = 4,
+}
+
+/// Takes the SWSM hardware semaphore, which guards SW_FW_SYNC.
+fn acquire_swsm(mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+    // SMBI is set by the hardware when it's read as 0, so reading it as 0 takes the semaphore between drivers
+    let mut acquired = false;
+    for _ in 0..EEPROM_POLL_ATTEMPTS {
+        if !mac_regs.software_semaphore_register.read().get_bit(SWSM_SMBI) {
+            acquired = true;
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    if !acquired {
+        return Err("timed out waiting for the SWSM semaphore");
+    }
+
+    // SWESMBI takes the semaphore between software and firmware
+    for _ in 0..EEPROM_POLL_ATTEMPTS {
+        mac_regs.software_semaphore_register.update(|swsm| { swsm.set_bit(SWSM_SWESMBI, true); });
+        if mac_regs.software_semaphore_register.read().get_bit(SWSM_SWESMBI) {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    release_swsm(mac_regs);
+    Err("timed out waiting for firmware to release the SWSM semaphore")
+}
+
+fn release_swsm(mac_regs: &mut IntelIxgbeMacRegisters) {
+    mac_regs.software_semaphore_register.update(|swsm| {
+        swsm.set_bit(SWSM_SMBI, false);
+        swsm.set_bit(SWSM_SWESMBI, false);
+    });
+}
+
+/// Takes ownership of `resource` from the firmware and the other LAN function.
+pub fn acquire_swfw_sync(mac_regs: &mut IntelIxgbeMacRegisters, resource: SwFwResource) -> Result<(), &'static str> {
+    let sw_bit = resource as usize;
+    let fw_bit = sw_bit + SW_FW_SYNC_FW_SHIFT;
+    for _ in 0..SWFW_SYNC_ATTEMPTS {
+        acquire_swsm(mac_regs)?;
+        let mut sync = mac_regs.software_firmware_synchronization.read();
+        let free = !sync.get_bit(sw_bit) && !sync.get_bit(fw_bit);
+        if free {
+            sync.set_bit(sw_bit, true);
+            mac_regs.software_firmware_synchronization.write(sync);
+        }
+        release_swsm(mac_regs);
+        if free {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err("timed out waiting for a SW_FW_SYNC resource")
+}
+
+/// Gives up ownership of `resource`.
+pub fn release_swfw_sync(mac_regs: &mut IntelIxgbeMacRegisters, resource: SwFwResource) -> Result<(), &'static str> {
+    acquire_swsm(mac_regs)?;
+    mac_regs.software_firmware_synchronization.update(|sync| { sync.set_bit(resource as usize, false); });
+    release_swsm(mac_regs);
+    Ok(())
+}
+
+/// Runs `f` while holding the EEPROM resource.
+/// An error from `f` is returned in preference to an error releasing the resource.
+fn with_eeprom_sync<T>(
+    mac_regs: &mut IntelIxgbeMacRegisters,
+    f: impl FnOnce(&mut IntelIxgbeMacRegisters) -> Result<T, &'static str>,
+) -> Result<T, &'static str> {
+    acquire_swfw_sync(mac_regs, SwFwResource::Eeprom)?;
+    let result = f(mac_regs);
+    let released = release_swfw_sync(mac_regs, SwFwResource::Eeprom);
+    let value = result?;
+    released?;
+    Ok(value)
+}
+
+/// Computes the checksum word of an NVM image whose words are read through `read_words`:
+/// the words before the checksum word and the sections pointed to by words 0x03 to 0x0E
+/// have to add up to 0xBABA. The firmware section pointed to by word 0x0F isn't covered.
+pub fn nvm_checksum(
+    mut read_words: impl FnMut(u16, &mut [u16]) -> Result<(), &'static str>,
+) -> Result<u16, &'static str> {
+    let mut words = [0; NVM_CHECKSUM_WORD as usize];
+    read_words(0, &mut words)?;
+    let mut sum = words.iter().fold(0u16, |sum, &word| sum.wrapping_add(word));
+
+    for ptr in NVM_PCIE_ANALOG_PTR..NVM_FW_PTR {
+        let Some(section) = section_pointer(words[ptr as usize]) else { continue };
+        let mut length = [0];
+        read_words(section, &mut length)?;
+        let length = length[0];
+        if length == 0 || length == 0xFFFF {
+            continue;
+        }
+        let first = section as usize + 1;
+        let last = section_word(section, length)? as usize;
+        let mut buffer = [0; CHECKSUM_CHUNK_WORDS];
+        for start in (first..=last).step_by(CHECKSUM_CHUNK_WORDS) {
+            let chunk = &mut buffer[..(last + 1 - start).min(CHECKSUM_CHUNK_WORDS)];
+            read_words(start as u16, chunk)?;
+            sum = chunk.iter().fold(sum, |sum, &word| sum.wrapping_add(word));
+        }
+    }
+    Ok(NVM_CHECKSUM_SUM.wrapping_sub(sum))
+}
+
+/// Computes the checksum word of an NVM image held in memory, such as one loaded from a file before an update.
+pub fn image_checksum(image: &[u16]) -> Result<u16, &'static str> {
+    nvm_checksum(|offset, words| {
+        let start = offset as usize;
+        let source = image.get(start..start + words.len()).ok_or("EEPROM section is out of range")?;
+        words.copy_from_slice(source);
+        Ok(())
+    })
+}
+
+/// The section pointers in the first words of the NVM. A pointer is `None` if the section isn't present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NvmPointers {
+    pub pcie_analog: Option<u16>,
+    pub pcie_general: Option<u16>,
+    pub pcie_config: [Option<u16>; 2],
+    pub lan_core: [Option<u16>; 2],
+    pub mac: [Option<u16>; 2],
+    pub firmware: Option<u16>,
+    pub san_mac: Option<u16>,
+}
+
+/// Returns the pointer stored in a pointer word, treating erased and zero words as absent.
+fn section_pointer(word: u16) -> Option<u16> {
+    if word == 0 || word == 0xFFFF { None } else { Some(word) }
+}
+
+/// Returns the offset of a word of a section, which is out of range if a corrupt pointer would make it overflow.
+fn section_word(section: u16, offset: u16) -> Result<u16, &'static str> {
+    section.checked_add(offset).ok_or("EEPROM section is out of range")
+}
+
+/// The NVM attached to the device.
+pub struct Eeprom {
+    word_size: u32,
+}
+
+impl Eeprom {
+    /// Checks that an NVM is present and has been loaded by the hardware, and reads its size.
+    pub fn new(mac_regs: &IntelIxgbeMacRegisters) -> Result<Eeprom, &'static str> {
+        let eec = mac_regs.eeprom_flash_control_register.read();
+        if !eec.get_bit(EEC_PRES) {
+            return Err("no EEPROM is present");
+        }
+        if !eec.get_bit(EEC_ARD) {
+            return Err("EEPROM auto-read hasn't completed");
+        }
+        Ok(Eeprom { word_size: 1 << (eec.get_bits(EEC_SIZE) + EEC_WORD_SIZE_SHIFT) })
+    }
+
+    /// The size of the NVM in 16-bit words.
+    pub fn word_size(&self) -> u32 {
+        self.word_size
+    }
+
+    pub fn read_word(&self, mac_regs: &mut IntelIxgbeMacRegisters, offset: u16) -> Result<u16, &'static str> {
+        let mut word = [0];
+        self.read_words(mac_regs, offset, &mut word)?;
+        Ok(word[0])
+    }
+
+    /// Reads consecutive words starting at `offset`.
+    pub fn read_words(&self, mac_regs: &mut IntelIxgbeMacRegisters, offset: u16, words: &mut [u16]) -> Result<(), &'static str> {
+        self.check_range(offset, words.len())?;
+        with_eeprom_sync(mac_regs, |mac_regs| {
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = eerd_read(mac_regs, offset + i as u16)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn write_word(&self, mac_regs: &mut IntelIxgbeMacRegisters, offset: u16, word: u16) -> Result<(), &'static str> {
+        self.write_words(mac_regs, offset, &[word])
+    }
+
+    /// Writes consecutive words starting at `offset`.
+    /// The checksum has to be updated afterwards if any of the words are covered by it.
+    pub fn write_words(&self, mac_regs: &mut IntelIxgbeMacRegisters, offset: u16, words: &[u16]) -> Result<(), &'static str> {
+        self.check_range(offset, words.len())?;
+        with_eeprom_sync(mac_regs, |mac_regs| {
+            for (i, &word) in words.iter().enumerate() {
+                eewr_write(mac_regs, offset + i as u16, word)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn pointers(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<NvmPointers, &'static str> {
+        let mut words = [0; NVM_FW_PTR as usize + 1];
+        self.read_words(mac_regs, 0, &mut words)?;
+        let ptr = |offset: u16| section_pointer(words[offset as usize]);
+        Ok(NvmPointers {
+            pcie_analog: ptr(NVM_PCIE_ANALOG_PTR),
+            pcie_general: ptr(NVM_PCIE_GENERAL_PTR),
+            pcie_config: [ptr(NVM_PCIE_CONFIG0_PTR), ptr(NVM_PCIE_CONFIG1_PTR)],
+            lan_core: [ptr(NVM_CORE0_PTR), ptr(NVM_CORE1_PTR)],
+            mac: [ptr(NVM_MAC0_PTR), ptr(NVM_MAC1_PTR)],
+            firmware: ptr(NVM_FW_PTR),
+            san_mac: section_pointer(self.read_word(mac_regs, NVM_SAN_MAC_PTR)?),
+        })
+    }
+
+    /// Returns the factory MAC address of LAN function `lan` (0 or 1), from its LAN core section.
+    pub fn mac_address(&self, mac_regs: &mut IntelIxgbeMacRegisters, lan: usize) -> Result<[u8; 6], &'static str> {
+        let core = *self.pointers(mac_regs)?.lan_core.get(lan).ok_or("LAN function is out of range")?;
+        let core = core.ok_or("EEPROM has no LAN core section")?;
+        self.read_mac_address(mac_regs, section_word(core, NVM_CORE_MAC_ADDR_OFFSET)?)
+    }
+
+    /// Returns the SAN MAC address of LAN function `lan` (0 or 1), if the NVM has a SAN MAC section.
+    pub fn san_mac_address(&self, mac_regs: &mut IntelIxgbeMacRegisters, lan: usize) -> Result<Option<[u8; 6]>, &'static str> {
+        if lan > 1 {
+            return Err("LAN function is out of range");
+        }
+        match section_pointer(self.read_word(mac_regs, NVM_SAN_MAC_PTR)?) {
+            // the section starts with the capabilities word, followed by the address of each function
+            Some(san_mac) => self.read_mac_address(mac_regs, section_word(san_mac, 1 + 3 * lan as u16)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the EtrackID, which identifies the NVM image version.
+    pub fn etrack_id(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<u32, &'static str> {
+        let low = self.read_word(mac_regs, NVM_ETRACK_ID_LOW)?;
+        let high = self.read_word(mac_regs, NVM_ETRACK_ID_HIGH)?;
+        Ok(((high as u32) << 16) | low as u32)
+    }
+
+    /// Returns the patch version of the manageability firmware stored in the NVM.
+    pub fn firmware_version(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<u16, &'static str> {
+        let fw = section_pointer(self.read_word(mac_regs, NVM_FW_PTR)?).ok_or("EEPROM has no firmware section")?;
+        let patch_config = section_pointer(self.read_word(mac_regs, section_word(fw, NVM_FW_PT_PATCH_CONFIG_PTR)?)?)
+            .ok_or("EEPROM has no firmware patch configuration section")?;
+        self.read_word(mac_regs, section_word(patch_config, NVM_FW_PATCH_VERSION)?)
+    }
+
+    /// Computes the checksum word from the contents of the NVM, as [`nvm_checksum`] describes.
+    pub fn calculate_checksum(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<u16, &'static str> {
+        nvm_checksum(|offset, words| self.read_words(mac_regs, offset, words))
+    }
+
+    /// Checks the checksum word against the contents of the NVM.
+    pub fn verify_checksum(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        let checksum = self.calculate_checksum(mac_regs)?;
+        if self.read_word(mac_regs, NVM_CHECKSUM_WORD)? != checksum {
+            return Err("EEPROM checksum is invalid");
+        }
+        Ok(())
+    }
+
+    /// Recomputes the checksum word and writes it.
+    pub fn update_checksum(&self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        let checksum = self.calculate_checksum(mac_regs)?;
+        self.write_word(mac_regs, NVM_CHECKSUM_WORD, checksum)
+    }
+
+    /// Reads a MAC address stored as 3 words, the first byte in the low byte of the first word.
+    fn read_mac_address(&self, mac_regs: &mut IntelIxgbeMacRegisters, offset: u16) -> Result<[u8; 6], &'static str> {
+        let mut words = [0; 3];
+        self.read_words(mac_regs, offset, &mut words)?;
+        let mut mac = [0; 6];
+        for (bytes, word) in mac.chunks_exact_mut(2).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(mac)
+    }
+
+    /// Checks that the words are in the NVM and can be addressed by EERD and EEWR,
+    /// which also keeps `offset` plus the index of any of the words within a `u16`.
+    fn check_range(&self, offset: u16, len: usize) -> Result<(), &'static str> {
+        if offset as usize + len > (self.word_size as usize).min(EERW_MAX_WORDS) {
+            return Err("EEPROM access is out of range");
+        }
+        Ok(())
+    }
+}
+
+/// Reads a word through EERD. The EEPROM resource must be held.
+fn eerd_read(mac_regs: &mut IntelIxgbeMacRegisters, offset: u16) -> Result<u16, &'static str> {
+    let mut eerd = 0;
+    eerd.set_bits(EERW_ADDR, offset as u32);
+    eerd.set_bit(EERW_START, true);
+    mac_regs.eeprom_read_register.write(eerd);
+
+    for _ in 0..EEPROM_POLL_ATTEMPTS {
+        let eerd = mac_regs.eeprom_read_register.read();
+        if eerd.get_bit(EERW_DONE) {
+            return Ok(eerd.get_bits(EERW_DATA) as u16);
+        }
+        core::hint::spin_loop();
+    }
+    Err("EEPROM read timed out")
+}
+
+/// Writes a word through EEWR. The EEPROM resource must be held.
+fn eewr_write(mac_regs: &mut IntelIxgbeMacRegisters, offset: u16, word: u16) -> Result<(), &'static str> {
+    // a previous write has to complete before the next one is started
+    wait_for_eewr_done(mac_regs)?;
+    let mut eewr = 0;
+    eewr.set_bits(EERW_DATA, word as u32);
+    eewr.set_bits(EERW_ADDR, offset as u32);
+    eewr.set_bit(EERW_START, true);
+    mac_regs.eeprom_write_register.write(eewr);
+    wait_for_eewr_done(mac_regs)
+}
+
+fn wait_for_eewr_done(mac_regs: &IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+    for _ in 0..EEPROM_POLL_ATTEMPTS {
+        if mac_regs.eeprom_write_register.read().get_bit(EERW_DONE) {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err("EEPROM write timed out")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The checksum of [`fixture_image`], computed the way the Linux ixgbe driver does.
+    const FIXTURE_CHECKSUM: u16 = 0xBEB9;
+
+    /// An image with a PCIe analog, PHY, option ROM, LAN core 0 and firmware section,
+    /// an erased PCIe general pointer and zero pointers for the other sections.
+    fn fixture_image() -> [u16; 0x100] {
+        let mut image = [0; 0x100];
+        for (i, word) in image[..NVM_CHECKSUM_WORD as usize].iter_mut().enumerate() {
+            *word = 0x1000 + i as u16 * 0x111;
+        }
+        image[0x03..0x10].copy_from_slice(&[0x40, 0x50, 0x60, 0xFFFF, 0, 0, 0x70, 0, 0, 0, 0, 0, 0x80]);
+        for (section, length) in [(0x40, 4), (0x50, 2), (0x60, 3), (0x70, 5), (0x80, 8)] {
+            image[section] = length;
+            for j in 1..=length as usize {
+                image[section + j] = (section * 0x31 + j * 0x1F3) as u16;
+            }
+        }
+        image[NVM_CHECKSUM_WORD as usize] = FIXTURE_CHECKSUM;
+        image
+    }
+
+    #[test]
+    fn checksum_of_a_known_good_image() {
+        assert_eq!(image_checksum(&fixture_image()), Ok(FIXTURE_CHECKSUM));
+    }
+
+    #[test]
+    fn phy_and_option_rom_sections_are_covered() {
+        for word in [0x51, 0x62] {
+            let mut image = fixture_image();
+            image[word] ^= 0x1;
+            assert_ne!(image_checksum(&image), Ok(FIXTURE_CHECKSUM));
+        }
+    }
+
+    #[test]
+    fn firmware_section_and_checksum_word_are_not_covered() {
+        let mut image = fixture_image();
+        image[0x84] ^= 0xFFFF;
+        image[NVM_CHECKSUM_WORD as usize] = 0;
+        assert_eq!(image_checksum(&image), Ok(FIXTURE_CHECKSUM));
+    }
+
+    #[test]
+    fn corrupt_section_is_an_error() {
+        let mut image = fixture_image();
+        image[0x40] = 0xFFF0;
+        assert!(image_checksum(&image).is_err());
+
+        let mut image = fixture_image();
+        image[NVM_CORE0_PTR as usize] = 0xFFFE;
+        assert!(image_checksum(&image).is_err());
+    }
+
+    #[test]
+    fn eeprom_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::MAC_REGISTERS_OFFSET;
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, eeprom_flash_control_register), 0x10010);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, eeprom_read_register), 0x10014);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, eeprom_write_register), 0x10018);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, software_semaphore_register), 0x10140);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, software_firmware_synchronization), 0x10160);
+    }
+}
@@ -0,0 +1,415 @@
+//! FCoE offload: FC CRC checking, direct data placement (DDP) and FCoE redirection.
+//!
+//! FCoE frames are recognized by an EtherType filter with its FCoE bit set, after which the hardware checks their FC CRC
+//! and, when the exchange ID (XID) of a frame has a valid DDP context, places its payload directly into the
+//! buffers of that exchange. A DDP context has a DMA part, with the user descriptor list (UDL) of buffer addresses
+//! and how much of the first and last buffers is used, and a filter part that tracks the frame sequence.
+//! Both parts are read and written indirectly: the context registers are staged and then
+//! committed to the XID selected in FCDMARW and FCFLTRW.
+//!
+//! FCoE frames can be spread over up to 8 Rx queues by the FCoE redirection table, indexed by the XID.
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::queue_filter::{EtqfSlots, EtqfUser, ETH_P_FCOE, ETH_P_FIP};
+
+/// Number of DDP contexts, one per exchange ID.
+pub const NUM_DDP_CONTEXTS: usize = 512;
+/// Number of entries in the FCoE redirection table.
+pub const FCRETA_ENTRIES: usize = 8;
+/// Largest number of buffers in a user descriptor list.
+pub const MAX_DDP_BUFFERS: u16 = 256;
+/// Alignment of a user descriptor list.
+pub const UDL_ALIGNMENT: u64 = 16;
+/// Number of Rx queues a packet can be steered to.
+const NUM_RX_QUEUES: u8 = 128;
+
+const ETQF_ETHER_TYPE: Range<usize> = 0..16;
+const ETQF_FCOE: usize = 27;
+const ETQF_FILTER_EN: usize = 31;
+
+// EType Queue Select
+const ETQS_RX_QUEUE: Range<usize> = 16..23;
+const ETQS_QUEUE_EN: usize = 31;
+
+/// Number of times the DMA context is polled for invalidation before giving up.
+const DDP_POLL_ATTEMPTS: u32 = 10_000;
+
+// FC Receive Control
+const FCRXCTRL_LASTSEQH: usize = 3;
+const FCRXCTRL_FCCRCBO: usize = 7;
+const FCRXCTRL_FCOEVER: Range<usize> = 8..12;
+const FCOE_VERSION: u32 = 0;
+
+// FC Buffer Control
+const FCBUFF_VALID: usize = 0;
+const FCBUFF_BUFFSIZE: Range<usize> = 3..5;
+const FCBUFF_WRCONTX: usize = 7;
+const FCBUFF_BUFFCNT: Range<usize> = 8..16;
+const FCBUFF_OFFSET: Range<usize> = 16..32;
+
+// FC Receive DMA RW
+const FCDMARW_FCOESEL: Range<usize> = 0..9;
+const FCDMARW_WE: usize = 14;
+const FCDMARW_RE: usize = 15;
+const FCDMARW_LASTSIZE: Range<usize> = 16..32;
+
+// FC FLT Context
+const FCFLT_VALID: usize = 0;
+
+// FC Filter RW Control
+const FCFLTRW_FCOESEL: Range<usize> = 0..9;
+const FCFLTRW_WE: usize = 14;
+
+// FCoE Redirection Control and Table
+const FCRECTL_ENA: usize = 0;
+const FCRETA_ENTRY: Range<usize> = 0..7;
+
+/// The size of every buffer in a user descriptor list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DdpBufferSize {
+    Size4K = 0,
+    Size8K = 1,
+    Size16K = 2,
+    Size64K = 3,
+}
+
+impl DdpBufferSize {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            DdpBufferSize::Size4K => 4 * 1024,
+            DdpBufferSize::Size8K => 8 * 1024,
+            DdpBufferSize::Size16K => 16 * 1024,
+            DdpBufferSize::Size64K => 64 * 1024,
+        }
+    }
+}
+
+/// The buffers that the payload of an exchange is placed into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DdpContext {
+    /// Physical address of the user descriptor list, an array of the 64-bit physical addresses of the buffers.
+    pub user_descriptor_list: u64,
+    /// Number of buffers in the user descriptor list.
+    pub buffer_count: u16,
+    pub buffer_size: DdpBufferSize,
+    /// Offset of the payload in the first buffer.
+    pub first_offset: u16,
+    /// Number of bytes used in the last buffer.
+    pub last_size: u16,
+    /// The exchange is a target write rather than an initiator read, so the context is written back on completion.
+    pub target: bool,
+}
+
+impl DdpContext {
+    fn validate(&self) -> Result<(), &'static str> {
+        if !self.user_descriptor_list.is_multiple_of(UDL_ALIGNMENT) {
+            return Err("user descriptor list must be 16-byte aligned");
+        }
+        if self.buffer_count == 0 || self.buffer_count > MAX_DDP_BUFFERS {
+            return Err("DDP buffer count must be between 1 and 256");
+        }
+        let buffer_size = self.buffer_size.bytes();
+        if self.first_offset as u32 >= buffer_size {
+            return Err("DDP first buffer offset must be within the buffer");
+        }
+        if self.last_size == 0 || self.last_size as u32 > buffer_size {
+            return Err("DDP last buffer size must be between 1 and the buffer size");
+        }
+        Ok(())
+    }
+}
+
+/// FCoE statistics accumulated from the clear on read counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FcoeStats {
+    /// Frames with a good Ethernet CRC but a bad FC CRC.
+    pub crc_errors: u64,
+    /// Frames with an error in the last frame of a sequence.
+    pub last_errors: u64,
+    pub rx_dropped: u64,
+    pub rx_packets: u64,
+    pub rx_dwords: u64,
+    pub tx_packets: u64,
+    pub tx_dwords: u64,
+}
+
+/// FCoE offload, with the DDP contexts in use.
+pub struct FcoeOffload {
+    contexts: [u64; NUM_DDP_CONTEXTS / 64],
+    stats: FcoeStats,
+    /// The EtherType filters that recognize FCoE and FIP packets.
+    fcoe_etqf_index: usize,
+    fip_etqf_index: usize,
+}
+
+impl FcoeOffload {
+    /// Enables the FCoE EtherType filter and FC CRC checking, and steers FCoE frames to the given Rx queues.
+    /// FIP frames go to the first of those queues. The FCoE and FIP EtherType filters are acquired from `slots`.
+    pub fn enable(
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        slots: &mut EtqfSlots,
+        queues: &[u8],
+    ) -> Result<FcoeOffload, &'static str> {
+        check_redirection(queues)?;
+        let fcoe_etqf_index = slots.acquire(ETH_P_FCOE, EtqfUser::Fcoe)?;
+        let fip_etqf_index = match slots.acquire(ETH_P_FIP, EtqfUser::Fcoe) {
+            Ok(index) => index,
+            Err(e) => {
+                slots.release(ETH_P_FCOE, EtqfUser::Fcoe)?;
+                return Err(e);
+            }
+        };
+        let fcoe = FcoeOffload { contexts: [0; NUM_DDP_CONTEXTS / 64], stats: FcoeStats::default(), fcoe_etqf_index, fip_etqf_index };
+        fcoe.set_redirection(mac_regs, queues)?;
+
+        let mut etqf = 0;
+        etqf.set_bits(ETQF_ETHER_TYPE, ETH_P_FCOE as u32);
+        etqf.set_bit(ETQF_FCOE, true);
+        etqf.set_bit(ETQF_FILTER_EN, true);
+        rx_regs2.etq_filter[fcoe_etqf_index].write(etqf);
+
+        let mut etqf = 0;
+        etqf.set_bits(ETQF_ETHER_TYPE, ETH_P_FIP as u32);
+        etqf.set_bit(ETQF_FILTER_EN, true);
+        rx_regs2.etq_filter[fip_etqf_index].write(etqf);
+        mac_regs.e_type_queue_select[fip_etqf_index].write(etqs(queues[0]));
+
+        let mut fcrxctrl = 0;
+        fcrxctrl.set_bit(FCRXCTRL_FCCRCBO, true);
+        fcrxctrl.set_bits(FCRXCTRL_FCOEVER, FCOE_VERSION);
+        rx_regs2.fc_receive_control.write(fcrxctrl);
+        Ok(fcoe)
+    }
+
+    /// Tears down the DDP contexts in use, disables the FCoE filters and redirection,
+    /// and releases the EtherType filters to `slots`.
+    pub fn disable(
+        mut self,
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        slots: &mut EtqfSlots,
+    ) -> Result<(), &'static str> {
+        for xid in 0..NUM_DDP_CONTEXTS as u16 {
+            if self.ddp_in_use(xid) {
+                self.teardown_ddp(rx_regs2, xid)?;
+            }
+        }
+        rx_regs2.fc_receive_control.write(0);
+        for ether_type in [ETH_P_FCOE, ETH_P_FIP] {
+            let (index, _) = slots.release(ether_type, EtqfUser::Fcoe)?;
+            rx_regs2.etq_filter[index].write(0);
+            mac_regs.e_type_queue_select[index].write(0);
+        }
+        mac_regs.fcoe_redirection_control.write(0);
+        Ok(())
+    }
+
+    /// Spreads FCoE frames over up to 8 Rx queues by their XID. With a single queue, the redirection table
+    /// is disabled and the FCoE EtherType filter steers the frames to the queue instead.
+    pub fn set_redirection(&self, mac_regs: &mut IntelIxgbeMacRegisters, queues: &[u8]) -> Result<(), &'static str> {
+        check_redirection(queues)?;
+
+        if queues.len() == 1 {
+            mac_regs.fcoe_redirection_control.write(0);
+            mac_regs.e_type_queue_select[self.fcoe_etqf_index].write(etqs(queues[0]));
+            return Ok(());
+        }
+        for (entry, fcreta) in mac_regs.fc_oe_redirection_table.iter_mut().enumerate() {
+            let mut value = 0;
+            value.set_bits(FCRETA_ENTRY, queues[entry % queues.len()] as u32);
+            fcreta.write(value);
+        }
+        mac_regs.e_type_queue_select[self.fcoe_etqf_index].write(0);
+        let mut fcrectl = 0;
+        fcrectl.set_bit(FCRECTL_ENA, true);
+        mac_regs.fcoe_redirection_control.write(fcrectl);
+        Ok(())
+    }
+
+    /// Programs the DDP context of an exchange. For a target exchange, the header of the last frame of a sequence
+    /// is also posted to the Rx queue, so the sequence completion is reported.
+    pub fn setup_ddp(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, xid: u16, context: &DdpContext) -> Result<(), &'static str> {
+        check_xid(xid)?;
+        if self.ddp_in_use(xid) {
+            return Err("DDP context is already in use");
+        }
+        context.validate()?;
+
+        let mut fcbuff = 0;
+        fcbuff.set_bits(FCBUFF_BUFFSIZE, context.buffer_size as u32);
+        // a count of 256 buffers wraps to 0
+        fcbuff.set_bits(FCBUFF_BUFFCNT, context.buffer_count as u32 & 0xFF);
+        fcbuff.set_bits(FCBUFF_OFFSET, context.first_offset as u32);
+        fcbuff.set_bit(FCBUFF_WRCONTX, context.target);
+        fcbuff.set_bit(FCBUFF_VALID, true);
+
+        let mut fcdmarw = 0;
+        fcdmarw.set_bits(FCDMARW_FCOESEL, xid as u32);
+        fcdmarw.set_bit(FCDMARW_WE, true);
+        fcdmarw.set_bits(FCDMARW_LASTSIZE, context.last_size as u32);
+
+        rx_regs2.fc_user_descriptor_ptr_low.write(context.user_descriptor_list as u32);
+        rx_regs2.fc_user_descriptor_ptr_high.write((context.user_descriptor_list >> 32) as u32);
+        rx_regs2.fc_buffer_control.write(fcbuff);
+        rx_regs2.fc_receive_dma_rw.write(fcdmarw);
+
+        let mut fcflt = 0;
+        fcflt.set_bit(FCFLT_VALID, true);
+        rx_regs2.fc_offset_parameter.write(0);
+        rx_regs2.fc_flt_context.write(fcflt);
+        rx_regs2.fc_filter_rw_control.write(filter_select(xid));
+
+        if context.target {
+            let mut fcrxctrl = rx_regs2.fc_receive_control.read();
+            fcrxctrl.set_bit(FCRXCTRL_LASTSEQH, true);
+            rx_regs2.fc_receive_control.write(fcrxctrl);
+        }
+
+        self.contexts[xid as usize / 64].set_bit(xid as usize % 64, true);
+        Ok(())
+    }
+
+    /// Invalidates the DDP context of an exchange, and waits for the hardware to stop using it
+    /// so that its buffers can be reused. The context stays in use if the invalidation times out.
+    pub fn teardown_ddp(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, xid: u16) -> Result<(), &'static str> {
+        check_xid(xid)?;
+        if !self.ddp_in_use(xid) {
+            return Err("DDP context isn't in use");
+        }
+
+        rx_regs2.fc_flt_context.write(0);
+        rx_regs2.fc_filter_rw_control.write(filter_select(xid));
+
+        let mut fcdmarw = 0;
+        fcdmarw.set_bits(FCDMARW_FCOESEL, xid as u32);
+        fcdmarw.set_bit(FCDMARW_WE, true);
+        rx_regs2.fc_buffer_control.write(0);
+        rx_regs2.fc_receive_dma_rw.write(fcdmarw);
+
+        fcdmarw.set_bit(FCDMARW_WE, false);
+        fcdmarw.set_bit(FCDMARW_RE, true);
+        for _ in 0..DDP_POLL_ATTEMPTS {
+            rx_regs2.fc_receive_dma_rw.write(fcdmarw);
+            if !rx_regs2.fc_buffer_control.read().get_bit(FCBUFF_VALID) {
+                self.contexts[xid as usize / 64].set_bit(xid as usize % 64, false);
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("DDP context invalidation timed out")
+    }
+
+    pub fn ddp_in_use(&self, xid: u16) -> bool {
+        (xid as usize) < NUM_DDP_CONTEXTS && self.contexts[xid as usize / 64].get_bit(xid as usize % 64)
+    }
+
+    /// The number of DDP contexts in use.
+    pub fn num_ddp_in_use(&self) -> u32 {
+        self.contexts.iter().map(|contexts| contexts.count_ones()).sum()
+    }
+
+    /// Adds the counts since the last update to the totals and returns them.
+    pub fn update_stats(&mut self, rx_regs2: &IntelIxgbeRxRegisters2, mac_regs: &IntelIxgbeMacRegisters) -> &FcoeStats {
+        self.stats.crc_errors += rx_regs2.fc_crc_error_count.read() as u64;
+        self.stats.last_errors += rx_regs2.fc_last_error_count.read() as u64;
+        self.stats.rx_dropped += rx_regs2.fcoe_rx_packets_dropped_count.read() as u64;
+        self.stats.rx_packets += rx_regs2.fcoe_packets_received_count.read() as u64;
+        self.stats.rx_dwords += rx_regs2.fcoe_dword_received_count.read() as u64;
+        self.stats.tx_packets += mac_regs.fcoe_packets_transmitted_count.read() as u64;
+        self.stats.tx_dwords += mac_regs.fcoe_dword_transmitted_count.read() as u64;
+        &self.stats
+    }
+
+    pub fn stats(&self) -> &FcoeStats {
+        &self.stats
+    }
+}
+
+fn check_redirection(queues: &[u8]) -> Result<(), &'static str> {
+    if queues.is_empty() || queues.len() > FCRETA_ENTRIES {
+        return Err("FCoE redirection needs between 1 and 8 queues");
+    }
+    if queues.iter().any(|&queue| queue >= NUM_RX_QUEUES) {
+        return Err("Rx queue is out of range");
+    }
+    Ok(())
+}
+
+fn check_xid(xid: u16) -> Result<(), &'static str> {
+    if xid as usize >= NUM_DDP_CONTEXTS {
+        return Err("exchange ID is out of range");
+    }
+    Ok(())
+}
+
+/// The FCFLTRW value that commits the staged filter context to an exchange.
+fn filter_select(xid: u16) -> u32 {
+    let mut fcfltrw = 0;
+    fcfltrw.set_bits(FCFLTRW_FCOESEL, xid as u32);
+    fcfltrw.set_bit(FCFLTRW_WE, true);
+    fcfltrw
+}
+
+/// The ETQS value that steers packets matched by an EtherType filter to a queue.
+fn etqs(queue: u8) -> u32 {
+    let mut etqs = 0;
+    etqs.set_bits(ETQS_RX_QUEUE, queue as u32);
+    etqs.set_bit(ETQS_QUEUE_EN, true);
+    etqs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> DdpContext {
+        DdpContext {
+            user_descriptor_list: 0x1_0000_0010,
+            buffer_count: 4,
+            buffer_size: DdpBufferSize::Size4K,
+            first_offset: 0x200,
+            last_size: 0x800,
+            target: false,
+        }
+    }
+
+    #[test]
+    fn valid_ddp_contexts_are_accepted() {
+        assert_eq!(context().validate(), Ok(()));
+        let full = DdpContext { buffer_count: MAX_DDP_BUFFERS, last_size: 4096, ..context() };
+        assert_eq!(full.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_ddp_contexts_are_rejected() {
+        assert!(DdpContext { user_descriptor_list: 0x1008, ..context() }.validate().is_err());
+        assert!(DdpContext { buffer_count: 0, ..context() }.validate().is_err());
+        assert!(DdpContext { buffer_count: MAX_DDP_BUFFERS + 1, ..context() }.validate().is_err());
+        assert!(DdpContext { first_offset: 4096, ..context() }.validate().is_err());
+        assert!(DdpContext { last_size: 0, ..context() }.validate().is_err());
+        assert!(DdpContext { last_size: 4097, ..context() }.validate().is_err());
+        // the offset and last size are checked against the buffer size
+        assert_eq!(DdpContext { buffer_size: DdpBufferSize::Size8K, last_size: 8192, ..context() }.validate(), Ok(()));
+    }
+
+    #[test]
+    fn redirection_needs_one_to_eight_queues() {
+        assert!(check_redirection(&[]).is_err());
+        assert_eq!(check_redirection(&[0]), Ok(()));
+        assert_eq!(check_redirection(&[0, 1, 2, 3, 4, 5, 6, 127]), Ok(()));
+        assert!(check_redirection(&[0; FCRETA_ENTRIES + 1]).is_err());
+        assert!(check_redirection(&[1, NUM_RX_QUEUES]).is_err());
+    }
+
+    #[test]
+    fn exchange_ids_select_their_context() {
+        assert_eq!(check_xid(NUM_DDP_CONTEXTS as u16 - 1), Ok(()));
+        assert!(check_xid(NUM_DDP_CONTEXTS as u16).is_err());
+        assert_eq!(filter_select(0x1FF), 0x41FF);
+        assert_eq!(etqs(5), 0x8005_0000);
+    }
+}
@@ -0,0 +1,455 @@
+//! Access to the SPI flash that holds the NVM image.
+//!
+//! Flash is read through the manageability flash interface (FLMNGCTL/FLMNGDATA), which issues
+//! the SPI read commands in hardware. FLMNGCTL also has write and erase commands, but erase and program
+//! are done here by bit-banging SPI commands through FLA after software has been granted direct access
+//! to the flash pins, so that a whole page is programmed by one command.
+//!
+//! Both are hidden behind the [`FlashDevice`] trait so that an image update can be run against
+//! [`SimulatedFlash`], an in-memory model with the erase and program semantics of NOR flash.
+
+use bit_field::BitField;
+use crate::IntelIxgbeMacRegisters;
+use crate::eeprom::{acquire_swfw_sync, release_swfw_sync, SwFwResource};
+
+/// Size of the smallest erasable unit of the flash.
+pub const FLASH_SECTOR_SIZE: usize = 4096;
+/// Size of a program page; a program command can't cross a page boundary.
+pub const FLASH_PAGE_SIZE: usize = 256;
+/// The value of erased flash bytes.
+pub const FLASH_ERASED: u8 = 0xFF;
+
+/// Number of times a grant, busy or done bit is polled before giving up.
+const FLASH_POLL_ATTEMPTS: u32 = 1_000_000;
+
+// EEPROM/Flash Control Register
+const EEC_FWE: core::ops::Range<usize> = 4..6;
+const EEC_FWE_DISABLED: u32 = 0b01;
+const EEC_FWE_ENABLED: u32 = 0b10;
+
+// Flash Access Register
+const FLA_FL_SCK: usize = 0;
+const FLA_FL_CE: usize = 1;
+const FLA_FL_SI: usize = 2;
+const FLA_FL_SO: usize = 3;
+const FLA_FL_REQ: usize = 4;
+const FLA_FL_GNT: usize = 5;
+const FLA_FL_BUSY: usize = 30;
+
+// Manageability Flash Control Register
+const FLMNGCTL_ADDR: core::ops::Range<usize> = 0..24;
+const FLMNGCTL_CMD: core::ops::Range<usize> = 24..26;
+const FLMNGCTL_CMD_READ: u32 = 0b00;
+const FLMNGCTL_CMDV: usize = 26;
+const FLMNGCTL_FLBUSY: usize = 27;
+const FLMNGCTL_RD_DONE: usize = 30;
+
+// SPI flash commands
+const SPI_WRITE_ENABLE: u8 = 0x06;
+const SPI_READ_STATUS: u8 = 0x05;
+const SPI_PAGE_PROGRAM: u8 = 0x02;
+const SPI_SECTOR_ERASE: u8 = 0x20;
+const SPI_STATUS_WIP: usize = 0;
+
+/// Erase and program operations on a flash, and reads of its contents.
+pub trait FlashDevice {
+    /// The size of the flash in bytes.
+    fn size(&self) -> usize;
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), &'static str>;
+    /// Sets every byte of the sector starting at `offset` to [`FLASH_ERASED`].
+    fn erase_sector(&mut self, offset: usize) -> Result<(), &'static str>;
+    /// Programs bytes within one page. Programming can only clear bits, so the bytes should be erased first.
+    fn program_page(&mut self, offset: usize, data: &[u8]) -> Result<(), &'static str>;
+}
+
+/// Checks that an erase or program request is aligned and within the flash.
+fn check_access(size: usize, offset: usize, len: usize, align: usize) -> Result<(), &'static str> {
+    if offset + len > size {
+        return Err("flash access is out of range");
+    }
+    if len > 0 && offset / align != (offset + len - 1) / align {
+        return Err("flash access crosses a sector or page boundary");
+    }
+    Ok(())
+}
+
+/// The flash attached to the device.
+pub struct IxgbeFlash<'a> {
+    mac_regs: &'a mut IntelIxgbeMacRegisters,
+    size: usize,
+}
+
+impl<'a> IxgbeFlash<'a> {
+    /// `size` is the size of the flash part in bytes, which the device doesn't report.
+    pub fn new(mac_regs: &'a mut IntelIxgbeMacRegisters, size: usize) -> IxgbeFlash<'a> {
+        IxgbeFlash { mac_regs, size }
+    }
+
+    /// Reads a dword through the manageability flash interface.
+    fn read_dword(&mut self, offset: usize) -> Result<u32, &'static str> {
+        self.wait_for_flmngctl(FLMNGCTL_FLBUSY, false)?;
+        let mut flmngctl = 0;
+        flmngctl.set_bits(FLMNGCTL_ADDR, offset as u32);
+        flmngctl.set_bits(FLMNGCTL_CMD, FLMNGCTL_CMD_READ);
+        flmngctl.set_bit(FLMNGCTL_CMDV, true);
+        self.mac_regs.manageability_flash_control_register.write(flmngctl);
+        self.wait_for_flmngctl(FLMNGCTL_RD_DONE, true)?;
+        Ok(self.mac_regs.manageability_flash_read_data.read())
+    }
+
+    /// Reads bytes with one manageability flash read per dword. The flash resource must be held.
+    fn read_bytes(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let address = offset + filled;
+            let dword = self.read_dword(address & !3)?.to_le_bytes();
+            let start = address & 3;
+            let count = (4 - start).min(buf.len() - filled);
+            buf[filled..filled + count].copy_from_slice(&dword[start..start + count]);
+            filled += count;
+        }
+        Ok(())
+    }
+
+    fn wait_for_flmngctl(&self, bit: usize, value: bool) -> Result<(), &'static str> {
+        for _ in 0..FLASH_POLL_ATTEMPTS {
+            if self.mac_regs.manageability_flash_control_register.read().get_bit(bit) == value {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("manageability flash command timed out")
+    }
+
+    /// Runs `f` with the flash resource held, direct access to the flash pins granted and writes enabled.
+    fn with_direct_access(&mut self, f: impl FnOnce(&mut Self) -> Result<(), &'static str>) -> Result<(), &'static str> {
+        acquire_swfw_sync(self.mac_regs, SwFwResource::Flash)?;
+        let result = self.request_direct_access().and_then(|_| {
+            self.mac_regs.eeprom_flash_control_register.update(|eec| { eec.set_bits(EEC_FWE, EEC_FWE_ENABLED); });
+            let result = f(self);
+            self.mac_regs.eeprom_flash_control_register.update(|eec| { eec.set_bits(EEC_FWE, EEC_FWE_DISABLED); });
+            result
+        });
+        self.mac_regs.flash_access_register.update(|fla| { fla.set_bit(FLA_FL_REQ, false); });
+        let released = release_swfw_sync(self.mac_regs, SwFwResource::Flash);
+        result.and(released)
+    }
+
+    fn request_direct_access(&mut self) -> Result<(), &'static str> {
+        self.mac_regs.flash_access_register.update(|fla| {
+            fla.set_bit(FLA_FL_REQ, true);
+            // leave the flash deselected with the clock low
+            fla.set_bit(FLA_FL_CE, true);
+            fla.set_bit(FLA_FL_SCK, false);
+        });
+        for _ in 0..FLASH_POLL_ATTEMPTS {
+            let fla = self.mac_regs.flash_access_register.read();
+            if fla.get_bit(FLA_FL_GNT) && !fla.get_bit(FLA_FL_BUSY) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("timed out waiting for direct flash access to be granted")
+    }
+
+    /// Drives the active-low chip select of the flash.
+    fn select(&mut self, selected: bool) {
+        self.mac_regs.flash_access_register.update(|fla| { fla.set_bit(FLA_FL_CE, !selected); });
+    }
+
+    /// Shifts a byte out to the flash and a byte in from it, most significant bit first.
+    fn transfer(&mut self, out: u8) -> u8 {
+        let mut input = 0u8;
+        for bit in (0..8).rev() {
+            self.mac_regs.flash_access_register.update(|fla| { fla.set_bit(FLA_FL_SI, out.get_bit(bit)); });
+            self.mac_regs.flash_access_register.update(|fla| { fla.set_bit(FLA_FL_SCK, true); });
+            input.set_bit(bit, self.mac_regs.flash_access_register.read().get_bit(FLA_FL_SO));
+            self.mac_regs.flash_access_register.update(|fla| { fla.set_bit(FLA_FL_SCK, false); });
+        }
+        input
+    }
+
+    /// Sends a command with a 24-bit address, followed by `data`.
+    fn command(&mut self, opcode: u8, offset: usize, data: &[u8]) {
+        self.select(true);
+        self.transfer(opcode);
+        for byte in &(offset as u32).to_be_bytes()[1..] {
+            self.transfer(*byte);
+        }
+        for &byte in data {
+            self.transfer(byte);
+        }
+        self.select(false);
+    }
+
+    fn write_enable(&mut self) {
+        self.select(true);
+        self.transfer(SPI_WRITE_ENABLE);
+        self.select(false);
+    }
+
+    /// Waits for the flash to finish an erase or program.
+    fn wait_until_ready(&mut self) -> Result<(), &'static str> {
+        self.select(true);
+        self.transfer(SPI_READ_STATUS);
+        for _ in 0..FLASH_POLL_ATTEMPTS {
+            if !self.transfer(0).get_bit(SPI_STATUS_WIP) {
+                self.select(false);
+                return Ok(());
+            }
+        }
+        self.select(false);
+        Err("flash erase or program timed out")
+    }
+}
+
+impl FlashDevice for IxgbeFlash<'_> {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+        if offset + buf.len() > self.size {
+            return Err("flash access is out of range");
+        }
+        acquire_swfw_sync(self.mac_regs, SwFwResource::Flash)?;
+        let result = self.read_bytes(offset, buf);
+        let released = release_swfw_sync(self.mac_regs, SwFwResource::Flash);
+        result.and(released)
+    }
+
+    fn erase_sector(&mut self, offset: usize) -> Result<(), &'static str> {
+        check_access(self.size, offset, FLASH_SECTOR_SIZE, FLASH_SECTOR_SIZE)?;
+        self.with_direct_access(|flash| {
+            flash.write_enable();
+            flash.command(SPI_SECTOR_ERASE, offset, &[]);
+            flash.wait_until_ready()
+        })
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8]) -> Result<(), &'static str> {
+        check_access(self.size, offset, data.len(), FLASH_PAGE_SIZE)?;
+        self.with_direct_access(|flash| {
+            flash.write_enable();
+            flash.command(SPI_PAGE_PROGRAM, offset, data);
+            flash.wait_until_ready()
+        })
+    }
+}
+
+/// An in-memory flash of `N` bytes. Like NOR flash, erasing sets bytes to 0xFF and programming can only clear bits.
+pub struct SimulatedFlash<const N: usize> {
+    contents: [u8; N],
+    /// Number of sectors erased and pages programmed, so tests can check that an update skipped unchanged data.
+    pub sectors_erased: usize,
+    pub pages_programmed: usize,
+}
+
+impl<const N: usize> Default for SimulatedFlash<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SimulatedFlash<N> {
+    /// Creates an erased flash.
+    pub fn new() -> SimulatedFlash<N> {
+        SimulatedFlash { contents: [FLASH_ERASED; N], sectors_erased: 0, pages_programmed: 0 }
+    }
+
+    /// Creates a flash holding `image`, e.g. a copy of the flash of a device, followed by erased bytes.
+    pub fn from_image(image: &[u8]) -> Result<SimulatedFlash<N>, &'static str> {
+        if image.len() > N {
+            return Err("image is larger than the flash");
+        }
+        let mut flash = Self::new();
+        flash.contents[..image.len()].copy_from_slice(image);
+        Ok(flash)
+    }
+
+    pub fn contents(&self) -> &[u8; N] {
+        &self.contents
+    }
+}
+
+impl<const N: usize> FlashDevice for SimulatedFlash<N> {
+    fn size(&self) -> usize {
+        N
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+        let contents = self.contents.get(offset..offset + buf.len()).ok_or("flash access is out of range")?;
+        buf.copy_from_slice(contents);
+        Ok(())
+    }
+
+    fn erase_sector(&mut self, offset: usize) -> Result<(), &'static str> {
+        check_access(N, offset, FLASH_SECTOR_SIZE, FLASH_SECTOR_SIZE)?;
+        self.contents[offset..offset + FLASH_SECTOR_SIZE].fill(FLASH_ERASED);
+        self.sectors_erased += 1;
+        Ok(())
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8]) -> Result<(), &'static str> {
+        check_access(N, offset, data.len(), FLASH_PAGE_SIZE)?;
+        for (byte, &value) in self.contents[offset..].iter_mut().zip(data) {
+            *byte &= value;
+        }
+        self.pages_programmed += 1;
+        Ok(())
+    }
+}
+
+/// Image verification and update on top of a [`FlashDevice`].
+pub struct Flash<D: FlashDevice> {
+    device: D,
+}
+
+impl<D: FlashDevice> Flash<D> {
+    pub fn new(device: D) -> Flash<D> {
+        Flash { device }
+    }
+
+    pub fn device(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    /// Compares the flash with `image`, which starts at offset 0.
+    /// To verify against an image file, the caller loads the file into memory and passes its contents.
+    /// Returns the offset of the first byte that differs, or `None` if the flash holds the image.
+    pub fn verify_image(&mut self, image: &[u8]) -> Result<Option<usize>, &'static str> {
+        if image.len() > self.device.size() {
+            return Err("image is larger than the flash");
+        }
+        let mut buf = [0; FLASH_PAGE_SIZE];
+        for (page, expected) in image.chunks(FLASH_PAGE_SIZE).enumerate() {
+            let offset = page * FLASH_PAGE_SIZE;
+            let actual = &mut buf[..expected.len()];
+            self.device.read(offset, actual)?;
+            if let Some(i) = actual.iter().zip(expected).position(|(a, e)| a != e) {
+                return Ok(Some(offset + i));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes `image` at offset 0, then verifies it. As with [`Flash::verify_image`], an image file is
+    /// loaded into memory by the caller.
+    /// Sectors that already hold their part of the image are left alone, and erased pages aren't programmed.
+    pub fn update_image(&mut self, image: &[u8]) -> Result<(), &'static str> {
+        if image.len() > self.device.size() {
+            return Err("image is larger than the flash");
+        }
+        let mut buf = [0; FLASH_PAGE_SIZE];
+        for (sector, expected) in image.chunks(FLASH_SECTOR_SIZE).enumerate() {
+            let sector_offset = sector * FLASH_SECTOR_SIZE;
+            let mut unchanged = true;
+            for (page, expected) in expected.chunks(FLASH_PAGE_SIZE).enumerate() {
+                let actual = &mut buf[..expected.len()];
+                self.device.read(sector_offset + page * FLASH_PAGE_SIZE, actual)?;
+                if actual != expected {
+                    unchanged = false;
+                    break;
+                }
+            }
+            if unchanged {
+                continue;
+            }
+
+            // the rest of the last sector is erased too, since it can't be erased separately
+            self.device.erase_sector(sector_offset)?;
+            for (page, data) in expected.chunks(FLASH_PAGE_SIZE).enumerate() {
+                if data.iter().all(|&byte| byte == FLASH_ERASED) {
+                    continue;
+                }
+                self.device.program_page(sector_offset + page * FLASH_PAGE_SIZE, data)?;
+            }
+        }
+
+        match self.verify_image(image)? {
+            None => Ok(()),
+            Some(_) => Err("flash contents don't match the image after the update"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 4 * FLASH_SECTOR_SIZE;
+
+    /// An image that covers two and a half sectors, with an erased page in the second sector.
+    fn image() -> [u8; 5 * FLASH_SECTOR_SIZE / 2] {
+        let mut image = core::array::from_fn(|i| (i * 7 + i / 256) as u8);
+        image[FLASH_SECTOR_SIZE..FLASH_SECTOR_SIZE + FLASH_PAGE_SIZE].fill(FLASH_ERASED);
+        image
+    }
+
+    #[test]
+    fn programming_only_clears_bits() {
+        let mut flash = SimulatedFlash::<SIZE>::new();
+        flash.program_page(0, &[0xF0]).unwrap();
+        flash.program_page(0, &[0x3C]).unwrap();
+        assert_eq!(flash.contents()[0], 0x30);
+        flash.erase_sector(0).unwrap();
+        assert_eq!(flash.contents()[0], FLASH_ERASED);
+    }
+
+    #[test]
+    fn unaligned_accesses_are_rejected() {
+        let mut flash = SimulatedFlash::<SIZE>::new();
+        assert!(flash.erase_sector(FLASH_PAGE_SIZE).is_err());
+        assert!(flash.program_page(FLASH_PAGE_SIZE - 1, &[0, 0]).is_err());
+        assert!(flash.program_page(SIZE, &[0]).is_err());
+    }
+
+    #[test]
+    fn update_writes_and_verifies_the_image() {
+        let image = image();
+        let mut flash = Flash::new(SimulatedFlash::<SIZE>::new());
+        assert_eq!(flash.verify_image(&image), Ok(Some(0)));
+        flash.update_image(&image).unwrap();
+        assert_eq!(flash.verify_image(&image), Ok(None));
+
+        let device = flash.into_device();
+        assert_eq!(device.sectors_erased, 3);
+        // 40 pages, less the erased one
+        assert_eq!(device.pages_programmed, 39);
+        assert!(device.contents()[image.len()..].iter().all(|&byte| byte == FLASH_ERASED));
+    }
+
+    #[test]
+    fn update_skips_unchanged_sectors() {
+        let mut image = image();
+        let mut flash = Flash::new(SimulatedFlash::<SIZE>::from_image(&image).unwrap());
+        image[FLASH_SECTOR_SIZE + 2 * FLASH_PAGE_SIZE] ^= 0xFF;
+        assert_eq!(flash.verify_image(&image), Ok(Some(FLASH_SECTOR_SIZE + 2 * FLASH_PAGE_SIZE)));
+        flash.update_image(&image).unwrap();
+
+        let device = flash.into_device();
+        assert_eq!(device.sectors_erased, 1);
+        assert_eq!(device.pages_programmed, 15);
+        assert_eq!(&device.contents()[..image.len()], &image[..]);
+    }
+
+    #[test]
+    fn image_larger_than_the_flash_is_rejected() {
+        let mut flash = Flash::new(SimulatedFlash::<FLASH_SECTOR_SIZE>::new());
+        assert!(flash.update_image(&image()).is_err());
+    }
+
+    #[test]
+    fn flash_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::MAC_REGISTERS_OFFSET;
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, eeprom_flash_control_register), 0x10010);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flash_access_register), 0x1001C);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, manageability_flash_control_register), 0x10118);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, manageability_flash_read_data), 0x1011C);
+    }
+}
@@ -0,0 +1,304 @@
+//! Link-level (802.3x) and priority (802.1Qbb) flow control.
+//!
+//! On the Rx side, the MAC acts on received pause frames when it's enabled in MFLCN: link-level pause frames
+//! stop all transmission, while priority pause frames only stop the TCs of the paused priorities.
+//! On the Tx side, FCCFG enables sending pause frames, which the Rx packet buffer of each TC does
+//! when it fills above its XOFF (high) watermark in FCRTH, and sending XON once it drains below
+//! its XON (low) watermark in FCRTL.
+//!
+//! The watermarks are derived from the Rx packet buffer sizes already programmed in RXPBSIZE,
+//! so flow control has to be configured after the packet buffers are split with a [`crate::packet_buffer::PacketBufferLayout`].
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::dcb::{MAX_TCS, NUM_USER_PRIORITIES};
+use crate::packet_buffer::rx_buffer_size_kb;
+
+/// The pause time sent in XOFF frames by default, in units of 512 bit times.
+pub const DEFAULT_PAUSE_TIME: u16 = 0xFFFF;
+
+// Delays that the XOFF watermark has to absorb once a pause frame is sent, in bit times,
+// as given in the datasheet for the 82599 with copper cables.
+/// Time for the link partner to react to a pause frame.
+const PAUSE_REACTION_DELAY: u32 = 672;
+/// Copper cable delay.
+const CABLE_DELAY: u32 = 5556;
+/// Interface delay of the MAC, XAUI and PHY.
+const INTERFACE_DELAY: u32 = 4096 + 2 * 1024 + 12800;
+/// Delay of the higher layers.
+const HIGHER_LAYER_DELAY: u32 = 6144;
+/// PCIe bus delay that the XON watermark has to cover.
+const PCI_DELAY: u32 = 10000;
+
+/// Headroom left above the XOFF watermark of TCs without flow control, so that the internal Tx switch
+/// doesn't hang the Tx path when the Rx packet buffer fills.
+const NO_FLOW_CONTROL_HEADROOM: u32 = 24 * 1024;
+
+// Flow Control Receive Threshold Low
+const FCRTL_RTL: Range<usize> = 10..20;
+const FCRTL_XONE: usize = 31;
+
+// Flow Control Receive Threshold High
+const FCRTH_RTH: Range<usize> = 10..20;
+const FCRTH_FCEN: usize = 31;
+
+// Flow Control Transmit Timer Value, with the pause times of 2 TCs per register
+const FCTTV_TTV_WIDTH: usize = 16;
+
+// Flow Control Refresh Threshold Value
+const FCRTV_FC_REFRESH_TH: Range<usize> = 0..16;
+
+// Flow Control Configuration
+const FCCFG_TFCE_802_3X: usize = 3;
+const FCCFG_TFCE_PRIORITY: usize = 4;
+
+// MAC Flow Control Register
+const MFLCN_DPF: usize = 1;
+const MFLCN_RPFCM: usize = 2;
+const MFLCN_RFCE: usize = 3;
+const MFLCN_RPFCE: Range<usize> = 4..12;
+
+// Priority Flow Control Type, with the EtherType and opcode of MAC control frames
+const PFCTOP_FCT: Range<usize> = 0..16;
+const PFCTOP_FCOP: Range<usize> = 16..32;
+const MAC_CONTROL_ETHER_TYPE: u32 = 0x8808;
+const PFC_OPCODE: u32 = 0x0101;
+
+// Transmit Flow Control Status
+const TFCS_TXOFF: usize = 0;
+const TFCS_TC_TXOFF: Range<usize> = 8..16;
+
+/// Which pause frames are honored and sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControlMode {
+    None,
+    /// Received link-level pause frames stop transmission, but none are sent.
+    RxPause,
+    /// Link-level pause frames are sent when the Rx packet buffer fills up, but received ones are ignored.
+    TxPause,
+    /// Link-level pause frames are both honored and sent.
+    Full,
+    /// Priority flow control of the priorities set in `enabled`, in both directions.
+    /// Pause frames are sent for the TCs that any enabled priority maps to in `up_to_tc`.
+    Priority { enabled: u8, up_to_tc: [u8; NUM_USER_PRIORITIES] },
+}
+
+impl FlowControlMode {
+    /// The TCs whose Rx packet buffer sends pause frames.
+    fn tx_pause_tcs(&self) -> u8 {
+        match *self {
+            FlowControlMode::None | FlowControlMode::RxPause => 0,
+            FlowControlMode::TxPause | FlowControlMode::Full => 0xFF,
+            FlowControlMode::Priority { enabled, up_to_tc } => {
+                let mut tcs = 0u8;
+                for (priority, &tc) in up_to_tc.iter().enumerate() {
+                    if enabled.get_bit(priority) {
+                        tcs.set_bit(tc as usize, true);
+                    }
+                }
+                tcs
+            }
+        }
+    }
+}
+
+/// The XOFF and XON watermarks of a TC's Rx packet buffer, in KB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Watermarks {
+    /// An XOFF frame is sent when the packet buffer fills above this.
+    pub high_kb: u32,
+    /// An XON frame is sent when the packet buffer drains below this.
+    pub low_kb: u32,
+}
+
+fn bit_times_to_kb(bit_times: u32) -> u32 {
+    bit_times.div_ceil(8 * 1024)
+}
+
+/// Computes the watermarks of an Rx packet buffer for frames of up to `max_frame_size` bytes.
+///
+/// Above the XOFF watermark, the buffer must still have room for what arrives while the pause frame is
+/// sent and the link partner reacts to it. Below the XON watermark, it must still hold enough to keep
+/// the PCIe bus busy while the XON frame restarts the link partner.
+pub fn watermarks(packet_buffer_kb: u32, max_frame_size: u16) -> Result<Watermarks, &'static str> {
+    let max_frame_bits = max_frame_size as u32 * 8;
+    let delay = 36 * (max_frame_bits + PAUSE_REACTION_DELAY + 2 * CABLE_DELAY + 2 * INTERFACE_DELAY + HIGHER_LAYER_DELAY) / 25
+        + 1
+        + 2 * max_frame_bits;
+    let low_delay = 2 * (2 * max_frame_bits + 36 * PCI_DELAY / 25 + 1);
+
+    let high_kb = packet_buffer_kb.saturating_sub(bit_times_to_kb(delay));
+    if high_kb == 0 {
+        return Err("Rx packet buffer is too small for flow control");
+    }
+    // with small buffers and jumbo frames, XON is only sent once the buffer is empty
+    let low_kb = bit_times_to_kb(low_delay);
+    let low_kb = if low_kb > high_kb { 0 } else { low_kb };
+    Ok(Watermarks { high_kb, low_kb })
+}
+
+/// Pause frames received per priority, accumulated from PXONRXCNT and PXOFFRXCNT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PauseStats {
+    pub xon_received: [u64; NUM_USER_PRIORITIES],
+    pub xoff_received: [u64; NUM_USER_PRIORITIES],
+}
+
+/// Whether transmission is currently stopped by received pause frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxPauseState {
+    /// Transmission is stopped by a link-level pause frame.
+    pub link_paused: bool,
+    /// The bitmap of TCs stopped by priority pause frames.
+    pub paused_tcs: u8,
+}
+
+/// The flow control configuration of the port.
+pub struct FlowControl {
+    mode: FlowControlMode,
+    pause_time: u16,
+    watermarks: [Watermarks; MAX_TCS],
+    stats: PauseStats,
+}
+
+impl FlowControl {
+    /// Enables flow control in the given mode. XOFF frames ask the link partner to pause for `pause_time`,
+    /// in units of 512 bit times, and are refreshed after half of it while the packet buffer stays full.
+    ///
+    /// Watermarks are set on every TC that sends pause frames and has an Rx packet buffer;
+    /// it's an error if one of those buffers is too small for frames of `max_frame_size` bytes.
+    pub fn configure(
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mode: FlowControlMode,
+        pause_time: u16,
+        max_frame_size: u16,
+    ) -> Result<FlowControl, &'static str> {
+        let tx_pause_tcs = mode.tx_pause_tcs();
+        let mut watermarks = [Watermarks::default(); MAX_TCS];
+        for (tc, tc_watermarks) in watermarks.iter_mut().enumerate() {
+            let packet_buffer_kb = rx_buffer_size_kb(rx_regs2, tc);
+            if tx_pause_tcs.get_bit(tc) && packet_buffer_kb > 0 {
+                *tc_watermarks = self::watermarks(packet_buffer_kb, max_frame_size)?;
+            }
+        }
+
+        let mut fccfg = 0;
+        let mut mflcn = rx_regs2.mac_flow_control_register.read();
+        mflcn.set_bits(MFLCN_RPFCE, 0);
+        mflcn.set_bit(MFLCN_RPFCM, false);
+        mflcn.set_bit(MFLCN_RFCE, false);
+        match mode {
+            FlowControlMode::None => {}
+            FlowControlMode::RxPause => { mflcn.set_bit(MFLCN_RFCE, true); }
+            FlowControlMode::TxPause => { fccfg.set_bit(FCCFG_TFCE_802_3X, true); }
+            FlowControlMode::Full => {
+                mflcn.set_bit(MFLCN_RFCE, true);
+                fccfg.set_bit(FCCFG_TFCE_802_3X, true);
+            }
+            FlowControlMode::Priority { enabled, .. } => {
+                mflcn.set_bit(MFLCN_RPFCM, true);
+                mflcn.set_bits(MFLCN_RPFCE, enabled as u32);
+                fccfg.set_bit(FCCFG_TFCE_PRIORITY, true);
+
+                let mut pfctop = 0;
+                pfctop.set_bits(PFCTOP_FCT, MAC_CONTROL_ETHER_TYPE);
+                pfctop.set_bits(PFCTOP_FCOP, PFC_OPCODE);
+                rx_regs2.priority_flow_control_type.write(pfctop);
+            }
+        }
+        // pause frames are consumed by the MAC rather than passed up to the host
+        mflcn.set_bit(MFLCN_DPF, true);
+        rx_regs2.mac_flow_control_register.write(mflcn);
+        rx_regs2.flow_control_configuration.write(fccfg);
+
+        for (tc, tc_watermarks) in watermarks.iter().enumerate() {
+            let (mut fcrtl, mut fcrth) = (0, 0);
+            if tc_watermarks.high_kb > 0 {
+                fcrtl.set_bits(FCRTL_RTL, tc_watermarks.low_kb);
+                fcrtl.set_bit(FCRTL_XONE, true);
+                fcrth.set_bits(FCRTH_RTH, tc_watermarks.high_kb);
+                fcrth.set_bit(FCRTH_FCEN, true);
+            } else {
+                let packet_buffer_size = rx_regs2.receive_packet_buffer_size[tc].read();
+                fcrth = packet_buffer_size.saturating_sub(NO_FLOW_CONTROL_HEADROOM);
+            }
+            rx_regs2.flow_control_receive_threshold_low[tc].write(fcrtl);
+            rx_regs2.flow_control_receive_threshold_high[tc].write(fcrth);
+        }
+
+        let mut fcttv = 0;
+        fcttv.set_bits(0..FCTTV_TTV_WIDTH, pause_time as u32);
+        fcttv.set_bits(FCTTV_TTV_WIDTH..2 * FCTTV_TTV_WIDTH, pause_time as u32);
+        for timer in rx_regs2.flow_control_transmit_timer.iter_mut() {
+            timer.write(fcttv);
+        }
+        let mut fcrtv = 0;
+        fcrtv.set_bits(FCRTV_FC_REFRESH_TH, pause_time as u32 / 2);
+        rx_regs2.flow_control_refresh_threshold_value.write(fcrtv);
+
+        Ok(FlowControl { mode, pause_time, watermarks, stats: PauseStats::default() })
+    }
+
+    pub fn mode(&self) -> FlowControlMode {
+        self.mode
+    }
+
+    pub fn pause_time(&self) -> u16 {
+        self.pause_time
+    }
+
+    /// The watermarks of each TC, which are 0 for TCs that don't send pause frames.
+    pub fn watermarks(&self) -> &[Watermarks; MAX_TCS] {
+        &self.watermarks
+    }
+
+    /// Reads whether transmission is stopped by received pause frames.
+    pub fn tx_pause_state(mac_regs: &IntelIxgbeMacRegisters) -> TxPauseState {
+        let tfcs = mac_regs.transmit_flow_control_status.read();
+        TxPauseState { link_paused: tfcs.get_bit(TFCS_TXOFF), paused_tcs: tfcs.get_bits(TFCS_TC_TXOFF) as u8 }
+    }
+
+    /// Adds the pause frames received since the last update to the totals and returns them.
+    /// The counters are cleared when read.
+    pub fn update_stats(&mut self, rx_regs2: &IntelIxgbeRxRegisters2) -> &PauseStats {
+        for priority in 0..NUM_USER_PRIORITIES {
+            self.stats.xon_received[priority] += rx_regs2.priority_xon_received_count[priority].read() as u64;
+            self.stats.xoff_received[priority] += rx_regs2.priority_xoff_received_count[priority].read() as u64;
+        }
+        &self.stats
+    }
+
+    pub fn stats(&self) -> &PauseStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watermarks_for_standard_frames() {
+        assert_eq!(watermarks(512, 1518), Ok(Watermarks { high_kb: 497, low_kb: 10 }));
+    }
+
+    #[test]
+    fn xon_is_sent_when_empty_if_the_low_watermark_doesnt_fit() {
+        assert_eq!(watermarks(64, 9018), Ok(Watermarks { high_kb: 23, low_kb: 0 }));
+    }
+
+    #[test]
+    fn buffers_smaller_than_the_delay_are_rejected() {
+        assert!(watermarks(41, 9018).is_err());
+        assert!(watermarks(42, 9018).is_ok());
+    }
+
+    #[test]
+    fn priority_mode_pauses_the_tcs_of_enabled_priorities() {
+        let mode = FlowControlMode::Priority { enabled: 0b1000_0010, up_to_tc: [0, 1, 1, 2, 2, 3, 3, 3] };
+        assert_eq!(mode.tx_pause_tcs(), 0b1010);
+        assert_eq!(FlowControlMode::RxPause.tx_pause_tcs(), 0);
+        assert_eq!(FlowControlMode::Full.tx_pause_tcs(), 0xFF);
+    }
+}
@@ -0,0 +1,607 @@
+//! Flow Director filter programming over the FDIR registers.
+//!
+//! Flow Director steers received flows to Rx queues with either signature filters,
+//! which match on a 16-bit hash of the flow, or perfect filters, which match the masked flow fields exactly.
+//! Filters are added and removed one at a time through FDIRHASH and FDIRCMD,
+//! after the flow fields have been staged in the FDIRIPSA/FDIRIPDA/FDIRPORT/FDIRVLAN registers.
+//!
+//! The hardware places each filter in a bucket chosen by a hash of the flow.
+//! [`fdir_hash`] computes that hash in software the same way the hardware does,
+//! since FDIRHASH has to be written by the driver along with the filter.
+
+use core::net::IpAddr;
+use bit_field::BitField;
+use embedded_hal::delay::DelayNs;
+use crate::IntelIxgbeMacRegisters;
+use crate::rss::{FlowTuple, L4Protocol};
+
+/// Key for the bucket hash, written to FDIRHKEY.
+pub const FDIR_BUCKET_HASH_KEY: u32 = 0x3DAD14E2;
+/// Key for the signature hash, written to FDIRSKEY.
+pub const FDIR_SIGNATURE_HASH_KEY: u32 = 0x174D3614;
+
+const FDIR_SIGNATURE_BUCKET_MASK: u32 = 0x7FFF;
+const FDIR_PERFECT_BUCKET_MASK: u32 = 0x1FFF;
+/// The largest software index that fits in FDIRHASH.SW_INDEX.
+pub const FDIR_MAX_SOFT_ID: u16 = 0x7FFF;
+
+// Flow Director Filters Control Register
+const FDIRCTRL_PBALLOC: core::ops::Range<usize> = 0..2;
+const FDIRCTRL_INIT_DONE: usize = 3;
+const FDIRCTRL_PERFECT_MATCH: usize = 4;
+const FDIRCTRL_DROP_Q: core::ops::Range<usize> = 8..15;
+const FDIRCTRL_FLEX_OFFSET: core::ops::Range<usize> = 16..21;
+const FDIRCTRL_MAX_LENGTH: core::ops::Range<usize> = 24..28;
+const FDIRCTRL_FULL_THRESH: core::ops::Range<usize> = 28..32;
+
+// Flow Director Filters Command Register
+const FDIRCMD_CMD: core::ops::Range<usize> = 0..2;
+const FDIRCMD_CMD_ADD_FLOW: u32 = 0x1;
+const FDIRCMD_CMD_REMOVE_FLOW: u32 = 0x2;
+const FDIRCMD_CMD_QUERY_REM_FILT: u32 = 0x3;
+const FDIRCMD_FILTER_VALID: usize = 2;
+const FDIRCMD_FILTER_UPDATE: usize = 3;
+const FDIRCMD_FLOW_TYPE: core::ops::Range<usize> = 5..8;
+const FDIRCMD_DROP: usize = 9;
+const FDIRCMD_LAST: usize = 11;
+const FDIRCMD_QUEUE_EN: usize = 15;
+const FDIRCMD_RX_QUEUE: core::ops::Range<usize> = 16..23;
+const FDIRCMD_VT_POOL: core::ops::Range<usize> = 24..30;
+
+// Flow Director Filters Other Mask: a set bit excludes the field from the comparison
+const FDIRM_VLANID: usize = 0;
+const FDIRM_VLANP: usize = 1;
+const FDIRM_POOL: usize = 2;
+const FDIRM_L4P: usize = 3;
+const FDIRM_FLEX: usize = 4;
+const FDIRM_DIPV6: usize = 5;
+
+const FDIRHASH_SW_INDEX_SHIFT: u32 = 16;
+
+/// Number of times the init done bit is polled, 1 ms apart, before giving up.
+const FDIR_INIT_POLL_ATTEMPTS: u32 = 10;
+const FDIR_INIT_POLL_INTERVAL_MS: u32 = 1;
+/// Number of times the command complete bit is polled before giving up.
+const FDIR_POLL_ATTEMPTS: u32 = 10_000;
+
+/// The amount of Rx packet buffer space reserved for the filter table (FDIRCTRL.PBALLOC).
+/// It also sets the capacity of the table: 2K/4K/8K perfect filters or 8K/16K/32K signature filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FdirPballoc {
+    Size64K = 1,
+    Size128K = 2,
+    Size256K = 3,
+}
+
+impl FdirPballoc {
+    /// The reserved Rx packet buffer space in KB.
+    pub fn size_kb(&self) -> u32 {
+        32 << (*self as u32)
+    }
+}
+
+/// The matching mode of the filter table, which is fixed at initialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdirMode {
+    Signature,
+    /// Perfect match filters, where packets of filters with the drop action go to `drop_queue`.
+    Perfect { drop_queue: u8 },
+}
+
+/// What the hardware does with a packet that matches a perfect filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdirAction {
+    Queue(u8),
+    Drop,
+}
+
+/// The fields a Flow Director filter matches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdirInput {
+    pub flow: FlowTuple,
+    pub vlan_tci: u16,
+    /// The flex bytes in host order, which is the order they're both hashed and written to FDIRVLAN in.
+    pub flex_bytes: u16,
+    pub vm_pool: u8,
+}
+
+/// The fields that perfect filters compare. The mask is global to the filter table.
+///
+/// For addresses and ports, set bits are compared.
+/// The IPv6 masks have one bit per address byte, bit `i` standing for octet `i`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdirMask {
+    pub src_ipv4: u32,
+    pub dst_ipv4: u32,
+    pub src_ipv6: u16,
+    pub dst_ipv6: u16,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub vlan_id: bool,
+    pub vlan_priority: bool,
+    pub vm_pool: bool,
+    pub l4_type: bool,
+    pub flex_bytes: bool,
+}
+
+impl FdirMask {
+    /// A mask that compares every field.
+    pub const fn exact() -> FdirMask {
+        FdirMask {
+            src_ipv4: 0xFFFF_FFFF,
+            dst_ipv4: 0xFFFF_FFFF,
+            src_ipv6: 0xFFFF,
+            dst_ipv6: 0xFFFF,
+            src_port: 0xFFFF,
+            dst_port: 0xFFFF,
+            vlan_id: true,
+            vlan_priority: true,
+            vm_pool: true,
+            l4_type: true,
+            flex_bytes: true,
+        }
+    }
+}
+
+/// Flow Director statistics. Most of them are cleared when read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FdirStats {
+    /// Filters added successfully (FDIRUSTAT.ADD).
+    pub adds: u16,
+    /// Filters removed successfully (FDIRUSTAT.REMOVE).
+    pub removes: u16,
+    /// Filters that could not be added (FDIRFSTAT.FADD).
+    pub failed_adds: u8,
+    /// Filters that could not be removed because they weren't found (FDIRFSTAT.FREMOVE).
+    pub failed_removes: u8,
+    /// Packets that matched a filter (FDIRMATCH).
+    pub matches: u32,
+    /// Packets that didn't match any filter (FDIRMISS).
+    pub misses: u32,
+    /// Number of free entries in the filter table (FDIRFREE.FREE).
+    pub free: u16,
+    /// Number of filters that share a bucket with another filter (FDIRFREE.COLL).
+    pub collisions: u16,
+}
+
+/// Computes a Flow Director hash of `input` with the given key, the same way the 82599 does.
+///
+/// The flow is laid out as an 11-dword stream: pool, flow type and VLAN; destination address;
+/// source address; ports; and flex bytes. The hash is the XOR of the stream words selected by the key bits.
+/// With [`FDIR_BUCKET_HASH_KEY`] the result is the bucket hash, with [`FDIR_SIGNATURE_HASH_KEY`] the signature.
+pub fn fdir_hash(input: &FdirInput, key: u32) -> u16 {
+    let stream = input_stream(input);
+    let flow_vm_vlan = stream[0];
+    let mut hi = stream[1..].iter().fold(0, |acc, dword| acc ^ dword);
+    // the low dword is a word swapped copy of the common dword
+    let mut lo = hi.rotate_left(16);
+    hi ^= flow_vm_vlan ^ (flow_vm_vlan >> 16);
+
+    let mut hash = 0;
+    // bit 0 of the key is applied before the flow type, pool and VLAN bits are folded into the low dword
+    if key.get_bit(0) {
+        hash ^= lo;
+    }
+    if key.get_bit(16) {
+        hash ^= hi;
+    }
+    lo ^= flow_vm_vlan ^ (flow_vm_vlan << 16);
+    for n in 1..16 {
+        if key.get_bit(n) {
+            hash ^= lo >> n;
+        }
+        if key.get_bit(n + 16) {
+            hash ^= hi >> n;
+        }
+    }
+    hash as u16
+}
+
+/// A Flow Director filter table, initialized in either signature or perfect match mode.
+///
+/// The table itself lives in the device. This struct only keeps what is needed to stage filters:
+/// the mode, the perfect match mask and the number of filters that have been added.
+pub struct FlowDirector {
+    mode: FdirMode,
+    mask: FdirMask,
+    num_filters: u32,
+}
+
+impl FlowDirector {
+    /// Programs the hash keys and the control register, and waits for the hardware
+    /// to finish initializing the filter table.
+    ///
+    /// In perfect mode the `mask` is programmed before the table is initialized.
+    /// Signature filters always match on every field, so the mask is ignored.
+    /// `delay` paces the polling, since initialization takes a few milliseconds.
+    pub fn init<D: DelayNs>(
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        mode: FdirMode,
+        pballoc: FdirPballoc,
+        mask: FdirMask,
+        delay: &mut D,
+    ) -> Result<FlowDirector, &'static str> {
+        let mut fdirctrl = 0;
+        fdirctrl.set_bits(FDIRCTRL_PBALLOC, pballoc as u32);
+        // the flex bytes are the 6th word of the packet, which is the Ethertype
+        fdirctrl.set_bits(FDIRCTRL_FLEX_OFFSET, 0x6);
+        fdirctrl.set_bits(FDIRCTRL_MAX_LENGTH, 0xA);
+        fdirctrl.set_bits(FDIRCTRL_FULL_THRESH, 0x4);
+
+        let mut fdir = FlowDirector { mode, mask: FdirMask::exact(), num_filters: 0 };
+        if let FdirMode::Perfect { drop_queue } = mode {
+            if drop_queue >= 128 {
+                return Err("Flow Director drop queue is out of range");
+            }
+            fdirctrl.set_bit(FDIRCTRL_PERFECT_MATCH, true);
+            fdirctrl.set_bits(FDIRCTRL_DROP_Q, drop_queue as u32);
+            fdir.set_mask(mac_regs, mask)?;
+        }
+
+        mac_regs.flow_director_filters_lookup_table_hash_key.write(FDIR_BUCKET_HASH_KEY);
+        mac_regs.flow_director_filters_lookup_table_stream_key.write(FDIR_SIGNATURE_HASH_KEY);
+        mac_regs.flow_director_filters_control_register.write(fdirctrl);
+
+        for _ in 0..FDIR_INIT_POLL_ATTEMPTS {
+            if mac_regs.flow_director_filters_control_register.read().get_bit(FDIRCTRL_INIT_DONE) {
+                return Ok(fdir);
+            }
+            delay.delay_ms(FDIR_INIT_POLL_INTERVAL_MS);
+        }
+        Err("Flow Director table initialization timed out")
+    }
+
+    /// Programs the fields that perfect filters compare.
+    /// The mask can only be changed while the table holds no filters.
+    pub fn set_mask(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, mask: FdirMask) -> Result<(), &'static str> {
+        if !matches!(self.mode, FdirMode::Perfect { .. }) {
+            return Err("Flow Director masks only apply to perfect filters");
+        }
+        if self.num_filters != 0 {
+            return Err("Flow Director mask can't change while filters are installed");
+        }
+
+        let mut fdirm = 0;
+        fdirm.set_bit(FDIRM_VLANID, !mask.vlan_id);
+        fdirm.set_bit(FDIRM_VLANP, !mask.vlan_priority);
+        fdirm.set_bit(FDIRM_POOL, !mask.vm_pool);
+        fdirm.set_bit(FDIRM_L4P, !mask.l4_type);
+        fdirm.set_bit(FDIRM_FLEX, !mask.flex_bytes);
+        // only the low dword of the destination IPv6 address is stored with a filter, so the rest can't be compared
+        fdirm.set_bit(FDIRM_DIPV6, true);
+        mac_regs.flow_director_filters_other_mask.write(fdirm);
+
+        // the port masks are stored bit reversed, and the same mask is used for TCP and UDP
+        let port_mask = ((mask.dst_port.reverse_bits() as u32) << 16) | mask.src_port.reverse_bits() as u32;
+        mac_regs.flow_director_filters_tcp_mask.write(!port_mask);
+        mac_regs.flow_director_filters_udp_mask.write(!port_mask);
+
+        mac_regs.flow_director_filters_source_ipv4_mask.write(!mask.src_ipv4);
+        mac_regs.flow_director_filters_ipv4_mask.write(!mask.dst_ipv4);
+        mac_regs.flow_director_filters_ipv6_mask.write(!(((mask.dst_ipv6 as u32) << 16) | mask.src_ipv6 as u32));
+
+        self.mask = mask;
+        Ok(())
+    }
+
+    pub fn mode(&self) -> FdirMode {
+        self.mode
+    }
+
+    pub fn num_filters(&self) -> u32 {
+        self.num_filters
+    }
+
+    /// Adds a perfect filter that is identified by `soft_id` when it's matched or removed.
+    /// The mask is applied to `input` before it's written, so only the compared fields matter.
+    pub fn add_perfect_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, input: &FdirInput, soft_id: u16, action: FdirAction) -> Result<(), &'static str> {
+        if !matches!(self.mode, FdirMode::Perfect { .. }) {
+            return Err("Flow Director table is not in perfect match mode");
+        }
+        if soft_id > FDIR_MAX_SOFT_ID {
+            return Err("Flow Director software index is out of range");
+        }
+        check_flow(input)?;
+
+        let input = apply_mask(input, &self.mask);
+        let bucket = fdir_hash(&input, FDIR_BUCKET_HASH_KEY) as u32 & FDIR_PERFECT_BUCKET_MASK;
+        write_flow_fields(mac_regs, &input);
+        mac_regs.flow_director_filters_hash_signature.write(bucket | ((soft_id as u32) << FDIRHASH_SW_INDEX_SHIFT));
+
+        let mut fdircmd = add_command(&input);
+        match action {
+            FdirAction::Queue(queue) => {
+                if queue >= 128 {
+                    return Err("Flow Director queue is out of range");
+                }
+                fdircmd.set_bits(FDIRCMD_RX_QUEUE, queue as u32);
+            }
+            FdirAction::Drop => {
+                fdircmd.set_bit(FDIRCMD_DROP, true);
+            }
+        }
+        mac_regs.flow_director_filters_command_register.write(fdircmd);
+        wait_for_command(mac_regs)?;
+
+        self.num_filters += 1;
+        Ok(())
+    }
+
+    /// Removes the perfect filter for `input` that was added with `soft_id`.
+    pub fn remove_perfect_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, input: &FdirInput, soft_id: u16) -> Result<(), &'static str> {
+        if !matches!(self.mode, FdirMode::Perfect { .. }) {
+            return Err("Flow Director table is not in perfect match mode");
+        }
+        if soft_id > FDIR_MAX_SOFT_ID {
+            return Err("Flow Director software index is out of range");
+        }
+        let input = apply_mask(input, &self.mask);
+        let bucket = fdir_hash(&input, FDIR_BUCKET_HASH_KEY) as u32 & FDIR_PERFECT_BUCKET_MASK;
+        self.remove_filter(mac_regs, bucket | ((soft_id as u32) << FDIRHASH_SW_INDEX_SHIFT))
+    }
+
+    /// Adds a signature filter that steers `input` to `queue`.
+    pub fn add_signature_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, input: &FdirInput, queue: u8) -> Result<(), &'static str> {
+        if self.mode != FdirMode::Signature {
+            return Err("Flow Director table is not in signature mode");
+        }
+        if queue >= 128 {
+            return Err("Flow Director queue is out of range");
+        }
+        check_flow(input)?;
+
+        mac_regs.flow_director_filters_hash_signature.write(signature_hash(input));
+        let mut fdircmd = add_command(input);
+        fdircmd.set_bits(FDIRCMD_RX_QUEUE, queue as u32);
+        mac_regs.flow_director_filters_command_register.write(fdircmd);
+        wait_for_command(mac_regs)?;
+
+        self.num_filters += 1;
+        Ok(())
+    }
+
+    /// Removes the signature filter for `input`.
+    pub fn remove_signature_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, input: &FdirInput) -> Result<(), &'static str> {
+        if self.mode != FdirMode::Signature {
+            return Err("Flow Director table is not in signature mode");
+        }
+        self.remove_filter(mac_regs, signature_hash(input))
+    }
+
+    /// Reads the Flow Director statistics registers.
+    pub fn stats(mac_regs: &IntelIxgbeMacRegisters) -> FdirStats {
+        let ustat = mac_regs.flow_director_filters_usage_statistics.read();
+        let fstat = mac_regs.flow_director_filters_failed_usage_statistics.read();
+        let free = mac_regs.flow_director_filters_free.read();
+        FdirStats {
+            adds: ustat.get_bits(0..16) as u16,
+            removes: ustat.get_bits(16..32) as u16,
+            failed_adds: fstat.get_bits(0..8) as u8,
+            failed_removes: fstat.get_bits(8..16) as u8,
+            matches: mac_regs.flow_director_filters_match_statistics.read(),
+            misses: mac_regs.flow_director_filters_miss_match_statistics.read(),
+            free: free.get_bits(0..16) as u16,
+            collisions: free.get_bits(16..31) as u16,
+        }
+    }
+
+    /// Queries the filter with the given FDIRHASH value and removes it if it exists.
+    fn remove_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, fdirhash: u32) -> Result<(), &'static str> {
+        mac_regs.flow_director_filters_hash_signature.write(fdirhash);
+        mac_regs.flow_director_filters_command_register.write(FDIRCMD_CMD_QUERY_REM_FILT);
+        let fdircmd = wait_for_command(mac_regs)?;
+        if !fdircmd.get_bit(FDIRCMD_FILTER_VALID) {
+            return Err("Flow Director filter doesn't exist");
+        }
+
+        mac_regs.flow_director_filters_hash_signature.write(fdirhash);
+        mac_regs.flow_director_filters_command_register.write(FDIRCMD_CMD_REMOVE_FLOW);
+        wait_for_command(mac_regs)?;
+
+        self.num_filters = self.num_filters.saturating_sub(1);
+        Ok(())
+    }
+}
+
+/// The flow type as used in the hash stream and FDIRCMD: the L4 type in bits 1:0 and IPv6 in bit 2.
+fn flow_type(flow: &FlowTuple) -> u32 {
+    let l4_type = match flow.protocol {
+        L4Protocol::Other => 0,
+        L4Protocol::Udp => 1,
+        L4Protocol::Tcp => 2,
+        L4Protocol::Sctp => 3,
+    };
+    match flow.src_ip {
+        IpAddr::V4(_) => l4_type,
+        IpAddr::V6(_) => l4_type | 0x4,
+    }
+}
+
+fn check_flow(input: &FdirInput) -> Result<(), &'static str> {
+    if input.flow.src_ip.is_ipv4() != input.flow.dst_ip.is_ipv4() {
+        return Err("Flow Director filter mixes IPv4 and IPv6 addresses");
+    }
+    if input.vm_pool >= 64 {
+        return Err("Flow Director pool is out of range");
+    }
+    Ok(())
+}
+
+fn input_stream(input: &FdirInput) -> [u32; 11] {
+    let mut stream = [0; 11];
+    stream[0] = ((input.vm_pool as u32) << 24) | (flow_type(&input.flow) << 16) | input.vlan_tci as u32;
+    write_address(&mut stream[1..5], input.flow.dst_ip);
+    write_address(&mut stream[5..9], input.flow.src_ip);
+    stream[9] = ((input.flow.src_port as u32) << 16) | input.flow.dst_port as u32;
+    stream[10] = (input.flex_bytes as u32) << 16;
+    stream
+}
+
+fn write_address(dwords: &mut [u32], addr: IpAddr) {
+    match addr {
+        IpAddr::V4(addr) => dwords[0] = u32::from(addr),
+        IpAddr::V6(addr) => {
+            for (dword, octets) in dwords.iter_mut().zip(addr.octets().chunks_exact(4)) {
+                *dword = u32::from_be_bytes([octets[0], octets[1], octets[2], octets[3]]);
+            }
+        }
+    }
+}
+
+fn apply_mask(input: &FdirInput, mask: &FdirMask) -> FdirInput {
+    let mut masked = *input;
+    masked.flow.src_ip = mask_address(input.flow.src_ip, mask.src_ipv4, mask.src_ipv6);
+    masked.flow.dst_ip = mask_address(input.flow.dst_ip, mask.dst_ipv4, mask.dst_ipv6);
+    masked.flow.src_port &= mask.src_port;
+    masked.flow.dst_port &= mask.dst_port;
+    let vlan_mask = if mask.vlan_id { 0x0FFF } else { 0 } | if mask.vlan_priority { 0xE000 } else { 0 };
+    masked.vlan_tci &= vlan_mask;
+    if !mask.vm_pool {
+        masked.vm_pool = 0;
+    }
+    if !mask.l4_type {
+        masked.flow.protocol = L4Protocol::Other;
+    }
+    if !mask.flex_bytes {
+        masked.flex_bytes = 0;
+    }
+    masked
+}
+
+fn mask_address(addr: IpAddr, ipv4_mask: u32, ipv6_mask: u16) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => IpAddr::V4((u32::from(addr) & ipv4_mask).into()),
+        IpAddr::V6(addr) => {
+            let mut octets = addr.octets();
+            for (i, octet) in octets.iter_mut().enumerate() {
+                if !ipv6_mask.get_bit(i) {
+                    *octet = 0;
+                }
+            }
+            IpAddr::V6(octets.into())
+        }
+    }
+}
+
+/// Returns the FDIRHASH value of a signature filter: the signature in the high word and the bucket hash in the low word.
+fn signature_hash(input: &FdirInput) -> u32 {
+    let bucket = fdir_hash(input, FDIR_BUCKET_HASH_KEY) as u32 & FDIR_SIGNATURE_BUCKET_MASK;
+    let signature = fdir_hash(input, FDIR_SIGNATURE_HASH_KEY) as u32;
+    (signature << 16) | bucket
+}
+
+/// Stages the flow fields of a perfect filter in the FDIR filter registers.
+fn write_flow_fields(mac_regs: &mut IntelIxgbeMacRegisters, input: &FdirInput) {
+    match (input.flow.src_ip, input.flow.dst_ip) {
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            let src = src.octets();
+            for (i, octets) in src[..12].chunks_exact(4).enumerate() {
+                mac_regs.flow_director_filters_source_ipv6[i].write(u32::from_be_bytes([octets[0], octets[1], octets[2], octets[3]]));
+            }
+            mac_regs.flow_director_filters_ip_sa.write(u32::from_be_bytes([src[12], src[13], src[14], src[15]]));
+            let dst = dst.octets();
+            mac_regs.flow_director_filters_ip_da.write(u32::from_be_bytes([dst[12], dst[13], dst[14], dst[15]]));
+        }
+        (src, dst) => {
+            mac_regs.flow_director_filters_ip_sa.write(address_low_dword(src));
+            mac_regs.flow_director_filters_ip_da.write(address_low_dword(dst));
+        }
+    }
+    mac_regs.flow_director_filters_port.write(((input.flow.dst_port as u32) << 16) | input.flow.src_port as u32);
+    mac_regs.flow_director_filters_vlan_and_flex_bytes.write(((input.flex_bytes as u32) << 16) | input.vlan_tci as u32);
+}
+
+fn address_low_dword(addr: IpAddr) -> u32 {
+    match addr {
+        IpAddr::V4(addr) => u32::from(addr),
+        IpAddr::V6(addr) => addr.to_bits() as u32,
+    }
+}
+
+fn add_command(input: &FdirInput) -> u32 {
+    let mut fdircmd = 0;
+    fdircmd.set_bits(FDIRCMD_CMD, FDIRCMD_CMD_ADD_FLOW);
+    fdircmd.set_bit(FDIRCMD_FILTER_UPDATE, true);
+    fdircmd.set_bit(FDIRCMD_LAST, true);
+    fdircmd.set_bit(FDIRCMD_QUEUE_EN, true);
+    fdircmd.set_bits(FDIRCMD_FLOW_TYPE, flow_type(&input.flow));
+    fdircmd.set_bits(FDIRCMD_VT_POOL, input.vm_pool as u32);
+    fdircmd
+}
+
+/// Waits for the hardware to clear FDIRCMD.CMD and returns the final value of FDIRCMD.
+fn wait_for_command(mac_regs: &IntelIxgbeMacRegisters) -> Result<u32, &'static str> {
+    for _ in 0..FDIR_POLL_ATTEMPTS {
+        let fdircmd = mac_regs.flow_director_filters_command_register.read();
+        if fdircmd.get_bits(FDIRCMD_CMD) == 0 {
+            return Ok(fdircmd);
+        }
+        core::hint::spin_loop();
+    }
+    Err("Flow Director command timed out")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::Ipv4Addr;
+
+    fn input(flex_bytes: u16) -> FdirInput {
+        FdirInput {
+            flow: FlowTuple {
+                src_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                dst_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                src_port: 0x1234,
+                dst_port: 0x5678,
+                protocol: L4Protocol::Tcp,
+            },
+            vlan_tci: 0x0064,
+            flex_bytes,
+            vm_pool: 3,
+        }
+    }
+
+    #[test]
+    fn flex_bytes_are_hashed_in_host_order() {
+        assert_eq!(input_stream(&input(0x0800))[10], 0x0800_0000);
+    }
+
+    #[test]
+    fn single_key_bits_select_the_stream_words() {
+        let input = input(0);
+        // bit 0 takes the word swapped common dword, whose low word is the source port
+        assert_eq!(fdir_hash(&input, 1), 0x1234);
+        // bit 16 takes the common dword with the pool, flow type and VLAN folded in
+        assert_eq!(fdir_hash(&input, 1 << 16), 0x5678 ^ 0x0064 ^ 0x0302);
+    }
+
+    #[test]
+    fn masked_fields_are_cleared_before_hashing() {
+        let mask = FdirMask { src_port: 0xFF00, vlan_id: false, vm_pool: false, flex_bytes: false, ..FdirMask::exact() };
+        let masked = apply_mask(&input(0x0800), &mask);
+        assert_eq!(masked.flow.src_port, 0x1200);
+        assert_eq!(masked.flow.dst_port, 0x5678);
+        assert_eq!(masked.vlan_tci, 0);
+        assert_eq!(masked.vm_pool, 0);
+        assert_eq!(masked.flex_bytes, 0);
+    }
+
+    #[test]
+    fn pballoc_sizes() {
+        assert_eq!(FdirPballoc::Size64K.size_kb(), 64);
+        assert_eq!(FdirPballoc::Size256K.size_kb(), 256);
+    }
+
+    #[test]
+    fn flow_director_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::MAC_REGISTERS_OFFSET;
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_control_register), 0xEE00);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_source_ipv6), 0xEE0C);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_ip_da), 0xEE1C);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_hash_signature), 0xEE28);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_command_register), 0xEE2C);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, flow_director_filters_tcp_mask), 0xEE44);
+    }
+}
@@ -0,0 +1,91 @@
+//! The types that give each register of the memory-mapped region its access rights.
+//!
+//! Every access is a volatile read or write of the whole register, so the compiler can't elide, merge or reorder them.
+//! The types are transparent, so a struct of registers has the same layout as the registers in memory.
+
+use core::ptr;
+use zerocopy::{FromBytes, FromZeroes};
+
+/// A register that can be read and written.
+#[derive(FromZeroes, FromBytes)]
+#[repr(transparent)]
+pub struct Volatile<T: Copy>(T);
+
+impl<T: Copy> Volatile<T> {
+    pub const fn new(value: T) -> Volatile<T> {
+        Volatile(value)
+    }
+
+    pub fn read(&self) -> T {
+        // SAFETY: the reference is valid and aligned for `T`
+        unsafe { ptr::read_volatile(&self.0) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        // SAFETY: the reference is valid and aligned for `T`
+        unsafe { ptr::write_volatile(&mut self.0, value) }
+    }
+
+    /// Reads the register, lets `f` modify the value and writes it back.
+    pub fn update<F: FnOnce(&mut T)>(&mut self, f: F) {
+        let mut value = self.read();
+        f(&mut value);
+        self.write(value);
+    }
+}
+
+/// A register that can only be read.
+#[derive(FromZeroes, FromBytes)]
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy>(Volatile<T>);
+
+impl<T: Copy> ReadOnly<T> {
+    pub const fn new(value: T) -> ReadOnly<T> {
+        ReadOnly(Volatile::new(value))
+    }
+
+    pub fn read(&self) -> T {
+        self.0.read()
+    }
+}
+
+/// A register that can only be written.
+#[derive(FromZeroes, FromBytes)]
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy>(Volatile<T>);
+
+impl<T: Copy> WriteOnly<T> {
+    pub const fn new(value: T) -> WriteOnly<T> {
+        WriteOnly(Volatile::new(value))
+    }
+
+    pub fn write(&mut self, value: T) {
+        self.0.write(value)
+    }
+}
+
+/// A register that is cleared when it's read, such as a statistics counter.
+#[derive(FromZeroes, FromBytes)]
+#[repr(transparent)]
+pub struct RC<T: Copy>(Volatile<T>);
+
+impl<T: Copy> RC<T> {
+    pub const fn new(value: T) -> RC<T> {
+        RC(Volatile::new(value))
+    }
+
+    pub fn read(&self) -> T {
+        self.0.read()
+    }
+}
+
+/// A register that the driver doesn't access.
+#[derive(FromZeroes, FromBytes)]
+#[repr(transparent)]
+pub struct Reserved<T: Copy>(T);
+
+impl<T: Copy> Reserved<T> {
+    pub const fn new(value: T) -> Reserved<T> {
+        Reserved(value)
+    }
+}
@@ -0,0 +1,193 @@
+//! A bit-banged I2C master on the I2CCTL register, which drives the I2C pins of the SFP+ cage.
+//!
+//! Both lines are open drain: writing a 1 to the clock or data out bit releases the line,
+//! and the in bits return the level of the line, which may be held low by the module.
+//! The lines are shared with the firmware, so each transaction is done while holding the PHY resource
+//! of the LAN function in SW_FW_SYNC.
+//!
+//! [`IxgbeI2c`] implements the `embedded-hal` I2C trait, so device drivers written against that trait
+//! (such as the SFP+ module reader in [`crate::sfp`]) can run on it.
+
+use bit_field::BitField;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress};
+use crate::{IntelIxgbeRegisters1, IntelIxgbeMacRegisters};
+use crate::eeprom::{acquire_swfw_sync, release_swfw_sync, SwFwResource};
+
+// I2C Control
+const I2CCTL_CLK_IN: usize = 0;
+const I2CCTL_CLK_OUT: usize = 1;
+const I2CCTL_DATA_IN: usize = 2;
+const I2CCTL_DATA_OUT: usize = 3;
+
+/// Half of the clock period for the 100 kHz standard mode, which SFP+ modules are required to support.
+const I2C_HALF_PERIOD_NS: u32 = 5_000;
+/// Number of half periods a device may stretch the clock for before the bus is considered stuck.
+const I2C_CLOCK_STRETCH_LIMIT: u32 = 2_000;
+
+/// Errors of an I2C transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cError {
+    /// The device didn't acknowledge its address or a data byte.
+    NoAcknowledge(NoAcknowledgeSource),
+    /// The clock line was held low for too long.
+    ClockStretchTimeout,
+    /// The I2C lines couldn't be acquired from the firmware.
+    Semaphore(&'static str),
+}
+
+impl i2c::Error for I2cError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            I2cError::NoAcknowledge(source) => ErrorKind::NoAcknowledge(*source),
+            I2cError::ClockStretchTimeout => ErrorKind::Bus,
+            I2cError::Semaphore(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// The I2C master of one LAN function.
+pub struct IxgbeI2c<'a, D: DelayNs> {
+    regs1: &'a mut IntelIxgbeRegisters1,
+    mac_regs: &'a mut IntelIxgbeMacRegisters,
+    resource: SwFwResource,
+    delay: D,
+}
+
+impl<'a, D: DelayNs> IxgbeI2c<'a, D> {
+    /// `lan` is the LAN function (0 or 1) that the registers belong to, which selects the PHY resource to hold.
+    /// `delay` is used to time the bus at 100 kHz.
+    pub fn new(regs1: &'a mut IntelIxgbeRegisters1, mac_regs: &'a mut IntelIxgbeMacRegisters, lan: u8, delay: D) -> IxgbeI2c<'a, D> {
+        let resource = if lan == 0 { SwFwResource::Phy0 } else { SwFwResource::Phy1 };
+        IxgbeI2c { regs1, mac_regs, resource, delay }
+    }
+
+    fn half_period(&mut self) {
+        self.delay.delay_ns(I2C_HALF_PERIOD_NS);
+    }
+
+    fn set_data(&mut self, high: bool) {
+        self.regs1.i2c_control.update(|ctrl| { ctrl.set_bit(I2CCTL_DATA_OUT, high); });
+    }
+
+    fn data(&self) -> bool {
+        self.regs1.i2c_control.read().get_bit(I2CCTL_DATA_IN)
+    }
+
+    fn clock_low(&mut self) {
+        self.regs1.i2c_control.update(|ctrl| { ctrl.set_bit(I2CCTL_CLK_OUT, false); });
+    }
+
+    /// Releases the clock line and waits for it to go high, since the device may stretch the clock.
+    fn clock_high(&mut self) -> Result<(), I2cError> {
+        self.regs1.i2c_control.update(|ctrl| { ctrl.set_bit(I2CCTL_CLK_OUT, true); });
+        for _ in 0..I2C_CLOCK_STRETCH_LIMIT {
+            if self.regs1.i2c_control.read().get_bit(I2CCTL_CLK_IN) {
+                return Ok(());
+            }
+            self.half_period();
+        }
+        Err(I2cError::ClockStretchTimeout)
+    }
+
+    /// Generates a start condition, or a repeated start if the bus is already owned.
+    fn start(&mut self) -> Result<(), I2cError> {
+        self.set_data(true);
+        self.clock_high()?;
+        self.half_period();
+        self.set_data(false);
+        self.half_period();
+        self.clock_low();
+        self.half_period();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), I2cError> {
+        self.set_data(false);
+        self.half_period();
+        self.clock_high()?;
+        self.half_period();
+        self.set_data(true);
+        self.half_period();
+        Ok(())
+    }
+
+    /// Clocks one bit out, or in if `bit` is true and the device drives the data line.
+    fn clock_bit(&mut self, bit: bool) -> Result<bool, I2cError> {
+        self.set_data(bit);
+        self.half_period();
+        self.clock_high()?;
+        self.half_period();
+        let value = self.data();
+        self.clock_low();
+        Ok(value)
+    }
+
+    /// Writes a byte, most significant bit first, and returns whether the device acknowledged it.
+    fn write_byte(&mut self, byte: u8) -> Result<bool, I2cError> {
+        for bit in (0..8).rev() {
+            self.clock_bit(byte.get_bit(bit))?;
+        }
+        // the device acknowledges by pulling the released data line low
+        Ok(!self.clock_bit(true)?)
+    }
+
+    /// Reads a byte, then acknowledges it if more bytes are to be read.
+    fn read_byte(&mut self, ack: bool) -> Result<u8, I2cError> {
+        let mut byte = 0u8;
+        for bit in (0..8).rev() {
+            byte.set_bit(bit, self.clock_bit(true)?);
+        }
+        self.clock_bit(!ack)?;
+        Ok(byte)
+    }
+
+    fn run_operations(&mut self, address: SevenBitAddress, operations: &mut [Operation<'_>]) -> Result<(), I2cError> {
+        let num_operations = operations.len();
+        let mut previous_read = None;
+        for i in 0..num_operations {
+            let is_read = matches!(operations[i], Operation::Read(_));
+            // consecutive operations of the same kind are merged, and a change of direction needs a repeated start
+            if previous_read != Some(is_read) {
+                self.start()?;
+                if !self.write_byte((address << 1) | is_read as u8)? {
+                    return Err(I2cError::NoAcknowledge(NoAcknowledgeSource::Address));
+                }
+            }
+            let next_is_read = operations.get(i + 1).is_some_and(|op| matches!(op, Operation::Read(_)));
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        if !self.write_byte(byte)? {
+                            return Err(I2cError::NoAcknowledge(NoAcknowledgeSource::Data));
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    let len = buf.len();
+                    for (j, byte) in buf.iter_mut().enumerate() {
+                        // the last byte before a stop or a change of direction isn't acknowledged
+                        *byte = self.read_byte(j + 1 < len || next_is_read)?;
+                    }
+                }
+            }
+            previous_read = Some(is_read);
+        }
+        Ok(())
+    }
+}
+
+impl<D: DelayNs> ErrorType for IxgbeI2c<'_, D> {
+    type Error = I2cError;
+}
+
+impl<D: DelayNs> I2c<SevenBitAddress> for IxgbeI2c<'_, D> {
+    fn transaction(&mut self, address: SevenBitAddress, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        acquire_swfw_sync(self.mac_regs, self.resource).map_err(I2cError::Semaphore)?;
+        let result = self.run_operations(address, operations);
+        // the bus is always left idle, even after a failed transaction
+        let stop = self.stop();
+        let released = release_swfw_sync(self.mac_regs, self.resource).map_err(I2cError::Semaphore);
+        result.and(stop).and(released)
+    }
+}
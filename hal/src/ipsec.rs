@@ -0,0 +1,505 @@
+//! IPsec inline crypto offload.
+//!
+//! The security block holds 1024 Tx SAs, 1024 Rx SAs and a table of 128 Rx destination IP addresses
+//! that the Rx SAs refer to. None of the tables can be read or written directly: an entry is staged
+//! in the key/salt/SPI/IP registers and then committed with a write command in IPSTXIDX or IPSRXIDX.
+//! The only supported cipher is AES-128-GCM with a 16 byte ICV (RFC 4106).
+//!
+//! A packet is encrypted on transmit when its context descriptor carries the index of a Tx SA,
+//! and decrypted on receive when its SPI, destination IP and protocol match an Rx SA.
+
+use bit_field::BitField;
+use core::net::IpAddr;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+
+/// Number of SAs in the Tx table, and also in the Rx table.
+pub const IPSEC_NUM_SA: usize = 1024;
+/// Number of entries in the Rx IP address table.
+pub const IPSEC_NUM_RX_IP: usize = 128;
+/// Length of the AES-GCM key material of an SA: a 16 byte key followed by a 4 byte salt.
+pub const AES_GCM_KEY_MATERIAL_LEN: usize = 20;
+/// The only ICV length that the hardware can check.
+pub const AES_GCM_ICV_LEN: usize = 16;
+
+/// Number of times the security block is polled for having drained its data path.
+const SECURITY_DRAIN_POLL_ATTEMPTS: u32 = 100_000;
+
+// Security Tx/Rx Control
+pub(crate) const SECTXCTRL_SECTX_DIS: usize = 0;
+pub(crate) const SECTXCTRL_TX_DIS: usize = 1;
+const SECTXCTRL_STORE_FORWARD: usize = 2;
+pub(crate) const SECRXCTRL_SECRX_DIS: usize = 0;
+pub(crate) const SECRXCTRL_RX_DIS: usize = 1;
+
+// Security Tx/Rx Status
+const SECTXSTAT_SECTX_RDY: usize = 0;
+const SECRXSTAT_SECRX_RDY: usize = 0;
+
+// Security Tx Minimum IFG and Tx Buffer Almost Full
+pub(crate) const SECTXMINIFG_MINSECIFG: core::ops::Range<usize> = 0..4;
+/// The minimum inter-frame gap that leaves the security block time to process each packet.
+pub(crate) const SECTXMINIFG_SECURE_IFG: u32 = 3;
+/// The minimum inter-frame gap after reset, which is restored when the security block is bypassed again.
+pub(crate) const SECTXMINIFG_DEFAULT_IFG: u32 = 1;
+const SECTXBUFFAF_THRESHOLD: core::ops::Range<usize> = 0..10;
+/// Large enough that the almost full indication is only raised once a jumbo packet fits in the buffer.
+const SECTXBUFFAF_IPSEC: u32 = 0x15;
+const SECTXBUFFAF_DEFAULT: u32 = 0x250;
+
+// MAC Core Control 0 Register
+const HLREG0_LPBK: usize = 15;
+
+// Link Status Register
+const LINKS_UP: usize = 30;
+
+// MAC Control Register
+const MACC_FLU: usize = 0;
+
+// IPsec Tx/Rx Index
+const IPSIDX_IPS_EN: usize = 0;
+const IPSRXIDX_TABLE: core::ops::Range<usize> = 1..3;
+const IPSIDX_SA_IDX: core::ops::Range<usize> = 3..13;
+const IPSIDX_READ: usize = 30;
+const IPSIDX_WRITE: usize = 31;
+const IPSRXIDX_TABLE_IP: u32 = 1;
+const IPSRXIDX_TABLE_SPI: u32 = 2;
+const IPSRXIDX_TABLE_KEY: u32 = 3;
+
+// IPsec Rx Mode
+const IPSRXMOD_VALID: usize = 0;
+const IPSRXMOD_PROTO_ESP: usize = 2;
+const IPSRXMOD_DECRYPT: usize = 3;
+const IPSRXMOD_IPV6: usize = 4;
+
+/// The IPsec protocol of an SA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpsecProtocol {
+    /// Encapsulating Security Payload: the payload is encrypted and authenticated.
+    Esp,
+    /// Authentication Header: the packet is only authenticated.
+    Ah,
+}
+
+/// The AES-128-GCM key and salt of an SA.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct AesGcmKey {
+    key: [u8; 16],
+    salt: [u8; 4],
+}
+
+impl AesGcmKey {
+    /// Validates RFC 4106 key material, which is the key followed by the salt,
+    /// and the ICV length negotiated for the SA.
+    pub fn new(key_material: &[u8], icv_len: usize) -> Result<AesGcmKey, &'static str> {
+        if key_material.len() != AES_GCM_KEY_MATERIAL_LEN {
+            return Err("IPsec offload only supports AES-128-GCM keys");
+        }
+        if icv_len != AES_GCM_ICV_LEN {
+            return Err("IPsec offload only supports a 16 byte ICV");
+        }
+        let mut key = AesGcmKey { key: [0; 16], salt: [0; 4] };
+        key.key.copy_from_slice(&key_material[..16]);
+        key.salt.copy_from_slice(&key_material[16..]);
+        Ok(key)
+    }
+
+    /// Returns the value of key register `i`; the last word of the key goes in the first register.
+    fn key_register(&self, i: usize) -> u32 {
+        let word = 3 - i;
+        u32::from_be_bytes(self.key[word * 4..word * 4 + 4].try_into().unwrap())
+    }
+
+    fn salt_register(&self) -> u32 {
+        u32::from_be_bytes(self.salt)
+    }
+}
+
+// Don't print the key.
+impl core::fmt::Debug for AesGcmKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AesGcmKey").finish_non_exhaustive()
+    }
+}
+
+/// An inbound SA, which is matched on its destination IP, SPI and protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxSa {
+    pub dst_ip: IpAddr,
+    /// The SPI, in host byte order.
+    pub spi: u32,
+    pub protocol: IpsecProtocol,
+    pub key: AesGcmKey,
+}
+
+/// The fields of an Rx SA that the hardware matches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RxSaMatch {
+    spi: u32,
+    ip_index: u8,
+    protocol: IpsecProtocol,
+}
+
+/// An entry in the Rx IP address table, shared by all the Rx SAs with that destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RxIpEntry {
+    ip: IpAddr,
+    num_sa: u16,
+}
+
+/// Software copy of the SA tables, used to allocate entries and to share Rx IP entries.
+pub struct IpsecOffload {
+    tx_sa: [bool; IPSEC_NUM_SA],
+    rx_sa: [Option<RxSaMatch>; IPSEC_NUM_SA],
+    rx_ip: [Option<RxIpEntry>; IPSEC_NUM_RX_IP],
+    enabled: bool,
+}
+
+impl IpsecOffload {
+    /// Clears all the SA tables and starts with the security block disabled.
+    pub fn new(rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<IpsecOffload, &'static str> {
+        let mut ipsec = IpsecOffload {
+            tx_sa: [false; IPSEC_NUM_SA],
+            rx_sa: [None; IPSEC_NUM_SA],
+            rx_ip: [None; IPSEC_NUM_RX_IP],
+            enabled: false,
+        };
+        ipsec.disable(rx_regs2, mac_regs)?;
+        for i in 0..IPSEC_NUM_SA {
+            clear_tx_sa(mac_regs, i);
+            clear_rx_sa(mac_regs, i);
+        }
+        for i in 0..IPSEC_NUM_RX_IP {
+            write_rx_ip(mac_regs, i, [0; 4]);
+        }
+        Ok(ipsec)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables the security block and SA lookups.
+    /// The data paths are drained first, since the block settings can't change while packets are in flight.
+    pub fn enable(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        stop_data_paths(rx_regs2, mac_regs)?;
+        set_tx_gaps(mac_regs, SECTXMINIFG_SECURE_IFG, SECTXBUFFAF_IPSEC);
+
+        // restart the data paths with the security block enabled
+        let mut sectxctrl = 0;
+        sectxctrl.set_bit(SECTXCTRL_STORE_FORWARD, true);
+        mac_regs.security_rx_control.write(0);
+        mac_regs.security_tx_control.write(sectxctrl);
+
+        mac_regs.ipsec_tx_index.update(|idx| { idx.set_bit(IPSIDX_IPS_EN, true); });
+        mac_regs.ipsec_rx_index.update(|idx| { idx.set_bit(IPSIDX_IPS_EN, true); });
+        self.enabled = true;
+        Ok(())
+    }
+
+    /// Disables SA lookups and bypasses the security block.
+    /// The SA tables are left as they are, so offload can be enabled again without re-adding them.
+    pub fn disable(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        mac_regs.ipsec_tx_index.update(|idx| { idx.set_bit(IPSIDX_IPS_EN, false); });
+        mac_regs.ipsec_rx_index.update(|idx| { idx.set_bit(IPSIDX_IPS_EN, false); });
+
+        let result = stop_data_paths(rx_regs2, mac_regs);
+        set_tx_gaps(mac_regs, SECTXMINIFG_DEFAULT_IFG, SECTXBUFFAF_DEFAULT);
+        // the block is bypassed even if it didn't drain, so that traffic isn't left stopped
+        let mut sectxctrl = 0;
+        sectxctrl.set_bit(SECTXCTRL_SECTX_DIS, true);
+        let mut secrxctrl = 0;
+        secrxctrl.set_bit(SECRXCTRL_SECRX_DIS, true);
+        mac_regs.security_tx_control.write(sectxctrl);
+        mac_regs.security_rx_control.write(secrxctrl);
+        self.enabled = false;
+        result
+    }
+
+    /// Adds a Tx SA and returns its index, which is passed to the hardware in the context descriptor.
+    pub fn add_tx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, key: &AesGcmKey) -> Result<u16, &'static str> {
+        let index = self.tx_sa.iter().position(|&used| !used).ok_or("IPsec Tx SA table is full")?;
+        for i in 0..4 {
+            mac_regs.ipsec_tx_key_registers[i].write(key.key_register(i));
+        }
+        mac_regs.ipsec_tx_salt_register.write(key.salt_register());
+        write_tx_index(mac_regs, index);
+        self.tx_sa[index] = true;
+        Ok(index as u16)
+    }
+
+    pub fn remove_tx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, index: u16) -> Result<(), &'static str> {
+        let index = index as usize;
+        if index >= IPSEC_NUM_SA || !self.tx_sa[index] {
+            return Err("IPsec Tx SA is not in use");
+        }
+        clear_tx_sa(mac_regs, index);
+        self.tx_sa[index] = false;
+        Ok(())
+    }
+
+    /// Adds an Rx SA and returns its index.
+    /// The destination IP shares an entry of the IP table with other SAs that have the same destination.
+    pub fn add_rx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: &RxSa) -> Result<u16, &'static str> {
+        let index = self.rx_sa.iter().position(|entry| entry.is_none()).ok_or("IPsec Rx SA table is full")?;
+
+        let ip_index = match self.rx_ip.iter().position(|entry| entry.is_some_and(|e| e.ip == sa.dst_ip)) {
+            Some(ip_index) => {
+                let duplicate = RxSaMatch { spi: sa.spi, ip_index: ip_index as u8, protocol: sa.protocol };
+                if self.rx_sa.contains(&Some(duplicate)) {
+                    return Err("an IPsec Rx SA with the same SPI, destination and protocol already exists");
+                }
+                ip_index
+            }
+            None => {
+                let ip_index = self.rx_ip.iter().position(|entry| entry.is_none()).ok_or("IPsec Rx IP table is full")?;
+                write_rx_ip(mac_regs, ip_index, ip_registers(sa.dst_ip));
+                self.rx_ip[ip_index] = Some(RxIpEntry { ip: sa.dst_ip, num_sa: 0 });
+                ip_index
+            }
+        };
+
+        // the SPI is matched against the packet in network byte order
+        mac_regs.ipsec_rx_spi_register.write(u32::from_le_bytes(sa.spi.to_be_bytes()));
+        mac_regs.ipsec_rx_ip_index_register.write(ip_index as u32);
+        write_rx_index(mac_regs, IPSRXIDX_TABLE_SPI, index);
+
+        let mut mode = 0;
+        mode.set_bit(IPSRXMOD_VALID, true);
+        mode.set_bit(IPSRXMOD_PROTO_ESP, sa.protocol == IpsecProtocol::Esp);
+        mode.set_bit(IPSRXMOD_DECRYPT, sa.protocol == IpsecProtocol::Esp);
+        mode.set_bit(IPSRXMOD_IPV6, sa.dst_ip.is_ipv6());
+        for i in 0..4 {
+            mac_regs.ipsec_rx_key_register[i].write(sa.key.key_register(i));
+        }
+        mac_regs.ipsec_rx_salt_register.write(sa.key.salt_register());
+        mac_regs.ipsec_rx_mode_register.write(mode);
+        write_rx_index(mac_regs, IPSRXIDX_TABLE_KEY, index);
+
+        if let Some(entry) = self.rx_ip[ip_index].as_mut() {
+            entry.num_sa += 1;
+        }
+        self.rx_sa[index] = Some(RxSaMatch { spi: sa.spi, ip_index: ip_index as u8, protocol: sa.protocol });
+        Ok(index as u16)
+    }
+
+    /// Removes an Rx SA, and the IP table entry of its destination if no other SA uses it.
+    pub fn remove_rx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, index: u16) -> Result<(), &'static str> {
+        let index = index as usize;
+        let sa = self.rx_sa.get(index).copied().flatten().ok_or("IPsec Rx SA is not in use")?;
+        clear_rx_sa(mac_regs, index);
+        self.rx_sa[index] = None;
+
+        let ip_index = sa.ip_index as usize;
+        if let Some(entry) = self.rx_ip[ip_index].as_mut() {
+            entry.num_sa -= 1;
+            if entry.num_sa == 0 {
+                write_rx_ip(mac_regs, ip_index, [0; 4]);
+                self.rx_ip[ip_index] = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn num_tx_sa(&self) -> usize {
+        self.tx_sa.iter().filter(|&&used| used).count()
+    }
+
+    pub fn num_rx_sa(&self) -> usize {
+        self.rx_sa.iter().filter(|entry| entry.is_some()).count()
+    }
+}
+
+/// Stops the Tx and Rx data paths of the security block and waits for both to drain.
+///
+/// Without link, the packets in the Tx path have nowhere to go, so the MAC is switched to loopback
+/// with its link forced up until they have drained, and then switched back.
+pub(crate) fn stop_data_paths(rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+    mac_regs.security_tx_control.update(|ctrl| { ctrl.set_bit(SECTXCTRL_TX_DIS, true); });
+    mac_regs.security_rx_control.update(|ctrl| { ctrl.set_bit(SECRXCTRL_RX_DIS, true); });
+    if data_paths_drained(mac_regs) {
+        return Ok(());
+    }
+
+    let link_up = rx_regs2.link_status_register.read().get_bit(LINKS_UP);
+    let hlreg0 = rx_regs2.mac_core_control_0_register.read();
+    let macc = rx_regs2.mac_control_register.read();
+    if !link_up {
+        let (loopback_hlreg0, loopback_macc) = mac_loopback(hlreg0, macc);
+        rx_regs2.mac_control_register.write(loopback_macc);
+        rx_regs2.mac_core_control_0_register.write(loopback_hlreg0);
+    }
+
+    let mut drained = false;
+    for _ in 0..SECURITY_DRAIN_POLL_ATTEMPTS {
+        drained = data_paths_drained(mac_regs);
+        if drained {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    if !link_up {
+        rx_regs2.mac_core_control_0_register.write(hlreg0);
+        rx_regs2.mac_control_register.write(macc);
+    }
+    if drained { Ok(()) } else { Err("security block data paths didn't drain") }
+}
+
+/// Returns whether IPsec is using the security block: the block isn't bypassed and SA lookups are enabled.
+pub(crate) fn ipsec_enabled(mac_regs: &IntelIxgbeMacRegisters) -> bool {
+    security_block_used_by_ipsec(
+        mac_regs.security_tx_control.read(),
+        mac_regs.security_rx_control.read(),
+        mac_regs.ipsec_tx_index.read(),
+        mac_regs.ipsec_rx_index.read(),
+    )
+}
+
+fn security_block_used_by_ipsec(sectxctrl: u32, secrxctrl: u32, ipstxidx: u32, ipsrxidx: u32) -> bool {
+    (!sectxctrl.get_bit(SECTXCTRL_SECTX_DIS) && ipstxidx.get_bit(IPSIDX_IPS_EN))
+        || (!secrxctrl.get_bit(SECRXCTRL_SECRX_DIS) && ipsrxidx.get_bit(IPSIDX_IPS_EN))
+}
+
+fn data_paths_drained(mac_regs: &IntelIxgbeMacRegisters) -> bool {
+    mac_regs.security_tx_status.read().get_bit(SECTXSTAT_SECTX_RDY) && mac_regs.security_rx_status.read().get_bit(SECRXSTAT_SECRX_RDY)
+}
+
+/// Returns the values of HLREG0 and MACC that loop the Tx path back to Rx with the link forced up.
+fn mac_loopback(mut hlreg0: u32, mut macc: u32) -> (u32, u32) {
+    hlreg0.set_bit(HLREG0_LPBK, true);
+    macc.set_bit(MACC_FLU, true);
+    (hlreg0, macc)
+}
+
+/// Sets the minimum inter-frame gap and the almost full threshold of the Tx security buffer.
+fn set_tx_gaps(mac_regs: &mut IntelIxgbeMacRegisters, min_ifg: u32, almost_full: u32) {
+    mac_regs.security_tx_minimum_ifg.update(|minifg| { minifg.set_bits(SECTXMINIFG_MINSECIFG, min_ifg); });
+    mac_regs.security_tx_buffer_almost_full.update(|buffaf| { buffaf.set_bits(SECTXBUFFAF_THRESHOLD, almost_full); });
+}
+
+/// Commits the staged key and salt registers to Tx SA `index`.
+fn write_tx_index(mac_regs: &mut IntelIxgbeMacRegisters, index: usize) {
+    mac_regs.ipsec_tx_index.update(|idx| {
+        idx.set_bits(IPSIDX_SA_IDX, index as u32);
+        idx.set_bit(IPSIDX_READ, false);
+        idx.set_bit(IPSIDX_WRITE, true);
+    });
+}
+
+/// Commits the staged registers of `table` to entry `index` of the Rx tables.
+fn write_rx_index(mac_regs: &mut IntelIxgbeMacRegisters, table: u32, index: usize) {
+    mac_regs.ipsec_rx_index.update(|idx| {
+        idx.set_bits(IPSRXIDX_TABLE, table);
+        idx.set_bits(IPSIDX_SA_IDX, index as u32);
+        idx.set_bit(IPSIDX_READ, false);
+        idx.set_bit(IPSIDX_WRITE, true);
+    });
+}
+
+fn clear_tx_sa(mac_regs: &mut IntelIxgbeMacRegisters, index: usize) {
+    for i in 0..4 {
+        mac_regs.ipsec_tx_key_registers[i].write(0);
+    }
+    mac_regs.ipsec_tx_salt_register.write(0);
+    write_tx_index(mac_regs, index);
+}
+
+fn clear_rx_sa(mac_regs: &mut IntelIxgbeMacRegisters, index: usize) {
+    mac_regs.ipsec_rx_spi_register.write(0);
+    mac_regs.ipsec_rx_ip_index_register.write(0);
+    write_rx_index(mac_regs, IPSRXIDX_TABLE_SPI, index);
+    for i in 0..4 {
+        mac_regs.ipsec_rx_key_register[i].write(0);
+    }
+    mac_regs.ipsec_rx_salt_register.write(0);
+    mac_regs.ipsec_rx_mode_register.write(0);
+    write_rx_index(mac_regs, IPSRXIDX_TABLE_KEY, index);
+}
+
+fn write_rx_ip(mac_regs: &mut IntelIxgbeMacRegisters, index: usize, ip: [u32; 4]) {
+    for (i, word) in ip.into_iter().enumerate() {
+        mac_regs.ipsec_rx_ip_address_register[i].write(word);
+    }
+    write_rx_index(mac_regs, IPSRXIDX_TABLE_IP, index);
+}
+
+/// Returns the IP address registers of an address, which hold it in network byte order.
+/// An IPv4 address goes in the last register.
+fn ip_registers(ip: IpAddr) -> [u32; 4] {
+    let mut regs = [0; 4];
+    match ip {
+        IpAddr::V4(ip) => regs[3] = u32::from_le_bytes(ip.octets()),
+        IpAddr::V6(ip) => {
+            for (reg, word) in regs.iter_mut().zip(ip.octets().chunks_exact(4)) {
+                *reg = u32::from_le_bytes(word.try_into().unwrap());
+            }
+        }
+    }
+    regs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    const KEY_MATERIAL: [u8; AES_GCM_KEY_MATERIAL_LEN] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0xca, 0xfe, 0xba, 0xbe,
+    ];
+
+    #[test]
+    fn key_words_are_written_last_word_first() {
+        let key = AesGcmKey::new(&KEY_MATERIAL, AES_GCM_ICV_LEN).unwrap();
+        assert_eq!(key.key_register(0), 0x0c0d0e0f);
+        assert_eq!(key.key_register(3), 0x00010203);
+        assert_eq!(key.salt_register(), 0xcafebabe);
+    }
+
+    #[test]
+    fn only_aes_128_gcm_with_a_16_byte_icv_is_offloaded() {
+        assert!(AesGcmKey::new(&KEY_MATERIAL[..16], AES_GCM_ICV_LEN).is_err());
+        assert!(AesGcmKey::new(&[0; 36], AES_GCM_ICV_LEN).is_err());
+        assert!(AesGcmKey::new(&KEY_MATERIAL, 12).is_err());
+    }
+
+    #[test]
+    fn ip_addresses_are_held_in_network_byte_order() {
+        let ipv4 = ip_registers(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)));
+        assert_eq!(ipv4, [0, 0, 0, u32::from_le_bytes([192, 168, 1, 2])]);
+        let ipv6 = ip_registers(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)));
+        assert_eq!(ipv6[0].to_le_bytes(), [0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(ipv6[3].to_le_bytes(), [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn tx_is_drained_through_mac_loopback_with_the_link_forced_up() {
+        let (hlreg0, macc) = mac_loopback(0x2FF, 0);
+        assert_eq!(hlreg0, 0x2FF | 1 << HLREG0_LPBK);
+        assert_eq!(macc, 1 << MACC_FLU);
+    }
+
+    #[test]
+    fn ipsec_only_holds_the_security_block_while_it_isnt_bypassed() {
+        let mut bypassed = 0;
+        bypassed.set_bit(SECTXCTRL_SECTX_DIS, true);
+        let mut lookups = 0;
+        lookups.set_bit(IPSIDX_IPS_EN, true);
+        assert!(security_block_used_by_ipsec(0, 0, lookups, lookups));
+        assert!(security_block_used_by_ipsec(bypassed, 0, lookups, lookups));
+        assert!(!security_block_used_by_ipsec(bypassed, bypassed, lookups, lookups));
+        assert!(!security_block_used_by_ipsec(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn security_block_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::{MAC_REGISTERS_OFFSET, RX_REGISTERS2_OFFSET};
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, mac_core_control_0_register), 0x4240);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, mac_control_register), 0x4330);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, security_tx_control), 0x8800);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, security_tx_minimum_ifg), 0x8810);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, ipsec_tx_index), 0x8900);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, ipsec_rx_index), 0x8E00);
+    }
+}
@@ -0,0 +1,163 @@
+//! Control of the four port LEDs through LEDCTL.
+//!
+//! Each LED has one byte of LEDCTL: the low nibble selects what drives the LED, and the top bits
+//! invert it or make it blink while it's on. The blink rate is shared by all LEDs.
+
+use bit_field::BitField;
+use core::time::Duration;
+use embedded_hal::delay::DelayNs;
+use crate::IntelIxgbeRegisters1;
+
+// LED Control, with one byte per LED
+const LEDCTL_LED_BITS: usize = 8;
+const LEDCTL_MODE: core::ops::Range<usize> = 0..4;
+/// Only present in the byte of LED 0, and applies to all LEDs.
+const LEDCTL_GLOBAL_BLINK_MODE: usize = 5;
+const LEDCTL_IVRT: usize = 6;
+const LEDCTL_BLINK: usize = 7;
+
+/// The port LEDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Led {
+    Led0 = 0,
+    Led1 = 1,
+    Led2 = 2,
+    Led3 = 3,
+}
+
+impl Led {
+    fn bits(self) -> core::ops::Range<usize> {
+        let start = self as usize * LEDCTL_LED_BITS;
+        start..start + LEDCTL_LED_BITS
+    }
+}
+
+/// What drives an LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedMode {
+    /// On while the link is up.
+    LinkUp = 0x0,
+    /// On while the link is up at 10 Gb/s.
+    Link10G = 0x1,
+    /// On while packets are transmitted or received.
+    MacActivity = 0x2,
+    /// On while packets that pass the MAC filters are transmitted or received.
+    FilterActivity = 0x3,
+    /// On while the link is up, and off while there is activity.
+    LinkActivity = 0x4,
+    /// On while the link is up at 1 Gb/s.
+    Link1G = 0x5,
+    /// On while the link is up at 100 Mb/s.
+    Link100M = 0x6,
+    /// Always on.
+    On = 0xE,
+    /// Always off.
+    Off = 0xF,
+}
+
+impl LedMode {
+    fn from_bits(bits: u32) -> Option<LedMode> {
+        match bits {
+            0x0 => Some(LedMode::LinkUp),
+            0x1 => Some(LedMode::Link10G),
+            0x2 => Some(LedMode::MacActivity),
+            0x3 => Some(LedMode::FilterActivity),
+            0x4 => Some(LedMode::LinkActivity),
+            0x5 => Some(LedMode::Link1G),
+            0x6 => Some(LedMode::Link100M),
+            0xE => Some(LedMode::On),
+            0xF => Some(LedMode::Off),
+            _ => None,
+        }
+    }
+}
+
+/// The configuration of one LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedConfig {
+    pub mode: LedMode,
+    /// The LED blinks instead of staying on.
+    pub blink: bool,
+    /// The LED is on when the mode condition is false.
+    pub invert: bool,
+}
+
+/// The blink rate of all LEDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlinkRate {
+    /// 200 ms on and 200 ms off.
+    Slow,
+    /// 83 ms on and 83 ms off.
+    Fast,
+}
+
+/// The LEDs of one port.
+pub struct LedControl<'a> {
+    regs1: &'a mut IntelIxgbeRegisters1,
+}
+
+impl<'a> LedControl<'a> {
+    pub fn new(regs1: &'a mut IntelIxgbeRegisters1) -> LedControl<'a> {
+        LedControl { regs1 }
+    }
+
+    /// Reads the configuration of an LED.
+    /// Fails if the LED is set to a reserved mode, which can be left by the NVM.
+    pub fn config(&self, led: Led) -> Result<LedConfig, &'static str> {
+        let bits = self.regs1.led_control.read().get_bits(led.bits());
+        Ok(LedConfig {
+            mode: LedMode::from_bits(bits.get_bits(LEDCTL_MODE)).ok_or("LED is set to a reserved mode")?,
+            blink: bits.get_bit(LEDCTL_BLINK),
+            invert: bits.get_bit(LEDCTL_IVRT),
+        })
+    }
+
+    pub fn set_config(&mut self, led: Led, config: LedConfig) {
+        self.regs1.led_control.update(|ledctl| {
+            let mut bits = ledctl.get_bits(led.bits());
+            bits.set_bits(LEDCTL_MODE, config.mode as u32);
+            bits.set_bit(LEDCTL_BLINK, config.blink);
+            bits.set_bit(LEDCTL_IVRT, config.invert);
+            ledctl.set_bits(led.bits(), bits);
+        });
+    }
+
+    pub fn set_mode(&mut self, led: Led, mode: LedMode) {
+        self.regs1.led_control.update(|ledctl| {
+            let start = led.bits().start;
+            ledctl.set_bits(start + LEDCTL_MODE.start..start + LEDCTL_MODE.end, mode as u32);
+        });
+    }
+
+    pub fn set_blink(&mut self, led: Led, blink: bool) {
+        self.regs1.led_control.update(|ledctl| { ledctl.set_bit(led.bits().start + LEDCTL_BLINK, blink); });
+    }
+
+    pub fn set_invert(&mut self, led: Led, invert: bool) {
+        self.regs1.led_control.update(|ledctl| { ledctl.set_bit(led.bits().start + LEDCTL_IVRT, invert); });
+    }
+
+    pub fn blink_rate(&self) -> BlinkRate {
+        if self.regs1.led_control.read().get_bit(LEDCTL_GLOBAL_BLINK_MODE) { BlinkRate::Fast } else { BlinkRate::Slow }
+    }
+
+    pub fn set_blink_rate(&mut self, rate: BlinkRate) {
+        self.regs1.led_control.update(|ledctl| { ledctl.set_bit(LEDCTL_GLOBAL_BLINK_MODE, rate == BlinkRate::Fast); });
+    }
+
+    /// Blinks an LED for `duration` so the port can be found, then restores all LEDs to their prior configuration.
+    /// Blocks for the whole duration.
+    pub fn identify<D: DelayNs>(&mut self, led: Led, duration: Duration, delay: &mut D) {
+        let saved = self.regs1.led_control.read();
+        self.set_config(led, LedConfig { mode: LedMode::On, blink: true, invert: false });
+
+        let mut remaining_ms = duration.as_millis();
+        while remaining_ms > 0 {
+            let ms = remaining_ms.min(u32::MAX as u128) as u32;
+            delay.delay_ms(ms);
+            remaining_ms -= ms as u128;
+        }
+
+        self.regs1.led_control.write(saved);
+    }
+}
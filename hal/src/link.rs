@@ -0,0 +1,310 @@
+//! Link mode configuration through AUTOC/AUTOC2, and link state decoding from LINKS and the AN registers.
+//!
+//! AUTOC selects the link mode state machine (LMS): backplane autonegotiation between KX4, KX and KR,
+//! 10G serial without autonegotiation for SFI, or SGMII. AUTOC2 selects which 10G serial PMA/PMD is used.
+//! AUTOC is also written by the firmware, so it's only changed while holding the MAC CSR resource in SW_FW_SYNC.
+//!
+//! KR FEC (clause 74) is negotiated during backplane autonegotiation: it's used when both ends advertise
+//! the ability and at least one of them requests it.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::eeprom::{acquire_swfw_sync, release_swfw_sync, SwFwResource};
+
+// Auto-Negotiation Control Register
+const AUTOC_FLU: usize = 0;
+const AUTOC_10G_PMA_PMD: core::ops::Range<usize> = 7..9;
+const AUTOC_10G_PMA_PMD_KX4: u32 = 0b01;
+const AUTOC_1G_PMA_PMD: usize = 9;
+const AUTOC_AN_RESTART: usize = 12;
+const AUTOC_LMS: core::ops::Range<usize> = 13..16;
+const AUTOC_LMS_10G_SERIAL: u32 = 0b011;
+const AUTOC_LMS_KX4_KX_KR: u32 = 0b100;
+const AUTOC_LMS_KX4_KX_KR_SGMII: u32 = 0b111;
+const AUTOC_KR_SUPP: usize = 16;
+const AUTOC_FECR: usize = 17;
+const AUTOC_FECA: usize = 18;
+const AUTOC_KX_SUPP: usize = 30;
+const AUTOC_KX4_SUPP: usize = 31;
+
+// Auto-Negotiation Control 2 Register
+const AUTOC2_10G_SERIAL_PMA_PMD: core::ops::Range<usize> = 16..18;
+const AUTOC2_10G_SERIAL_PMA_PMD_KR: u32 = 0b00;
+const AUTOC2_10G_SERIAL_PMA_PMD_SFI: u32 = 0b10;
+
+// Link Status Register
+const LINKS_UP: usize = 30;
+const LINKS_KX_AN_COMP: usize = 31;
+const LINKS_SPEED: core::ops::Range<usize> = 28..30;
+const LINKS_SPEED_100M: u32 = 0b01;
+const LINKS_SPEED_1G: u32 = 0b10;
+const LINKS_SPEED_10G: u32 = 0b11;
+
+// Auto-Negotiation Link Partner Link Control Words, which hold bits 15:0 and 47:16 of the partner's base page
+const ANLP1_SYM_PAUSE: usize = 10;
+const ANLP1_ASM_PAUSE: usize = 11;
+const ANLP2_KX: usize = 21 - 16;
+const ANLP2_KX4: usize = 22 - 16;
+const ANLP2_KR: usize = 23 - 16;
+const ANLP2_FEC_ABILITY: usize = 46 - 16;
+const ANLP2_FEC_REQUESTED: usize = 47 - 16;
+
+/// The speed of the link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkSpeed {
+    Speed100M,
+    Speed1G,
+    Speed10G,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+/// The link modes of the MAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// 10GBASE-KX4 backplane, autonegotiated.
+    Kx4,
+    /// 1000BASE-KX backplane, autonegotiated.
+    Kx,
+    /// 10GBASE-KR backplane, autonegotiated.
+    Kr,
+    /// 10G SFI to an SFP+ module, without autonegotiation.
+    Sfi,
+    /// 1G SGMII to an external PHY.
+    Sgmii1G,
+}
+
+/// The abilities a backplane link partner advertised in its autonegotiation base page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartnerAbilities {
+    pub kx: bool,
+    pub kx4: bool,
+    pub kr: bool,
+    pub fec_ability: bool,
+    pub fec_requested: bool,
+    pub symmetric_pause: bool,
+    pub asymmetric_pause: bool,
+}
+
+/// The state of the link. `speed` and `duplex` are `None` while the link is down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkState {
+    pub up: bool,
+    pub speed: Option<LinkSpeed>,
+    pub duplex: Option<Duplex>,
+    /// KR FEC is in use on the link.
+    pub fec: bool,
+    /// The partner's abilities, once backplane autonegotiation has completed.
+    pub partner_abilities: Option<PartnerAbilities>,
+}
+
+/// Counts of the blocks the KR FEC decoder corrected and couldn't correct, since the last read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FecCounters {
+    pub corrected_blocks: u32,
+    pub uncorrected_blocks: u32,
+}
+
+/// The configured link mode and KR FEC setting.
+pub struct Link {
+    mode: LinkMode,
+    fec: bool,
+}
+
+impl Link {
+    /// Programs the link mode into AUTOC and AUTOC2 and restarts autonegotiation.
+    /// `fec` advertises and requests KR FEC, which only applies to the KR mode.
+    pub fn configure(
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        mode: LinkMode,
+        fec: bool,
+    ) -> Result<Link, &'static str> {
+        check_fec(mode, fec)?;
+        let link = Link { mode, fec };
+        link.program(rx_regs2, mac_regs)?;
+        Ok(link)
+    }
+
+    pub fn mode(&self) -> LinkMode {
+        self.mode
+    }
+
+    pub fn fec_enabled(&self) -> bool {
+        self.fec
+    }
+
+    /// Enables or disables KR FEC, which renegotiates the link.
+    pub fn set_fec(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, fec: bool) -> Result<(), &'static str> {
+        check_fec(self.mode, fec)?;
+        self.fec = fec;
+        self.program(rx_regs2, mac_regs)
+    }
+
+    /// Restarts autonegotiation with the current AUTOC settings.
+    pub fn restart_autonegotiation(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        if self.mode == LinkMode::Sfi {
+            return Err("SFI links aren't autonegotiated");
+        }
+        acquire_swfw_sync(mac_regs, SwFwResource::MacCsr)?;
+        rx_regs2.auto_negotiation_control_register.update(|autoc| { autoc.set_bit(AUTOC_AN_RESTART, true); });
+        release_swfw_sync(mac_regs, SwFwResource::MacCsr)
+    }
+
+    /// Reads the state of the link.
+    pub fn state(&self, rx_regs2: &IntelIxgbeRxRegisters2) -> LinkState {
+        let links = rx_regs2.link_status_register.read();
+        let up = links.get_bit(LINKS_UP);
+        let speed = match links.get_bits(LINKS_SPEED) {
+            LINKS_SPEED_10G => Some(LinkSpeed::Speed10G),
+            LINKS_SPEED_1G => Some(LinkSpeed::Speed1G),
+            LINKS_SPEED_100M => Some(LinkSpeed::Speed100M),
+            _ => None,
+        }.filter(|_| up);
+
+        let partner_abilities = (links.get_bit(LINKS_KX_AN_COMP) && self.mode != LinkMode::Sfi).then(|| {
+            let anlp1 = rx_regs2.auto_negotiation_link_partner_link_control_word_1_register.read();
+            let anlp2 = rx_regs2.auto_negotiation_link_partner_link_control_word_2_register.read();
+            PartnerAbilities {
+                kx: anlp2.get_bit(ANLP2_KX),
+                kx4: anlp2.get_bit(ANLP2_KX4),
+                kr: anlp2.get_bit(ANLP2_KR),
+                fec_ability: anlp2.get_bit(ANLP2_FEC_ABILITY),
+                fec_requested: anlp2.get_bit(ANLP2_FEC_REQUESTED),
+                symmetric_pause: anlp1.get_bit(ANLP1_SYM_PAUSE),
+                asymmetric_pause: anlp1.get_bit(ANLP1_ASM_PAUSE),
+            }
+        });
+
+        let fec = up
+            && self.mode == LinkMode::Kr
+            && self.fec
+            && speed == Some(LinkSpeed::Speed10G)
+            && partner_abilities.is_some_and(|partner| partner.kr && partner.fec_ability);
+
+        LinkState {
+            up,
+            speed,
+            // the MAC only supports full duplex
+            duplex: up.then_some(Duplex::Full),
+            fec,
+            partner_abilities,
+        }
+    }
+
+    /// Reads the KR FEC block counters, which are cleared on read.
+    pub fn fec_counters(&self, rx_regs2: &IntelIxgbeRxRegisters2) -> FecCounters {
+        FecCounters {
+            corrected_blocks: rx_regs2.fec_status_1_register.read(),
+            uncorrected_blocks: rx_regs2.fec_status_2_register.read(),
+        }
+    }
+
+    fn program(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        let (autoc, autoc2) = self.encode(
+            rx_regs2.auto_negotiation_control_register.read(),
+            rx_regs2.auto_negotiation_control_2_register.read(),
+        );
+        acquire_swfw_sync(mac_regs, SwFwResource::MacCsr)?;
+        rx_regs2.auto_negotiation_control_2_register.write(autoc2);
+        rx_regs2.auto_negotiation_control_register.write(autoc);
+        release_swfw_sync(mac_regs, SwFwResource::MacCsr)
+    }
+
+    /// Returns AUTOC and AUTOC2 with the link mode and FEC fields replaced by this configuration.
+    fn encode(&self, mut autoc: u32, mut autoc2: u32) -> (u32, u32) {
+        autoc.set_bit(AUTOC_FLU, false);
+        autoc.set_bit(AUTOC_KX4_SUPP, false);
+        autoc.set_bit(AUTOC_KX_SUPP, false);
+        autoc.set_bit(AUTOC_KR_SUPP, false);
+        autoc.set_bit(AUTOC_FECA, self.fec);
+        autoc.set_bit(AUTOC_FECR, self.fec);
+
+        match self.mode {
+            LinkMode::Kx4 => {
+                autoc.set_bits(AUTOC_LMS, AUTOC_LMS_KX4_KX_KR);
+                autoc.set_bits(AUTOC_10G_PMA_PMD, AUTOC_10G_PMA_PMD_KX4);
+                autoc.set_bit(AUTOC_KX4_SUPP, true);
+            }
+            LinkMode::Kx => {
+                autoc.set_bits(AUTOC_LMS, AUTOC_LMS_KX4_KX_KR);
+                // the 1G PMA/PMD bit selects KX rather than BX
+                autoc.set_bit(AUTOC_1G_PMA_PMD, true);
+                autoc.set_bit(AUTOC_KX_SUPP, true);
+            }
+            LinkMode::Kr => {
+                autoc.set_bits(AUTOC_LMS, AUTOC_LMS_KX4_KX_KR);
+                autoc.set_bit(AUTOC_KR_SUPP, true);
+                autoc2.set_bits(AUTOC2_10G_SERIAL_PMA_PMD, AUTOC2_10G_SERIAL_PMA_PMD_KR);
+            }
+            LinkMode::Sfi => {
+                autoc.set_bits(AUTOC_LMS, AUTOC_LMS_10G_SERIAL);
+                // the 1G PMA/PMD bit selects SFI rather than KX
+                autoc.set_bit(AUTOC_1G_PMA_PMD, false);
+                autoc2.set_bits(AUTOC2_10G_SERIAL_PMA_PMD, AUTOC2_10G_SERIAL_PMA_PMD_SFI);
+            }
+            LinkMode::Sgmii1G => {
+                autoc.set_bits(AUTOC_LMS, AUTOC_LMS_KX4_KX_KR_SGMII);
+                autoc.set_bit(AUTOC_KX_SUPP, true);
+            }
+        }
+        autoc.set_bit(AUTOC_AN_RESTART, self.mode != LinkMode::Sfi);
+        (autoc, autoc2)
+    }
+}
+
+/// KR FEC can only be enabled in the KR link mode.
+fn check_fec(mode: LinkMode, fec: bool) -> Result<(), &'static str> {
+    if fec && mode != LinkMode::Kr {
+        return Err("KR FEC is only available in the KR link mode");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fec_is_only_accepted_in_kr_mode() {
+        assert!(check_fec(LinkMode::Kr, true).is_ok());
+        for mode in [LinkMode::Kx4, LinkMode::Kx, LinkMode::Sfi, LinkMode::Sgmii1G] {
+            assert!(check_fec(mode, true).is_err());
+            assert!(check_fec(mode, false).is_ok());
+        }
+    }
+
+    #[test]
+    fn kr_with_fec_advertises_and_requests_it() {
+        let (autoc, autoc2) = Link { mode: LinkMode::Kr, fec: true }.encode(0, u32::MAX);
+        assert_eq!(autoc.get_bits(AUTOC_LMS), AUTOC_LMS_KX4_KX_KR);
+        assert!(autoc.get_bit(AUTOC_KR_SUPP) && !autoc.get_bit(AUTOC_KX4_SUPP) && !autoc.get_bit(AUTOC_KX_SUPP));
+        assert!(autoc.get_bit(AUTOC_FECA) && autoc.get_bit(AUTOC_FECR));
+        assert!(autoc.get_bit(AUTOC_AN_RESTART));
+        assert_eq!(autoc2.get_bits(AUTOC2_10G_SERIAL_PMA_PMD), AUTOC2_10G_SERIAL_PMA_PMD_KR);
+    }
+
+    #[test]
+    fn sfi_isnt_autonegotiated() {
+        let (autoc, autoc2) = Link { mode: LinkMode::Sfi, fec: false }.encode(u32::MAX, 0);
+        assert_eq!(autoc.get_bits(AUTOC_LMS), AUTOC_LMS_10G_SERIAL);
+        assert!(!autoc.get_bit(AUTOC_AN_RESTART) && !autoc.get_bit(AUTOC_FLU) && !autoc.get_bit(AUTOC_1G_PMA_PMD));
+        assert!(!autoc.get_bit(AUTOC_FECA) && !autoc.get_bit(AUTOC_FECR));
+        assert_eq!(autoc2.get_bits(AUTOC2_10G_SERIAL_PMA_PMD), AUTOC2_10G_SERIAL_PMA_PMD_SFI);
+    }
+
+    #[test]
+    fn link_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::RX_REGISTERS2_OFFSET;
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, auto_negotiation_control_register), 0x42A0);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, link_status_register), 0x42A4);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, auto_negotiation_control_2_register), 0x42A8);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, auto_negotiation_link_partner_link_control_word_1_register), 0x42B0);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, auto_negotiation_link_partner_link_control_word_2_register), 0x42B4);
+    }
+}
@@ -0,0 +1,348 @@
+//! Unicast MAC address filtering over the Receive Address registers (RAL/RAH) and the MAC Pool Select Array (MPSAR).
+//!
+//! The 82599 has 128 exact-match receive address entries. Entry 0 holds the permanent address loaded from the NVM
+//! and isn't managed here. In virtualized mode, the MPSAR bitmap of an entry selects the pools that receive
+//! packets sent to its address.
+//!
+//! When all entries are taken, further addresses are kept on an overflow list and are received either by
+//! enabling unicast promiscuous mode, or by setting their hash in the Unicast Table Array (PFUTA) and letting
+//! their pools accept packets that hit it. Overflowed addresses are moved into entries as they are freed.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+
+/// Number of receive address entries, including the permanent address in entry 0.
+pub const NUM_RECEIVE_ADDRESSES: usize = 128;
+/// Number of addresses that can be held on the overflow list once all entries are taken.
+pub const MAX_OVERFLOW_ADDRESSES: usize = 128;
+/// Number of bits in the unicast and multicast hash tables.
+pub const HASH_TABLE_BITS: usize = 4096;
+
+// Receive Address High
+const RAH_RAH: core::ops::Range<usize> = 0..16;
+const RAH_AV: usize = 31;
+
+// Filter Control Register
+const FCTRL_UPE: usize = 9;
+
+// Multicast Control Register
+const MCSTCTRL_MO: core::ops::Range<usize> = 0..2;
+
+// PF VM L2 Control Register
+const PFVML2FLT_ROPE: usize = 26;
+
+/// The 12 bits of a destination address that index the unicast and multicast hash tables, selected by MCSTCTRL.MO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashOffset {
+    Bits47To36 = 0,
+    Bits46To35 = 1,
+    Bits45To34 = 2,
+    Bits43To32 = 3,
+}
+
+impl HashOffset {
+    pub(crate) fn from_bits(bits: u32) -> HashOffset {
+        match bits & 0x3 {
+            0 => HashOffset::Bits47To36,
+            1 => HashOffset::Bits46To35,
+            2 => HashOffset::Bits45To34,
+            _ => HashOffset::Bits43To32,
+        }
+    }
+}
+
+/// Computes the 12-bit hash table index of an address, the same way the hardware does.
+/// Bits 11:5 of the result select the table register, and bits 4:0 the bit in it.
+pub fn address_hash(address: &[u8; 6], offset: HashOffset) -> u16 {
+    let (low, high) = (address[4] as u16, address[5] as u16);
+    let hash = match offset {
+        HashOffset::Bits47To36 => (low >> 4) | (high << 4),
+        HashOffset::Bits46To35 => (low >> 3) | (high << 5),
+        HashOffset::Bits45To34 => (low >> 2) | (high << 6),
+        HashOffset::Bits43To32 => low | (high << 8),
+    };
+    hash & (HASH_TABLE_BITS as u16 - 1)
+}
+
+/// What to do with addresses that don't fit in the receive address entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Receive all unicast packets.
+    Promiscuous,
+    /// Receive packets whose address hashes to a bit set in the unicast table array.
+    /// Only available in virtualized mode, since the table is enabled per pool;
+    /// addresses that aren't associated with any pool fall back to promiscuous mode.
+    Hash,
+}
+
+/// Where an address was placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacFilterSlot {
+    /// The receive address entry at this index.
+    Entry(usize),
+    /// The overflow list, received through promiscuous mode.
+    Promiscuous,
+    /// The overflow list, received through the unicast hash table.
+    Hash,
+}
+
+/// An address and the bitmap of pools it's associated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacFilterEntry {
+    pub address: [u8; 6],
+    pub pools: u64,
+}
+
+/// The unicast address filters of the port.
+pub struct MacFilterTable {
+    entries: [Option<MacFilterEntry>; NUM_RECEIVE_ADDRESSES],
+    overflow: [Option<MacFilterEntry>; MAX_OVERFLOW_ADDRESSES],
+    overflow_mode: OverflowMode,
+    /// Unicast promiscuous mode was turned on for the overflow list.
+    promiscuous: bool,
+    /// The pools whose ROPE bit was set for the overflow list.
+    hash_pools: u64,
+}
+
+impl MacFilterTable {
+    /// Invalidates all entries other than the permanent address, and leaves promiscuous and hash mode.
+    pub fn new(rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, overflow_mode: OverflowMode) -> MacFilterTable {
+        for i in 1..NUM_RECEIVE_ADDRESSES {
+            Self::write_entry(mac_regs, i, None);
+        }
+        let mut table = MacFilterTable {
+            entries: [None; NUM_RECEIVE_ADDRESSES],
+            overflow: [None; MAX_OVERFLOW_ADDRESSES],
+            overflow_mode,
+            promiscuous: false,
+            hash_pools: 0,
+        };
+        table.entries[0] = Some(MacFilterEntry { address: Self::read_address(mac_regs, 0), pools: Self::read_pools(mac_regs, 0) });
+        table.update_overflow(rx_regs2, mac_regs);
+        table
+    }
+
+    /// Adds an address for the given pools, or adds the pools to an address that's already present.
+    pub fn add(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, address: [u8; 6], pools: u64) -> Result<MacFilterSlot, &'static str> {
+        if let Some(index) = self.find_entry(&address) {
+            let entry = self.entries[index].as_mut().unwrap();
+            entry.pools |= pools;
+            Self::write_entry(mac_regs, index, Some(*entry));
+            return Ok(MacFilterSlot::Entry(index));
+        }
+        if let Some(i) = self.find_overflow(&address) {
+            self.overflow[i].as_mut().unwrap().pools |= pools;
+            let slot = self.overflow_slot(self.overflow[i].as_ref().unwrap());
+            self.update_overflow(rx_regs2, mac_regs);
+            return Ok(slot);
+        }
+
+        let entry = MacFilterEntry { address, pools };
+        if let Some(index) = self.entries.iter().position(|e| e.is_none()) {
+            self.entries[index] = Some(entry);
+            Self::write_entry(mac_regs, index, Some(entry));
+            return Ok(MacFilterSlot::Entry(index));
+        }
+        let slot = self.overflow.iter_mut().find(|e| e.is_none()).ok_or("MAC filter table and overflow list are full")?;
+        *slot = Some(entry);
+        self.update_overflow(rx_regs2, mac_regs);
+        Ok(self.overflow_slot(&entry))
+    }
+
+    /// Removes an address. If it held an entry, the first overflowed address takes its place.
+    pub fn remove(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, address: &[u8; 6]) -> Result<(), &'static str> {
+        if let Some(index) = self.find_entry(address) {
+            if index == 0 {
+                return Err("the permanent address can't be removed");
+            }
+            self.free_entry(mac_regs, index);
+        } else {
+            let i = self.find_overflow(address).ok_or("address isn't in the MAC filter table")?;
+            self.overflow[i] = None;
+        }
+        self.update_overflow(rx_regs2, mac_regs);
+        Ok(())
+    }
+
+    /// Replaces the pools an address is associated with.
+    pub fn set_pools(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, address: &[u8; 6], pools: u64) -> Result<(), &'static str> {
+        if let Some(index) = self.find_entry(address) {
+            let entry = self.entries[index].as_mut().unwrap();
+            entry.pools = pools;
+            Self::write_entry(mac_regs, index, Some(*entry));
+        } else {
+            let i = self.find_overflow(address).ok_or("address isn't in the MAC filter table")?;
+            self.overflow[i].as_mut().unwrap().pools = pools;
+            self.update_overflow(rx_regs2, mac_regs);
+        }
+        Ok(())
+    }
+
+    /// Removes a pool from every address, and removes the addresses that were only associated with that pool.
+    pub fn remove_pool(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, pool: u8) -> Result<(), &'static str> {
+        let bit = 1u64.checked_shl(pool as u32).ok_or("pool is out of range")?;
+        // the overflow list goes first, so that the addresses promoted into freed entries no longer have the pool
+        for slot in self.overflow.iter_mut() {
+            if let Some(entry) = slot.as_mut().filter(|entry| entry.pools & bit != 0) {
+                entry.pools &= !bit;
+                if entry.pools == 0 {
+                    *slot = None;
+                }
+            }
+        }
+
+        for index in 1..NUM_RECEIVE_ADDRESSES {
+            let Some(mut entry) = self.entries[index].filter(|entry| entry.pools & bit != 0) else {
+                continue;
+            };
+            entry.pools &= !bit;
+            if entry.pools == 0 {
+                self.free_entry(mac_regs, index);
+            } else {
+                self.entries[index] = Some(entry);
+                Self::write_entry(mac_regs, index, Some(entry));
+            }
+        }
+        self.update_overflow(rx_regs2, mac_regs);
+        Ok(())
+    }
+
+    /// Returns where an address is placed, or `None` if it isn't in the table.
+    pub fn slot(&self, address: &[u8; 6]) -> Option<MacFilterSlot> {
+        self.find_entry(address).map(MacFilterSlot::Entry)
+            .or_else(|| self.find_overflow(address).map(|i| self.overflow_slot(self.overflow[i].as_ref().unwrap())))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &MacFilterEntry> {
+        self.entries.iter().chain(self.overflow.iter()).flatten()
+    }
+
+    pub fn num_overflowed(&self) -> usize {
+        self.overflow.iter().flatten().count()
+    }
+
+    /// Frees an entry, moving the first overflowed address into it.
+    fn free_entry(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, index: usize) {
+        let promoted = self.overflow.iter_mut().find_map(|e| e.take());
+        self.entries[index] = promoted;
+        Self::write_entry(mac_regs, index, promoted);
+    }
+
+    fn find_entry(&self, address: &[u8; 6]) -> Option<usize> {
+        self.entries.iter().position(|e| e.is_some_and(|e| e.address == *address))
+    }
+
+    fn find_overflow(&self, address: &[u8; 6]) -> Option<usize> {
+        self.overflow.iter().position(|e| e.is_some_and(|e| e.address == *address))
+    }
+
+    fn overflow_slot(&self, entry: &MacFilterEntry) -> MacFilterSlot {
+        if self.overflow_mode == OverflowMode::Hash && entry.pools != 0 {
+            MacFilterSlot::Hash
+        } else {
+            MacFilterSlot::Promiscuous
+        }
+    }
+
+    /// Rebuilds the unicast table array, the pools' ROPE bits and the promiscuous mode from the overflow list,
+    /// since hash bits and pools may be shared by several addresses.
+    /// FCTRL.UPE and the ROPE bits are only changed when the overflow list needs them changed,
+    /// so that promiscuous settings made elsewhere are kept.
+    fn update_overflow(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) {
+        let offset = HashOffset::from_bits(rx_regs2.multicast_control_register.read().get_bits(MCSTCTRL_MO));
+        let mut uta = [0u32; HASH_TABLE_BITS / 32];
+        let mut hash_pools = 0u64;
+        let mut promiscuous = false;
+        for entry in self.overflow.iter().flatten() {
+            if self.overflow_slot(entry) == MacFilterSlot::Hash {
+                let hash = address_hash(&entry.address, offset) as usize;
+                uta[hash >> 5].set_bit(hash & 0x1F, true);
+                hash_pools |= entry.pools;
+            } else {
+                promiscuous = true;
+            }
+        }
+
+        if self.overflow_mode == OverflowMode::Hash {
+            for (i, value) in uta.iter().enumerate() {
+                mac_regs.pf_unicast_table_array[i].write(*value);
+            }
+            let changed = hash_pools ^ self.hash_pools;
+            for (pool, l2ctl) in mac_regs.pf_vm_l2_control_register.iter_mut().enumerate() {
+                if changed.get_bit(pool) {
+                    l2ctl.update(|l2ctl| { l2ctl.set_bit(PFVML2FLT_ROPE, hash_pools.get_bit(pool)); });
+                }
+            }
+            self.hash_pools = hash_pools;
+        }
+        if promiscuous != self.promiscuous {
+            rx_regs2.filter_control_register.update(|fctrl| { fctrl.set_bit(FCTRL_UPE, promiscuous); });
+            self.promiscuous = promiscuous;
+        }
+    }
+
+    fn read_address(mac_regs: &IntelIxgbeMacRegisters, index: usize) -> [u8; 6] {
+        let receive_address = &mac_regs.receive_address[index];
+        let low = receive_address.receive_address_low.read().to_le_bytes();
+        let high = (receive_address.receive_address_high.read().get_bits(RAH_RAH) as u16).to_le_bytes();
+        [low[0], low[1], low[2], low[3], high[0], high[1]]
+    }
+
+    fn read_pools(mac_regs: &IntelIxgbeMacRegisters, index: usize) -> u64 {
+        let low = mac_regs.mac_pool_select_array[index * 2].read() as u64;
+        let high = mac_regs.mac_pool_select_array[index * 2 + 1].read() as u64;
+        (high << 32) | low
+    }
+
+    /// Writes an entry, or invalidates it. The address valid bit is cleared while the address is changed.
+    fn write_entry(mac_regs: &mut IntelIxgbeMacRegisters, index: usize, entry: Option<MacFilterEntry>) {
+        mac_regs.receive_address[index].receive_address_high.write(0);
+        let Some(entry) = entry else {
+            mac_regs.receive_address[index].receive_address_low.write(0);
+            mac_regs.mac_pool_select_array[index * 2].write(0);
+            mac_regs.mac_pool_select_array[index * 2 + 1].write(0);
+            return;
+        };
+        let address = entry.address;
+        mac_regs.receive_address[index].receive_address_low.write(u32::from_le_bytes([address[0], address[1], address[2], address[3]]));
+        mac_regs.mac_pool_select_array[index * 2].write(entry.pools as u32);
+        mac_regs.mac_pool_select_array[index * 2 + 1].write((entry.pools >> 32) as u32);
+        let mut rah = 0;
+        rah.set_bits(RAH_RAH, u16::from_le_bytes([address[4], address[5]]) as u32);
+        rah.set_bit(RAH_AV, true);
+        mac_regs.receive_address[index].receive_address_high.write(rah);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_hash_selects_the_configured_bits() {
+        let address = [0x01, 0x00, 0x5E, 0x00, 0x12, 0x34];
+        assert_eq!(address_hash(&address, HashOffset::Bits47To36), 0x341);
+        assert_eq!(address_hash(&address, HashOffset::Bits46To35), 0x682);
+        assert_eq!(address_hash(&address, HashOffset::Bits45To34), 0xD04);
+        assert_eq!(address_hash(&address, HashOffset::Bits43To32), 0x412);
+    }
+
+    #[test]
+    fn hash_offset_round_trips_through_mcstctrl_bits() {
+        for offset in [HashOffset::Bits47To36, HashOffset::Bits46To35, HashOffset::Bits45To34, HashOffset::Bits43To32] {
+            assert_eq!(HashOffset::from_bits(offset as u32), offset);
+        }
+    }
+
+    #[test]
+    fn mac_filter_registers_are_at_their_datasheet_offsets() {
+        use core::mem::{offset_of, size_of};
+        use crate::{RX_REGISTERS2_OFFSET, MAC_REGISTERS_OFFSET, ReceiveAddress};
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, filter_control_register), 0x5080);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, receive_address) + offset_of!(ReceiveAddress, receive_address_low), 0xA200);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, receive_address) + offset_of!(ReceiveAddress, receive_address_high), 0xA204);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, receive_address) + 127 * size_of::<ReceiveAddress>() + offset_of!(ReceiveAddress, receive_address_high), 0xA5FC);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, mac_pool_select_array), 0xA600);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, pf_unicast_table_array), 0xF400);
+    }
+}
@@ -0,0 +1,503 @@
+//! LinkSec (IEEE 802.1AE MACsec) offload.
+//!
+//! The 82599 supports one Tx secure channel and one Rx secure channel, each with two secure associations
+//! (SA0 and SA1) so that a new key can be installed in one SA while the other is still in use.
+//! The only supported cipher suite is GCM-AES-128.
+//!
+//! LinkSec uses the same security block as IPsec, so the two offloads can't be enabled at the same time.
+
+use bit_field::BitField;
+use crate::hal::RC;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::register_index::RegisterIndex;
+use crate::ipsec::{
+    ipsec_enabled, stop_data_paths, SECTXCTRL_SECTX_DIS, SECTXCTRL_TX_DIS, SECRXCTRL_SECRX_DIS, SECRXCTRL_RX_DIS,
+    SECTXMINIFG_MINSECIFG, SECTXMINIFG_SECURE_IFG, SECTXMINIFG_DEFAULT_IFG,
+};
+
+// LinkSec Tx Control Register
+const LSECTXCTRL_EN: core::ops::Range<usize> = 0..2;
+const LSECTXCTRL_AISCI: usize = 5;
+const LSECTXCTRL_PNTHRSH: core::ops::Range<usize> = 8..32;
+/// Default PN threshold: raise the key rotation interrupt 512 packets before the PN is exhausted.
+const LSECTXCTRL_PNTHRSH_DEFAULT: u32 = 0xFFFFFE00;
+
+// LinkSec Rx Control Register
+const LSECRXCTRL_EN: core::ops::Range<usize> = 2..4;
+const LSECRXCTRL_PLSH: usize = 6;
+const LSECRXCTRL_RP: usize = 7;
+
+// LinkSec Tx SA
+const LSECTXSA_AN0: core::ops::Range<usize> = 0..2;
+const LSECTXSA_AN1: core::ops::Range<usize> = 2..4;
+const LSECTXSA_SELSA: usize = 4;
+const LSECTXSA_ACTSA: usize = 5;
+
+// LinkSec Rx SA
+const LSECRXSA_AN: core::ops::Range<usize> = 0..2;
+const LSECRXSA_SAV: usize = 2;
+const LSECRXSA_FRR: usize = 3;
+const LSECRXSA_RETIRED: usize = 4;
+
+// MAC Core Control 0 Register
+const HLREG0_TXCRCEN: usize = 0;
+const HLREG0_RXCRCSTRP: usize = 1;
+
+/// Number of association numbers; an SA's AN is carried in the SecTAG of its packets.
+pub const MACSEC_NUM_AN: u8 = 4;
+
+/// One of the two secure associations of a secure channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacsecSa {
+    Sa0,
+    Sa1,
+}
+
+impl MacsecSa {
+    /// Returns the other SA, which is the one a new key is installed in.
+    pub fn other(self) -> MacsecSa {
+        match self {
+            MacsecSa::Sa0 => MacsecSa::Sa1,
+            MacsecSa::Sa1 => MacsecSa::Sa0,
+        }
+    }
+
+    fn register_index(self) -> RegisterIndex<2> {
+        match self {
+            MacsecSa::Sa0 => RegisterIndex::at::<0>(),
+            MacsecSa::Sa1 => RegisterIndex::at::<1>(),
+        }
+    }
+}
+
+/// How transmitted packets are protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MacsecTxMode {
+    /// Packets are sent without a SecTAG.
+    Disabled = 0,
+    /// Packets are authenticated but sent in the clear.
+    Authenticate = 1,
+    /// Packets are authenticated and encrypted.
+    AuthenticateEncrypt = 2,
+}
+
+/// How received packets are validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MacsecRxMode {
+    /// Packets are passed up without being checked.
+    Disabled = 0,
+    /// Packets are checked and counted, but packets that fail are still passed up.
+    Check = 1,
+    /// Packets that fail validation are dropped.
+    Strict = 2,
+    /// All packets with a SecTAG are dropped.
+    Drop = 3,
+}
+
+/// A secure channel identifier: the MAC address of the sender and a port number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sci {
+    pub mac: [u8; 6],
+    pub port: u16,
+}
+
+impl Sci {
+    /// Returns the values of the SCI low and high registers.
+    /// The address goes in the first 6 bytes in network order, followed by the port in network order.
+    fn registers(&self) -> (u32, u32) {
+        let port = self.port.to_be_bytes();
+        (
+            u32::from_le_bytes([self.mac[0], self.mac[1], self.mac[2], self.mac[3]]),
+            u32::from_le_bytes([self.mac[4], self.mac[5], port[0], port[1]]),
+        )
+    }
+}
+
+/// A GCM-AES-128 SA key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MacsecKey(pub [u8; 16]);
+
+impl MacsecKey {
+    /// Returns the value of key register `i`, which holds bytes `4 * i` to `4 * i + 3` of the key.
+    fn register(&self, i: usize) -> u32 {
+        u32::from_le_bytes(self.0[i * 4..i * 4 + 4].try_into().unwrap())
+    }
+}
+
+/// Checks that a key can be installed in Tx SA `sa`, given the values of LSECTXCTRL and LSECTXSA.
+fn check_tx_sa_install(lsectxctrl: u32, lsectxsa: u32, sa: MacsecSa, an: u8, next_pn: u32) -> Result<(), &'static str> {
+    if an >= MACSEC_NUM_AN {
+        return Err("MACsec association number is out of range");
+    }
+    if next_pn == 0 {
+        return Err("MACsec PN can't be 0");
+    }
+    let tx_enabled = lsectxctrl.get_bits(LSECTXCTRL_EN) != MacsecTxMode::Disabled as u32;
+    let active = lsectxsa.get_bit(LSECTXSA_ACTSA) == (sa == MacsecSa::Sa1);
+    if tx_enabled && active {
+        return Err("can't change the key of the active MACsec Tx SA while Tx LinkSec is enabled");
+    }
+    Ok(())
+}
+
+// Don't print the key.
+impl core::fmt::Debug for MacsecKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MacsecKey").finish_non_exhaustive()
+    }
+}
+
+/// The state of an Rx SA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxSaStatus {
+    pub an: u8,
+    pub valid: bool,
+    /// A packet has been received on this SA since it was installed.
+    pub frame_received: bool,
+    /// The SA has been replaced by the other SA, and can be reused.
+    pub retired: bool,
+}
+
+/// LinkSec statistics as 64-bit totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MacsecStats {
+    pub tx_untagged_packets: u64,
+    pub tx_encrypted_packets: u64,
+    pub tx_protected_packets: u64,
+    pub tx_encrypted_octets: u64,
+    pub tx_protected_octets: u64,
+    pub rx_untagged_packets: u64,
+    pub rx_decrypted_octets: u64,
+    pub rx_validated_octets: u64,
+    pub rx_bad_tag_packets: u64,
+    pub rx_no_sci_packets: u64,
+    pub rx_unknown_sci_packets: u64,
+    pub rx_unchecked_packets: u64,
+    pub rx_late_packets: u64,
+    /// Packets that were validated successfully, per SA.
+    pub rx_ok_packets: [u64; 2],
+    /// Packets that failed validation and were passed up in check mode, per SA.
+    pub rx_invalid_packets: [u64; 2],
+    /// Packets that failed validation and were dropped, per SA.
+    pub rx_not_valid_packets: [u64; 2],
+    pub rx_unused_sa_packets: u64,
+    pub rx_not_using_sa_packets: u64,
+}
+
+impl MacsecStats {
+    /// Adds the counts since the last read to the totals. The LinkSec counters are cleared when read.
+    fn accumulate(&mut self, mac_regs: &IntelIxgbeMacRegisters) {
+        fn add(total: &mut u64, counter: &RC<u32>) {
+            *total += counter.read() as u64;
+        }
+        add(&mut self.tx_untagged_packets, &mac_regs.tx_untagged_packet_counter);
+        add(&mut self.tx_encrypted_packets, &mac_regs.encrypted_tx_packets);
+        add(&mut self.tx_protected_packets, &mac_regs.protected_tx_packets);
+        add(&mut self.tx_encrypted_octets, &mac_regs.encrypted_tx_octets);
+        add(&mut self.tx_protected_octets, &mac_regs.protected_tx_octets);
+        add(&mut self.rx_untagged_packets, &mac_regs.linksec_untagged_rx_packet);
+        add(&mut self.rx_decrypted_octets, &mac_regs.linksec_rx_octets_decrypted);
+        add(&mut self.rx_validated_octets, &mac_regs.linksec_rx_octets_validated);
+        add(&mut self.rx_bad_tag_packets, &mac_regs.linksec_rx_packet_with_bad_tag);
+        add(&mut self.rx_no_sci_packets, &mac_regs.linksec_no_sci);
+        add(&mut self.rx_unknown_sci_packets, &mac_regs.linksec_unknown_sci);
+        add(&mut self.rx_unchecked_packets, &mac_regs.linksec_rx_unchecked_packets);
+        add(&mut self.rx_late_packets, &mac_regs.linksec_rx_late_packets);
+        for sa in 0..2 {
+            add(&mut self.rx_ok_packets[sa], &mac_regs.linksec_rx_packet_ok[sa]);
+            add(&mut self.rx_invalid_packets[sa], &mac_regs.linksec_rx_invalid[sa]);
+            add(&mut self.rx_not_valid_packets[sa], &mac_regs.linksec_rx_not_valid[sa]);
+        }
+        add(&mut self.rx_unused_sa_packets, &mac_regs.linksec_rx_unused_sa);
+        add(&mut self.rx_not_using_sa_packets, &mac_regs.linksec_rx_not_using_sa);
+    }
+}
+
+/// Returns the lowest PN that is accepted on an Rx SA when `next_pn` is the next PN expected on it.
+fn lowest_pn(next_pn: u32, replay_window: Option<u32>) -> u32 {
+    next_pn.saturating_sub(replay_window.unwrap_or(0)).max(1)
+}
+
+/// The LinkSec offload engine, with its replay window and statistics totals.
+pub struct MacsecOffload {
+    replay_window: Option<u32>,
+    /// The next PN expected on each valid Rx SA, which its lowest acceptable PN was computed from.
+    rx_next_pn: [Option<u32>; 2],
+    stats: MacsecStats,
+}
+
+impl Default for MacsecOffload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MacsecOffload {
+    pub fn new() -> MacsecOffload {
+        MacsecOffload { replay_window: None, rx_next_pn: [None; 2], stats: MacsecStats::default() }
+    }
+
+    /// Enables the security block for LinkSec with the given Tx and Rx modes.
+    /// The data paths are drained first, and the Tx and Rx SAs should already be installed,
+    /// since traffic resumes right away. Fails if IPsec is using the security block.
+    pub fn enable(
+        &mut self,
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        tx_mode: MacsecTxMode,
+        rx_mode: MacsecRxMode,
+    ) -> Result<(), &'static str> {
+        if ipsec_enabled(mac_regs) {
+            return Err("the security block is in use by IPsec");
+        }
+        stop_data_paths(rx_regs2, mac_regs)?;
+
+        // the CRC has to be added and stripped by the MAC, since it covers the SecTAG and ICV
+        rx_regs2.mac_core_control_0_register.update(|hlreg0| {
+            hlreg0.set_bit(HLREG0_TXCRCEN, true);
+            hlreg0.set_bit(HLREG0_RXCRCSTRP, true);
+        });
+        mac_regs.security_tx_minimum_ifg.update(|minifg| { minifg.set_bits(SECTXMINIFG_MINSECIFG, SECTXMINIFG_SECURE_IFG); });
+
+        mac_regs.linksec_tx_control_register.update(|ctrl| {
+            ctrl.set_bits(LSECTXCTRL_EN, tx_mode as u32);
+            ctrl.set_bit(LSECTXCTRL_AISCI, true);
+            ctrl.set_bits(LSECTXCTRL_PNTHRSH, LSECTXCTRL_PNTHRSH_DEFAULT >> 8);
+        });
+        let replay_protect = self.replay_window.is_some();
+        mac_regs.linksec_rx_control_register.update(|ctrl| {
+            ctrl.set_bits(LSECRXCTRL_EN, rx_mode as u32);
+            ctrl.set_bit(LSECRXCTRL_PLSH, false);
+            ctrl.set_bit(LSECRXCTRL_RP, replay_protect);
+        });
+
+        // restart the data paths with the security block enabled
+        mac_regs.security_tx_control.update(|ctrl| {
+            ctrl.set_bit(SECTXCTRL_SECTX_DIS, false);
+            ctrl.set_bit(SECTXCTRL_TX_DIS, false);
+        });
+        mac_regs.security_rx_control.update(|ctrl| {
+            ctrl.set_bit(SECRXCTRL_SECRX_DIS, false);
+            ctrl.set_bit(SECRXCTRL_RX_DIS, false);
+        });
+        Ok(())
+    }
+
+    /// Disables LinkSec and bypasses the security block.
+    pub fn disable(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        let result = stop_data_paths(rx_regs2, mac_regs);
+        mac_regs.linksec_tx_control_register.update(|ctrl| { ctrl.set_bits(LSECTXCTRL_EN, MacsecTxMode::Disabled as u32); });
+        mac_regs.linksec_rx_control_register.update(|ctrl| { ctrl.set_bits(LSECRXCTRL_EN, MacsecRxMode::Disabled as u32); });
+        mac_regs.security_tx_minimum_ifg.update(|minifg| { minifg.set_bits(SECTXMINIFG_MINSECIFG, SECTXMINIFG_DEFAULT_IFG); });
+        // the block is bypassed even if it didn't drain, so that traffic isn't left stopped
+        let mut sectxctrl = 0;
+        sectxctrl.set_bit(SECTXCTRL_SECTX_DIS, true);
+        let mut secrxctrl = 0;
+        secrxctrl.set_bit(SECRXCTRL_SECRX_DIS, true);
+        mac_regs.security_tx_control.write(sectxctrl);
+        mac_regs.security_rx_control.write(secrxctrl);
+        result
+    }
+
+    /// Sets the SCI that is sent in the SecTAG of transmitted packets.
+    pub fn set_tx_sc(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sci: Sci) {
+        let (low, high) = sci.registers();
+        mac_regs.linksec_tx_sci_low.write(low);
+        mac_regs.linksec_tx_sci_high.write(high);
+    }
+
+    /// Sets the SCI of the peer that packets are received from.
+    pub fn set_rx_sc(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sci: Sci) {
+        let (low, high) = sci.registers();
+        mac_regs.linksec_rx_sci_low.write(low);
+        mac_regs.linksec_rx_sci_high.write(high);
+    }
+
+    /// Sets the PN at which the hardware raises the interrupt asking for a new Tx key.
+    /// Only bits 31:8 of the threshold are compared.
+    pub fn set_tx_pn_threshold(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, threshold: u32) {
+        mac_regs.linksec_tx_control_register.update(|ctrl| { ctrl.set_bits(LSECTXCTRL_PNTHRSH, threshold >> 8); });
+    }
+
+    /// Installs a key in a Tx SA, with the PN of the first packet that will be sent on it.
+    /// The SA isn't used until it's selected with [`MacsecOffload::select_tx_sa`].
+    ///
+    /// While Tx LinkSec is enabled, only the inactive SA can be installed. Before it's enabled,
+    /// the active SA can be installed too, which is how the first key goes in SA0, the active SA after reset.
+    pub fn install_tx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: MacsecSa, an: u8, next_pn: u32, key: &MacsecKey) -> Result<(), &'static str> {
+        check_tx_sa_install(mac_regs.linksec_tx_control_register.read(), mac_regs.linksec_tx_sa.read(), sa, an, next_pn)?;
+        let (pn_reg, key_regs) = match sa {
+            MacsecSa::Sa0 => (&mut mac_regs.linksec_tx_sa_pn_0, &mut mac_regs.linksec_tx_key_0),
+            MacsecSa::Sa1 => (&mut mac_regs.linksec_tx_sa_pn_1, &mut mac_regs.linksec_tx_key_1),
+        };
+        // the PN registers hold the PN in network byte order
+        pn_reg.write(next_pn.to_be());
+        for (i, key_reg) in key_regs.iter_mut().enumerate() {
+            key_reg.write(key.register(i));
+        }
+        mac_regs.linksec_tx_sa.update(|txsa| {
+            match sa {
+                MacsecSa::Sa0 => txsa.set_bits(LSECTXSA_AN0, an as u32),
+                MacsecSa::Sa1 => txsa.set_bits(LSECTXSA_AN1, an as u32),
+            };
+        });
+        Ok(())
+    }
+
+    /// Switches transmission to `sa`. The hardware switches at the next packet boundary.
+    pub fn select_tx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: MacsecSa) {
+        mac_regs.linksec_tx_sa.update(|txsa| { txsa.set_bit(LSECTXSA_SELSA, sa == MacsecSa::Sa1); });
+    }
+
+    /// Returns the SA that packets are currently sent on.
+    pub fn active_tx_sa(&self, mac_regs: &IntelIxgbeMacRegisters) -> MacsecSa {
+        if mac_regs.linksec_tx_sa.read().get_bit(LSECTXSA_ACTSA) { MacsecSa::Sa1 } else { MacsecSa::Sa0 }
+    }
+
+    /// Installs a new Tx key in the inactive SA, starting at PN 1, and switches to it.
+    /// Returns the SA the key was installed in.
+    pub fn rotate_tx_key(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, an: u8, key: &MacsecKey) -> Result<MacsecSa, &'static str> {
+        let sa = self.active_tx_sa(mac_regs).other();
+        self.install_tx_sa(mac_regs, sa, an, 1, key)?;
+        self.select_tx_sa(mac_regs, sa);
+        Ok(sa)
+    }
+
+    /// Returns the PN of the next packet that will be sent on `sa`.
+    pub fn tx_next_pn(&self, mac_regs: &IntelIxgbeMacRegisters, sa: MacsecSa) -> u32 {
+        let pn = match sa {
+            MacsecSa::Sa0 => mac_regs.linksec_tx_sa_pn_0.read(),
+            MacsecSa::Sa1 => mac_regs.linksec_tx_sa_pn_1.read(),
+        };
+        u32::from_be(pn)
+    }
+
+    /// Enables replay protection with the given window, or disables it if `window` is `None`.
+    ///
+    /// The hardware drops packets whose PN is below the lowest acceptable PN of their SA. That PN is set
+    /// to `window` below the next expected PN when an SA is installed, and should be moved forward
+    /// with [`MacsecOffload::update_rx_lowest_pn`] as packets are received.
+    /// The lowest acceptable PNs of the valid SAs are moved to the new window right away.
+    pub fn set_replay_window(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, window: Option<u32>) {
+        self.replay_window = window;
+        mac_regs.linksec_rx_control_register.update(|ctrl| { ctrl.set_bit(LSECRXCTRL_RP, window.is_some()); });
+        for sa in [MacsecSa::Sa0, MacsecSa::Sa1] {
+            if let Some(next_pn) = self.rx_next_pn[sa as usize] {
+                self.update_rx_lowest_pn(mac_regs, sa, next_pn);
+            }
+        }
+    }
+
+    /// Installs a key in an Rx SA and marks it valid. `next_pn` is the first PN expected on it.
+    /// Packets keep being accepted on the other SA until it's invalidated or retired.
+    pub fn install_rx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: MacsecSa, an: u8, next_pn: u32, key: &MacsecKey) -> Result<(), &'static str> {
+        if an >= MACSEC_NUM_AN {
+            return Err("MACsec association number is out of range");
+        }
+        let other = mac_regs.linksec_rx_sa[sa.other() as usize].read();
+        if other.get_bit(LSECRXSA_SAV) && other.get_bits(LSECRXSA_AN) == an as u32 {
+            return Err("the other MACsec Rx SA already uses this association number");
+        }
+        let index = sa as usize;
+        // invalidate the SA while its key is being changed
+        mac_regs.linksec_rx_sa[index].write(0);
+        self.update_rx_lowest_pn(mac_regs, sa, next_pn);
+        for word in RegisterIndex::<4>::all() {
+            mac_regs.lsecrxkey(sa.register_index(), word).write(key.register(word.get()));
+        }
+        let mut rxsa = 0;
+        rxsa.set_bits(LSECRXSA_AN, an as u32);
+        rxsa.set_bit(LSECRXSA_SAV, true);
+        mac_regs.linksec_rx_sa[index].write(rxsa);
+        Ok(())
+    }
+
+    /// Stops accepting packets on an Rx SA.
+    pub fn invalidate_rx_sa(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: MacsecSa) {
+        mac_regs.linksec_rx_sa[sa as usize].update(|rxsa| { rxsa.set_bit(LSECRXSA_SAV, false); });
+        self.rx_next_pn[sa as usize] = None;
+    }
+
+    /// Moves the lowest acceptable PN of an Rx SA to the replay window below `next_pn`.
+    pub fn update_rx_lowest_pn(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, sa: MacsecSa, next_pn: u32) {
+        mac_regs.linksec_rx_sa_pn[sa as usize].write(lowest_pn(next_pn, self.replay_window).to_be());
+        self.rx_next_pn[sa as usize] = Some(next_pn);
+    }
+
+    pub fn rx_sa_status(&self, mac_regs: &IntelIxgbeMacRegisters, sa: MacsecSa) -> RxSaStatus {
+        let rxsa = mac_regs.linksec_rx_sa[sa as usize].read();
+        RxSaStatus {
+            an: rxsa.get_bits(LSECRXSA_AN) as u8,
+            valid: rxsa.get_bit(LSECRXSA_SAV),
+            frame_received: rxsa.get_bit(LSECRXSA_FRR),
+            retired: rxsa.get_bit(LSECRXSA_RETIRED),
+        }
+    }
+
+    /// Reads the LinkSec counters and returns the updated totals.
+    pub fn stats(&mut self, mac_regs: &IntelIxgbeMacRegisters) -> &MacsecStats {
+        self.stats.accumulate(mac_regs);
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_key_goes_in_the_active_sa_before_tx_is_enabled() {
+        // after reset, LinkSec is disabled and SA0 is active
+        assert!(check_tx_sa_install(0, 0, MacsecSa::Sa0, 0, 1).is_ok());
+
+        let mut lsectxctrl = 0;
+        lsectxctrl.set_bits(LSECTXCTRL_EN, MacsecTxMode::AuthenticateEncrypt as u32);
+        assert!(check_tx_sa_install(lsectxctrl, 0, MacsecSa::Sa0, 0, 1).is_err());
+        assert!(check_tx_sa_install(lsectxctrl, 0, MacsecSa::Sa1, 1, 1).is_ok());
+        let mut lsectxsa = 0;
+        lsectxsa.set_bit(LSECTXSA_ACTSA, true);
+        assert!(check_tx_sa_install(lsectxctrl, lsectxsa, MacsecSa::Sa1, 1, 1).is_err());
+        assert!(check_tx_sa_install(lsectxctrl, lsectxsa, MacsecSa::Sa0, 2, 1).is_ok());
+    }
+
+    #[test]
+    fn invalid_sa_parameters_are_rejected() {
+        assert!(check_tx_sa_install(0, 0, MacsecSa::Sa1, MACSEC_NUM_AN, 1).is_err());
+        assert!(check_tx_sa_install(0, 0, MacsecSa::Sa1, 0, 0).is_err());
+    }
+
+    #[test]
+    fn sci_registers_hold_the_address_and_port_in_network_order() {
+        let sci = Sci { mac: [0x00, 0x1B, 0x21, 0x3C, 0x4D, 0x5E], port: 1 };
+        assert_eq!(sci.registers(), (0x3C21_1B00, 0x0100_5E4D));
+    }
+
+    #[test]
+    fn lowest_pn_follows_the_replay_window() {
+        assert_eq!(lowest_pn(100, None), 100);
+        assert_eq!(lowest_pn(100, Some(32)), 68);
+        assert_eq!(lowest_pn(100, Some(0)), 100);
+        // PN 0 is never valid
+        assert_eq!(lowest_pn(10, Some(32)), 1);
+    }
+
+    #[test]
+    fn linksec_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::MAC_REGISTERS_OFFSET;
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, linksec_tx_control_register), 0x8A04);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, linksec_tx_sa), 0x8A10);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, linksec_rx_control_register), 0x8F04);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, linksec_rx_sa), 0x8F10);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, linksec_rx_sa_pn), 0x8F18);
+    }
+
+    #[test]
+    fn key_registers() {
+        let key = MacsecKey(core::array::from_fn(|i| i as u8));
+        assert_eq!(key.register(0), 0x0302_0100);
+        assert_eq!(key.register(3), 0x0F0E_0D0C);
+    }
+}
@@ -0,0 +1,691 @@
+//! The PF side of the PF↔VF mailbox and the ixgbe VF message set.
+//!
+//! Each VF has a 16-word mailbox memory (PFMBMEM) shared with the PF, and the PFMAILBOX register that
+//! arbitrates it. A VF posts a message by writing the memory and raising VFREQ in PFMBICR; the PF locks the memory,
+//! reads the message, acknowledges it with PFMAILBOX.ACK, and then writes its reply and raises PFMAILBOX.STS.
+//!
+//! Word 0 of every message holds the message type, an 8-bit info field, and the ACK/NACK and clear-to-send (CTS)
+//! flags of replies. Until a VF has reset through the mailbox, every other message is NACKed without CTS.
+//!
+//! [`PfMailbox`] is a state machine over the [`MailboxTransport`] trait: it frames and decodes messages, tracks each VF's
+//! reset state and negotiated API version, and leaves every decision to a [`VfPolicy`]. The transport is implemented for
+//! the device by [`IxgbeMailbox`] and for tests by [`SimulatedMailbox`], which also plays the VF's side.
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{Volatile, IntelIxgbeRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::mac_filter::HashOffset;
+use crate::register_index::RegisterIndex;
+
+/// Number of 32-bit words in a mailbox.
+pub const MAILBOX_SIZE: usize = 16;
+/// Number of VFs that have a mailbox.
+pub const MAX_VFS: usize = 64;
+/// Number of multicast hashes a VF can send in one message. Longer lists are truncated to this.
+pub const MAX_VF_MULTICAST_ENTRIES: usize = 30;
+
+/// Number of times the PF tries to lock a mailbox the VF is holding.
+const MAILBOX_LOCK_ATTEMPTS: u32 = 1000;
+
+// Message header
+const MSG_TYPE: Range<usize> = 0..16;
+const MSG_INFO: Range<usize> = 16..24;
+const MSG_CTS: usize = 29;
+const MSG_NACK: usize = 30;
+const MSG_ACK: usize = 31;
+
+// Message types
+const VF_RESET: u32 = 0x01;
+const VF_SET_MAC_ADDR: u32 = 0x02;
+const VF_SET_MULTICAST: u32 = 0x03;
+const VF_SET_VLAN: u32 = 0x04;
+const VF_SET_LPE: u32 = 0x05;
+const VF_API_NEGOTIATE: u32 = 0x08;
+const VF_GET_QUEUES: u32 = 0x09;
+
+// PF Mailbox
+const PFMAILBOX_STS: usize = 0;
+const PFMAILBOX_ACK: usize = 1;
+const PFMAILBOX_PFU: usize = 3;
+
+// PF Mailbox Interrupt Causes, with 16 VFs per register
+const PFMBICR_VFREQ: usize = 0;
+const PFMBICR_VFACK: usize = 16;
+
+/// Versions of the mailbox API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MailboxApi {
+    /// The API every VF starts with after a reset.
+    V10,
+    /// Adds the queue configuration query.
+    V11,
+}
+
+impl MailboxApi {
+    fn from_wire(value: u32) -> Option<MailboxApi> {
+        match value {
+            0 => Some(MailboxApi::V10),
+            2 => Some(MailboxApi::V11),
+            _ => None,
+        }
+    }
+
+    fn to_wire(self) -> u32 {
+        match self {
+            MailboxApi::V10 => 0,
+            MailboxApi::V11 => 2,
+        }
+    }
+}
+
+/// A message sent by a VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfMessage {
+    Reset,
+    SetMacAddress([u8; 6]),
+    /// Multicast table array hashes of the groups the VF joined, of which the first `count` are valid.
+    SetMulticast { hashes: [u16; MAX_VF_MULTICAST_ENTRIES], count: usize },
+    SetVlan { vlan: u16, add: bool },
+    /// Enables long packets up to the given frame size.
+    SetLpe { max_frame_size: u32 },
+    ApiNegotiate(MailboxApi),
+    GetQueues,
+}
+
+impl VfMessage {
+    pub fn decode(msg: &[u32; MAILBOX_SIZE]) -> Result<VfMessage, &'static str> {
+        let info = msg[0].get_bits(MSG_INFO);
+        match msg[0].get_bits(MSG_TYPE) {
+            VF_RESET => Ok(VfMessage::Reset),
+            VF_SET_MAC_ADDR => Ok(VfMessage::SetMacAddress(words_to_address(msg[1], msg[2]))),
+            VF_SET_MULTICAST => {
+                // the hashes that don't fit in the message are dropped, as the ixgbe PF does
+                let count = (info as usize).min(MAX_VF_MULTICAST_ENTRIES);
+                let mut hashes = [0u16; MAX_VF_MULTICAST_ENTRIES];
+                for (i, hash) in hashes.iter_mut().enumerate().take(count) {
+                    // two hashes per word, after the header
+                    *hash = (msg[1 + i / 2] >> (16 * (i % 2))) as u16;
+                }
+                Ok(VfMessage::SetMulticast { hashes, count })
+            }
+            VF_SET_VLAN => Ok(VfMessage::SetVlan { vlan: (msg[1] & 0xFFF) as u16, add: info != 0 }),
+            VF_SET_LPE => Ok(VfMessage::SetLpe { max_frame_size: msg[1] }),
+            VF_API_NEGOTIATE => MailboxApi::from_wire(msg[1]).map(VfMessage::ApiNegotiate).ok_or("unsupported mailbox API version"),
+            VF_GET_QUEUES => Ok(VfMessage::GetQueues),
+            _ => Err("unknown VF message type"),
+        }
+    }
+
+    pub fn encode(&self) -> [u32; MAILBOX_SIZE] {
+        let mut msg = [0u32; MAILBOX_SIZE];
+        let (msg_type, info) = match *self {
+            VfMessage::Reset => (VF_RESET, 0),
+            VfMessage::SetMacAddress(address) => {
+                (msg[1], msg[2]) = address_to_words(&address);
+                (VF_SET_MAC_ADDR, 0)
+            }
+            VfMessage::SetMulticast { hashes, count } => {
+                let count = count.min(MAX_VF_MULTICAST_ENTRIES);
+                for (i, hash) in hashes.iter().enumerate().take(count) {
+                    msg[1 + i / 2] |= (*hash as u32) << (16 * (i % 2));
+                }
+                (VF_SET_MULTICAST, count as u32)
+            }
+            VfMessage::SetVlan { vlan, add } => {
+                msg[1] = vlan as u32;
+                (VF_SET_VLAN, add as u32)
+            }
+            VfMessage::SetLpe { max_frame_size } => {
+                msg[1] = max_frame_size;
+                (VF_SET_LPE, 0)
+            }
+            VfMessage::ApiNegotiate(api) => {
+                msg[1] = api.to_wire();
+                (VF_API_NEGOTIATE, 0)
+            }
+            VfMessage::GetQueues => (VF_GET_QUEUES, 0),
+        };
+        msg[0].set_bits(MSG_TYPE, msg_type);
+        msg[0].set_bits(MSG_INFO, info);
+        msg
+    }
+}
+
+/// Packs a MAC address into two message words, the way the VF driver lays out its byte buffer.
+fn address_to_words(address: &[u8; 6]) -> (u32, u32) {
+    (
+        u32::from_le_bytes([address[0], address[1], address[2], address[3]]),
+        u32::from_le_bytes([address[4], address[5], 0, 0]),
+    )
+}
+
+fn words_to_address(low: u32, high: u32) -> [u8; 6] {
+    let (low, high) = (low.to_le_bytes(), high.to_le_bytes());
+    [low[0], low[1], low[2], low[3], high[0], high[1]]
+}
+
+/// A PF reply, as seen by the VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PfReply {
+    pub msg_type: u32,
+    pub ack: bool,
+    pub clear_to_send: bool,
+    pub words: [u32; MAILBOX_SIZE],
+}
+
+impl PfReply {
+    pub fn decode(msg: &[u32; MAILBOX_SIZE]) -> PfReply {
+        PfReply {
+            msg_type: msg[0].get_bits(MSG_TYPE),
+            ack: msg[0].get_bit(MSG_ACK),
+            clear_to_send: msg[0].get_bit(MSG_CTS),
+            words: *msg,
+        }
+    }
+
+    /// The MAC address in the reply to a reset.
+    pub fn mac_address(&self) -> [u8; 6] {
+        words_to_address(self.words[1], self.words[2])
+    }
+}
+
+/// The queue configuration reported to a VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VfQueues {
+    pub tx_queues: u8,
+    pub rx_queues: u8,
+    /// The PF inserts and strips a port VLAN, so the VF shouldn't tag its packets.
+    pub transparent_vlan: bool,
+    pub default_queue: u8,
+}
+
+/// The PF's decisions on VF requests. Requests that return `false` are NACKed.
+pub trait VfPolicy {
+    /// The VF reset; returns the MAC address it's assigned.
+    fn reset(&mut self, vf: u8) -> [u8; 6];
+    fn set_mac_address(&mut self, vf: u8, address: [u8; 6]) -> bool;
+    fn set_multicast(&mut self, vf: u8, hashes: &[u16]) -> bool;
+    fn set_vlan(&mut self, vf: u8, vlan: u16, add: bool) -> bool;
+    fn set_lpe(&mut self, vf: u8, max_frame_size: u32) -> bool;
+    fn queues(&mut self, vf: u8) -> VfQueues;
+}
+
+/// Access to the mailboxes from the PF's side. Every method fails if `vf` doesn't have a mailbox.
+pub trait MailboxTransport {
+    /// Returns whether the VF has posted a message, and clears the indication.
+    fn take_request(&mut self, vf: u8) -> Result<bool, &'static str>;
+    /// Returns whether the VF has acknowledged the PF's last message, and clears the indication.
+    fn take_ack(&mut self, vf: u8) -> Result<bool, &'static str>;
+    /// Takes ownership of the mailbox memory. Returns false if the VF holds it.
+    fn lock(&mut self, vf: u8) -> Result<bool, &'static str>;
+    /// Reads the mailbox memory, which must be locked.
+    fn read(&mut self, vf: u8, msg: &mut [u32; MAILBOX_SIZE]) -> Result<(), &'static str>;
+    /// Writes the mailbox memory, which must be locked.
+    fn write(&mut self, vf: u8, msg: &[u32; MAILBOX_SIZE]) -> Result<(), &'static str>;
+    /// Acknowledges the message read from the VF, and releases the mailbox memory.
+    fn ack(&mut self, vf: u8) -> Result<(), &'static str>;
+    /// Tells the VF that a message was written, and releases the mailbox memory.
+    fn signal(&mut self, vf: u8) -> Result<(), &'static str>;
+}
+
+fn vf_index(vf: u8) -> Result<RegisterIndex<MAX_VFS>, &'static str> {
+    RegisterIndex::new(vf as usize).ok_or("VF is out of range")
+}
+
+/// The mailboxes of the device.
+pub struct IxgbeMailbox<'a> {
+    regs1: &'a mut IntelIxgbeRegisters1,
+    rx_regs2: &'a mut IntelIxgbeRxRegisters2,
+    mac_regs: &'a mut IntelIxgbeMacRegisters,
+}
+
+impl<'a> IxgbeMailbox<'a> {
+    pub fn new(regs1: &'a mut IntelIxgbeRegisters1, rx_regs2: &'a mut IntelIxgbeRxRegisters2, mac_regs: &'a mut IntelIxgbeMacRegisters) -> IxgbeMailbox<'a> {
+        IxgbeMailbox { regs1, rx_regs2, mac_regs }
+    }
+
+    /// Unmasks or masks the mailbox interrupt of a VF.
+    pub fn set_interrupt_enable(&mut self, vf: u8, enable: bool) -> Result<(), &'static str> {
+        let vf = vf_index(vf)?.get();
+        self.regs1.pf_mailbox_interrupt_mask_registertarget[vf / 32].update(|pfmbimr| { pfmbimr.set_bit(vf % 32, enable); });
+        Ok(())
+    }
+
+    /// Clears a cause bit of PFMBICR, which is write 1 to clear, and returns whether it was set.
+    fn take_cause(&mut self, vf: u8, cause: usize) -> Result<bool, &'static str> {
+        let vf = vf_index(vf)?.get();
+        let (index, bit) = (vf / 16, cause + vf % 16);
+        let pfmbicr = &mut self.regs1.pf_mailbox_interrupt_causes_registertarget[index];
+        if !pfmbicr.read().get_bit(bit) {
+            return Ok(false);
+        }
+        pfmbicr.write(1 << bit);
+        Ok(true)
+    }
+
+    fn pf_mailbox(&mut self, vf: u8) -> Result<&mut Volatile<u32>, &'static str> {
+        Ok(&mut self.rx_regs2.pf_mailbox_target[vf_index(vf)?.get()])
+    }
+}
+
+impl MailboxTransport for IxgbeMailbox<'_> {
+    fn take_request(&mut self, vf: u8) -> Result<bool, &'static str> {
+        self.take_cause(vf, PFMBICR_VFREQ)
+    }
+
+    fn take_ack(&mut self, vf: u8) -> Result<bool, &'static str> {
+        self.take_cause(vf, PFMBICR_VFACK)
+    }
+
+    fn lock(&mut self, vf: u8) -> Result<bool, &'static str> {
+        let pfmailbox = self.pf_mailbox(vf)?;
+        pfmailbox.write(1 << PFMAILBOX_PFU);
+        // the hardware only grants PFU when the VF doesn't own the memory
+        Ok(pfmailbox.read().get_bit(PFMAILBOX_PFU))
+    }
+
+    fn read(&mut self, vf: u8, msg: &mut [u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        let vf = vf_index(vf)?;
+        for (word, value) in RegisterIndex::all().zip(msg.iter_mut()) {
+            *value = self.mac_regs.pfmbmem(vf, word).read();
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, vf: u8, msg: &[u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        let vf = vf_index(vf)?;
+        for (word, value) in RegisterIndex::all().zip(msg.iter()) {
+            self.mac_regs.pfmbmem(vf, word).write(*value);
+        }
+        Ok(())
+    }
+
+    fn ack(&mut self, vf: u8) -> Result<(), &'static str> {
+        // writing without PFU releases the memory
+        self.pf_mailbox(vf)?.write(1 << PFMAILBOX_ACK);
+        Ok(())
+    }
+
+    fn signal(&mut self, vf: u8) -> Result<(), &'static str> {
+        self.pf_mailbox(vf)?.write(1 << PFMAILBOX_STS);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VfMailboxState {
+    clear_to_send: bool,
+    api: MailboxApi,
+}
+
+/// The PF's side of the mailbox protocol.
+pub struct PfMailbox {
+    vfs: [VfMailboxState; MAX_VFS],
+    num_vfs: u8,
+    multicast_offset: HashOffset,
+}
+
+impl PfMailbox {
+    /// `multicast_offset` is the MTA hash offset reported to VFs on reset, so they hash their groups the same way.
+    pub fn new(num_vfs: u8, multicast_offset: HashOffset) -> Result<PfMailbox, &'static str> {
+        if num_vfs as usize > MAX_VFS {
+            return Err("too many VFs for the mailbox");
+        }
+        Ok(PfMailbox {
+            vfs: [VfMailboxState { clear_to_send: false, api: MailboxApi::V10 }; MAX_VFS],
+            num_vfs,
+            multicast_offset,
+        })
+    }
+
+    /// Whether the VF has reset through the mailbox, so its other messages are handled.
+    pub fn clear_to_send(&self, vf: u8) -> bool {
+        self.vfs.get(vf as usize).is_some_and(|state| state.clear_to_send)
+    }
+
+    pub fn api(&self, vf: u8) -> Option<MailboxApi> {
+        self.vfs.get(vf as usize).map(|state| state.api)
+    }
+
+    /// Forgets a VF's state, which has to be done when the VF is reset by a function level reset.
+    pub fn reset_vf(&mut self, vf: u8) {
+        if let Some(state) = self.vfs.get_mut(vf as usize) {
+            *state = VfMailboxState { clear_to_send: false, api: MailboxApi::V10 };
+        }
+    }
+
+    /// Handles the pending messages of all VFs. Returns the number of messages handled.
+    pub fn process<T: MailboxTransport, P: VfPolicy>(&mut self, transport: &mut T, policy: &mut P) -> Result<usize, &'static str> {
+        let mut handled = 0;
+        for vf in 0..self.num_vfs {
+            if transport.take_request(vf)? {
+                self.process_vf(transport, policy, vf)?;
+                handled += 1;
+            }
+        }
+        Ok(handled)
+    }
+
+    /// Reads, acknowledges and replies to a message the VF has posted.
+    pub fn process_vf<T: MailboxTransport, P: VfPolicy>(&mut self, transport: &mut T, policy: &mut P, vf: u8) -> Result<(), &'static str> {
+        let mut msg = [0u32; MAILBOX_SIZE];
+        lock(transport, vf)?;
+        transport.read(vf, &mut msg)?;
+        transport.ack(vf)?;
+
+        let reply = self.handle(vf, &msg, policy);
+        self.send(transport, vf, &reply)
+    }
+
+    /// Writes a message to a VF. The VF acknowledges it through [`MailboxTransport::take_ack`].
+    pub fn send<T: MailboxTransport>(&self, transport: &mut T, vf: u8, msg: &[u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        lock(transport, vf)?;
+        transport.write(vf, msg)?;
+        transport.signal(vf)
+    }
+
+    /// Decodes a VF message, applies the policy and returns the reply, without touching the mailbox.
+    pub fn handle<P: VfPolicy>(&mut self, vf: u8, msg: &[u32; MAILBOX_SIZE], policy: &mut P) -> [u32; MAILBOX_SIZE] {
+        let mut reply = [0u32; MAILBOX_SIZE];
+        reply[0].set_bits(MSG_TYPE, msg[0].get_bits(MSG_TYPE));
+        let Some(state) = self.vfs.get_mut(vf as usize).filter(|_| vf < self.num_vfs) else {
+            reply[0].set_bit(MSG_NACK, true);
+            return reply;
+        };
+
+        let message = VfMessage::decode(msg);
+        // nothing but a reset is accepted until the VF is clear to send
+        if !state.clear_to_send && message != Ok(VfMessage::Reset) {
+            reply[0].set_bit(MSG_NACK, true);
+            return reply;
+        }
+
+        let ack = match message {
+            Ok(VfMessage::Reset) => {
+                *state = VfMailboxState { clear_to_send: true, api: MailboxApi::V10 };
+                let address = policy.reset(vf);
+                (reply[1], reply[2]) = address_to_words(&address);
+                reply[3] = self.multicast_offset as u32;
+                true
+            }
+            Ok(VfMessage::SetMacAddress(address)) => policy.set_mac_address(vf, address),
+            Ok(VfMessage::SetMulticast { hashes, count }) => policy.set_multicast(vf, &hashes[..count]),
+            Ok(VfMessage::SetVlan { vlan, add }) => policy.set_vlan(vf, vlan, add),
+            Ok(VfMessage::SetLpe { max_frame_size }) => policy.set_lpe(vf, max_frame_size),
+            Ok(VfMessage::ApiNegotiate(api)) => {
+                state.api = api;
+                reply[1] = api.to_wire();
+                true
+            }
+            Ok(VfMessage::GetQueues) if state.api >= MailboxApi::V11 => {
+                let queues = policy.queues(vf);
+                reply[1] = queues.tx_queues as u32;
+                reply[2] = queues.rx_queues as u32;
+                reply[3] = queues.transparent_vlan as u32;
+                reply[4] = queues.default_queue as u32;
+                true
+            }
+            Ok(VfMessage::GetQueues) | Err(_) => false,
+        };
+        reply[0].set_bit(MSG_ACK, ack);
+        reply[0].set_bit(MSG_NACK, !ack);
+        reply[0].set_bit(MSG_CTS, true);
+        reply
+    }
+}
+
+fn lock<T: MailboxTransport>(transport: &mut T, vf: u8) -> Result<(), &'static str> {
+    for _ in 0..MAILBOX_LOCK_ATTEMPTS {
+        if transport.lock(vf)? {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err("locking VF mailbox timed out")
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SimulatedVfMailbox {
+    memory: [u32; MAILBOX_SIZE],
+    vf_owned: bool,
+    pf_owned: bool,
+    /// VFREQ: the VF posted a message.
+    request: bool,
+    /// VFACK: the VF read the PF's message.
+    vf_ack: bool,
+    /// PFSTS: the PF posted a message.
+    pf_status: bool,
+    /// PFACK: the PF read the VF's message.
+    pf_ack: bool,
+}
+
+/// A software model of the mailboxes, which also plays the VFs' side of the protocol.
+pub struct SimulatedMailbox {
+    vfs: [SimulatedVfMailbox; MAX_VFS],
+}
+
+impl SimulatedMailbox {
+    pub fn new() -> SimulatedMailbox {
+        SimulatedMailbox { vfs: [SimulatedVfMailbox::default(); MAX_VFS] }
+    }
+
+    /// Posts a message from a VF. Fails if the PF holds the memory or hasn't read the previous message.
+    pub fn vf_post(&mut self, vf: u8, msg: &[u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        let mailbox = self.vfs.get_mut(vf as usize).ok_or("VF is out of range")?;
+        if mailbox.pf_owned || mailbox.request {
+            return Err("VF mailbox is busy");
+        }
+        mailbox.memory = *msg;
+        mailbox.pf_ack = false;
+        mailbox.request = true;
+        Ok(())
+    }
+
+    /// Whether the PF has read the VF's last message.
+    pub fn vf_message_acked(&self, vf: u8) -> bool {
+        self.vfs.get(vf as usize).is_some_and(|mailbox| mailbox.pf_ack)
+    }
+
+    /// Reads a message the PF posted to the VF, and acknowledges it.
+    pub fn vf_receive(&mut self, vf: u8) -> Option<[u32; MAILBOX_SIZE]> {
+        let mailbox = self.vfs.get_mut(vf as usize)?;
+        if !mailbox.pf_status || mailbox.pf_owned {
+            return None;
+        }
+        mailbox.pf_status = false;
+        mailbox.vf_ack = true;
+        Some(mailbox.memory)
+    }
+
+    /// Makes the VF hold its mailbox memory, so the PF can't lock it.
+    pub fn vf_hold(&mut self, vf: u8, hold: bool) {
+        if let Some(mailbox) = self.vfs.get_mut(vf as usize) {
+            mailbox.vf_owned = hold;
+        }
+    }
+}
+
+impl Default for SimulatedMailbox {
+    fn default() -> SimulatedMailbox {
+        SimulatedMailbox::new()
+    }
+}
+
+impl SimulatedMailbox {
+    fn mailbox(&mut self, vf: u8) -> Result<&mut SimulatedVfMailbox, &'static str> {
+        self.vfs.get_mut(vf as usize).ok_or("VF is out of range")
+    }
+}
+
+impl MailboxTransport for SimulatedMailbox {
+    fn take_request(&mut self, vf: u8) -> Result<bool, &'static str> {
+        Ok(core::mem::take(&mut self.mailbox(vf)?.request))
+    }
+
+    fn take_ack(&mut self, vf: u8) -> Result<bool, &'static str> {
+        Ok(core::mem::take(&mut self.mailbox(vf)?.vf_ack))
+    }
+
+    fn lock(&mut self, vf: u8) -> Result<bool, &'static str> {
+        let mailbox = self.mailbox(vf)?;
+        mailbox.pf_owned = !mailbox.vf_owned;
+        Ok(mailbox.pf_owned)
+    }
+
+    fn read(&mut self, vf: u8, msg: &mut [u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        *msg = self.mailbox(vf)?.memory;
+        Ok(())
+    }
+
+    fn write(&mut self, vf: u8, msg: &[u32; MAILBOX_SIZE]) -> Result<(), &'static str> {
+        self.mailbox(vf)?.memory = *msg;
+        Ok(())
+    }
+
+    fn ack(&mut self, vf: u8) -> Result<(), &'static str> {
+        let mailbox = self.mailbox(vf)?;
+        mailbox.pf_ack = true;
+        mailbox.pf_owned = false;
+        Ok(())
+    }
+
+    fn signal(&mut self, vf: u8) -> Result<(), &'static str> {
+        let mailbox = self.mailbox(vf)?;
+        mailbox.pf_status = true;
+        mailbox.pf_owned = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VF_ADDRESS: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x05];
+
+    #[derive(Default)]
+    struct TestPolicy {
+        multicast: [u16; MAX_VF_MULTICAST_ENTRIES],
+        num_multicast: usize,
+    }
+
+    impl VfPolicy for TestPolicy {
+        fn reset(&mut self, _vf: u8) -> [u8; 6] {
+            VF_ADDRESS
+        }
+        fn set_mac_address(&mut self, _vf: u8, address: [u8; 6]) -> bool {
+            address[0] & 1 == 0
+        }
+        fn set_multicast(&mut self, _vf: u8, hashes: &[u16]) -> bool {
+            self.multicast[..hashes.len()].copy_from_slice(hashes);
+            self.num_multicast = hashes.len();
+            true
+        }
+        fn set_vlan(&mut self, _vf: u8, _vlan: u16, _add: bool) -> bool {
+            false
+        }
+        fn set_lpe(&mut self, _vf: u8, max_frame_size: u32) -> bool {
+            max_frame_size <= 9728
+        }
+        fn queues(&mut self, _vf: u8) -> VfQueues {
+            VfQueues { tx_queues: 2, rx_queues: 2, transparent_vlan: false, default_queue: 0 }
+        }
+    }
+
+    fn exchange(mailbox: &mut SimulatedMailbox, pf: &mut PfMailbox, policy: &mut TestPolicy, vf: u8, msg: VfMessage) -> PfReply {
+        mailbox.vf_post(vf, &msg.encode()).unwrap();
+        assert_eq!(pf.process(mailbox, policy), Ok(1));
+        assert!(mailbox.vf_message_acked(vf));
+        PfReply::decode(&mailbox.vf_receive(vf).unwrap())
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut hashes = [0u16; MAX_VF_MULTICAST_ENTRIES];
+        hashes[..3].copy_from_slice(&[0x0FB, 0x341, 0xD04]);
+        for msg in [
+            VfMessage::Reset,
+            VfMessage::SetMacAddress(VF_ADDRESS),
+            VfMessage::SetMulticast { hashes, count: 3 },
+            VfMessage::SetVlan { vlan: 100, add: true },
+            VfMessage::SetLpe { max_frame_size: 9018 },
+            VfMessage::ApiNegotiate(MailboxApi::V11),
+            VfMessage::GetQueues,
+        ] {
+            assert_eq!(VfMessage::decode(&msg.encode()), Ok(msg));
+        }
+    }
+
+    #[test]
+    fn multicast_lists_are_truncated() {
+        let mut msg = VfMessage::SetMulticast { hashes: [0x123; MAX_VF_MULTICAST_ENTRIES], count: 0 }.encode();
+        msg[0].set_bits(MSG_INFO, 40);
+        let Ok(VfMessage::SetMulticast { count, .. }) = VfMessage::decode(&msg) else { panic!() };
+        assert_eq!(count, MAX_VF_MULTICAST_ENTRIES);
+    }
+
+    #[test]
+    fn vf_is_nacked_until_it_resets() {
+        let (mut mailbox, mut policy) = (SimulatedMailbox::new(), TestPolicy::default());
+        let mut pf = PfMailbox::new(4, HashOffset::Bits47To36).unwrap();
+
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 2, VfMessage::SetMacAddress(VF_ADDRESS));
+        assert!(!reply.ack && !reply.clear_to_send);
+
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 2, VfMessage::Reset);
+        assert!(reply.ack && reply.clear_to_send);
+        assert_eq!(reply.mac_address(), VF_ADDRESS);
+        assert!(pf.clear_to_send(2));
+        assert!(!pf.clear_to_send(1));
+
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 2, VfMessage::SetMacAddress([0x01, 0, 0, 0, 0, 1]));
+        assert!(!reply.ack && reply.clear_to_send);
+    }
+
+    #[test]
+    fn queue_query_needs_api_1_1() {
+        let (mut mailbox, mut policy) = (SimulatedMailbox::new(), TestPolicy::default());
+        let mut pf = PfMailbox::new(1, HashOffset::Bits47To36).unwrap();
+        exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::Reset);
+        assert!(!exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::GetQueues).ack);
+
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::ApiNegotiate(MailboxApi::V11));
+        assert!(reply.ack);
+        assert_eq!(pf.api(0), Some(MailboxApi::V11));
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::GetQueues);
+        assert!(reply.ack);
+        assert_eq!(reply.words[1..3], [2, 2]);
+    }
+
+    #[test]
+    fn multicast_hashes_reach_the_policy() {
+        let (mut mailbox, mut policy) = (SimulatedMailbox::new(), TestPolicy::default());
+        let mut pf = PfMailbox::new(1, HashOffset::Bits47To36).unwrap();
+        exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::Reset);
+        let hashes = core::array::from_fn(|i| i as u16 * 3);
+        let reply = exchange(&mut mailbox, &mut pf, &mut policy, 0, VfMessage::SetMulticast { hashes, count: MAX_VF_MULTICAST_ENTRIES });
+        assert!(reply.ack);
+        assert_eq!(policy.num_multicast, MAX_VF_MULTICAST_ENTRIES);
+        assert_eq!(policy.multicast, hashes);
+    }
+
+    #[test]
+    fn out_of_range_vfs_are_errors() {
+        let mut mailbox = SimulatedMailbox::new();
+        let pf = PfMailbox::new(1, HashOffset::Bits47To36).unwrap();
+        assert!(mailbox.take_request(MAX_VFS as u8).is_err());
+        assert!(pf.send(&mut mailbox, MAX_VFS as u8, &[0; MAILBOX_SIZE]).is_err());
+        assert!(PfMailbox::new(MAX_VFS as u8 + 1, HashOffset::Bits47To36).is_err());
+    }
+
+    #[test]
+    fn locking_a_held_mailbox_times_out() {
+        let (mut mailbox, mut policy) = (SimulatedMailbox::new(), TestPolicy::default());
+        let mut pf = PfMailbox::new(1, HashOffset::Bits47To36).unwrap();
+        mailbox.vf_post(0, &VfMessage::Reset.encode()).unwrap();
+        mailbox.vf_hold(0, true);
+        assert!(pf.process(&mut mailbox, &mut policy).is_err());
+        mailbox.vf_hold(0, false);
+        assert!(pf.process_vf(&mut mailbox, &mut policy, 0).is_ok());
+    }
+}
@@ -0,0 +1,471 @@
+//! MDIO access to external PHYs through the MSCA and MSRWD registers, and a Clause 45 PHY abstraction.
+//!
+//! A Clause 22 access is a single MDIO frame addressing one of 32 registers of a PHY. A Clause 45 access
+//! addresses a register of one of the PHY's MMDs (MDIO manageable devices) and takes two frames:
+//! an address frame that sets the register address, then the read or write frame.
+//!
+//! PHY drivers are written against the [`MdioBus`] trait, which is implemented by [`IxgbeMdio`] for the device
+//! and by [`SimulatedMdioBus`] for testing without hardware.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::eeprom::{acquire_swfw_sync, release_swfw_sync, SwFwResource};
+
+/// Number of times MSCA is polled for the end of an MDIO frame.
+const MDIO_POLL_ATTEMPTS: u32 = 100_000;
+/// Number of times the PHY is polled for the end of a reset.
+const PHY_RESET_POLL_ATTEMPTS: u32 = 100_000;
+
+/// Number of PHY addresses on an MDIO bus.
+pub const MDIO_NUM_PHY_ADDRESSES: u8 = 32;
+
+// MDI Single Command and Address
+const MSCA_NP_ADDR: core::ops::Range<usize> = 0..16;
+const MSCA_DEV_TYPE: core::ops::Range<usize> = 16..21;
+const MSCA_PHY_ADDR: core::ops::Range<usize> = 21..26;
+const MSCA_OP_CODE: core::ops::Range<usize> = 26..28;
+const MSCA_OP_ADDR_CYCLE: u32 = 0b00;
+const MSCA_OP_WRITE: u32 = 0b01;
+const MSCA_OP_READ_AUTOINC: u32 = 0b10;
+const MSCA_OP_READ: u32 = 0b11;
+const MSCA_ST_CODE: core::ops::Range<usize> = 28..30;
+const MSCA_ST_CLAUSE_45: u32 = 0b00;
+const MSCA_ST_CLAUSE_22: u32 = 0b01;
+const MSCA_MDI_COMMAND: usize = 30;
+
+// MDI Single Read and Write Data
+const MSRWD_WRITE_DATA: core::ops::Range<usize> = 0..16;
+const MSRWD_READ_DATA: core::ops::Range<usize> = 16..32;
+
+// Clause 45 MMDs
+pub const MDIO_MMD_PMA_PMD: u8 = 1;
+pub const MDIO_MMD_PCS: u8 = 3;
+pub const MDIO_MMD_PHY_XS: u8 = 4;
+pub const MDIO_MMD_AN: u8 = 7;
+
+// Registers common to all MMDs
+const MDIO_CTRL1: u16 = 0;
+const MDIO_STAT1: u16 = 1;
+const MDIO_DEVID1: u16 = 2;
+const MDIO_DEVID2: u16 = 3;
+const MDIO_CTRL1_RESET: usize = 15;
+const MDIO_STAT1_LINK: usize = 2;
+
+// PMA/PMD control 1 speed selection
+const MDIO_CTRL1_SPEED_SEL_LSB: usize = 6;
+const MDIO_CTRL1_SPEED_SEL_MSB: usize = 13;
+const MDIO_CTRL1_SPEED_SEL_EXT: core::ops::Range<usize> = 2..6;
+const MDIO_CTRL1_SPEED_10G: u16 = 0;
+
+/// Only the model bits of a PHY ID identify the PHY; the low bits are its revision.
+const PHY_ID_REVISION_MASK: u32 = 0xF;
+
+/// Access to the registers of the PHYs on an MDIO bus.
+pub trait MdioBus {
+    fn read_c22(&mut self, phy: u8, reg: u8) -> Result<u16, &'static str>;
+    fn write_c22(&mut self, phy: u8, reg: u8, value: u16) -> Result<(), &'static str>;
+    fn read_c45(&mut self, phy: u8, mmd: u8, reg: u16) -> Result<u16, &'static str>;
+    fn write_c45(&mut self, phy: u8, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str>;
+}
+
+/// The MDIO master of one LAN function.
+pub struct IxgbeMdio<'a> {
+    rx_regs2: &'a mut IntelIxgbeRxRegisters2,
+    mac_regs: &'a mut IntelIxgbeMacRegisters,
+    resource: SwFwResource,
+}
+
+impl<'a> IxgbeMdio<'a> {
+    /// `lan` is the LAN function (0 or 1) that the registers belong to, which selects the PHY resource to hold
+    /// while accessing the bus, since the firmware may access the PHY too.
+    pub fn new(rx_regs2: &'a mut IntelIxgbeRxRegisters2, mac_regs: &'a mut IntelIxgbeMacRegisters, lan: u8) -> IxgbeMdio<'a> {
+        let resource = if lan == 0 { SwFwResource::Phy0 } else { SwFwResource::Phy1 };
+        IxgbeMdio { rx_regs2, mac_regs, resource }
+    }
+
+    /// Runs `f` with the PHY resource held.
+    /// An error from `f` is returned in preference to an error releasing the resource.
+    fn with_phy_sync<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, &'static str>) -> Result<T, &'static str> {
+        acquire_swfw_sync(self.mac_regs, self.resource)?;
+        let result = f(self);
+        let released = release_swfw_sync(self.mac_regs, self.resource);
+        let value = result?;
+        released?;
+        Ok(value)
+    }
+
+    /// Sends one MDIO frame and waits for it to complete.
+    fn command(&mut self, clause: u32, op: u32, phy: u8, dev_type: u8, address: u16) -> Result<(), &'static str> {
+        if phy >= MDIO_NUM_PHY_ADDRESSES || dev_type >= 32 {
+            return Err("MDIO PHY address or device is out of range");
+        }
+        let mut msca = 0;
+        msca.set_bits(MSCA_NP_ADDR, address as u32);
+        msca.set_bits(MSCA_DEV_TYPE, dev_type as u32);
+        msca.set_bits(MSCA_PHY_ADDR, phy as u32);
+        msca.set_bits(MSCA_OP_CODE, op);
+        msca.set_bits(MSCA_ST_CODE, clause);
+        msca.set_bit(MSCA_MDI_COMMAND, true);
+        self.rx_regs2.mdi_single_command_and_address.write(msca);
+
+        for _ in 0..MDIO_POLL_ATTEMPTS {
+            if !self.rx_regs2.mdi_single_command_and_address.read().get_bit(MSCA_MDI_COMMAND) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("MDIO command timed out")
+    }
+
+    fn read_data(&self) -> u16 {
+        self.rx_regs2.mdi_single_read_and_write_data.read().get_bits(MSRWD_READ_DATA) as u16
+    }
+
+    fn write_data(&mut self, value: u16) {
+        let mut msrwd = 0;
+        msrwd.set_bits(MSRWD_WRITE_DATA, value as u32);
+        self.rx_regs2.mdi_single_read_and_write_data.write(msrwd);
+    }
+}
+
+impl MdioBus for IxgbeMdio<'_> {
+    fn read_c22(&mut self, phy: u8, reg: u8) -> Result<u16, &'static str> {
+        // Clause 22 frames carry the register address in the device type field
+        self.with_phy_sync(|mdio| {
+            mdio.command(MSCA_ST_CLAUSE_22, MSCA_OP_READ_AUTOINC, phy, reg, 0)?;
+            Ok(mdio.read_data())
+        })
+    }
+
+    fn write_c22(&mut self, phy: u8, reg: u8, value: u16) -> Result<(), &'static str> {
+        self.with_phy_sync(|mdio| {
+            mdio.write_data(value);
+            mdio.command(MSCA_ST_CLAUSE_22, MSCA_OP_WRITE, phy, reg, 0)
+        })
+    }
+
+    fn read_c45(&mut self, phy: u8, mmd: u8, reg: u16) -> Result<u16, &'static str> {
+        self.with_phy_sync(|mdio| {
+            mdio.command(MSCA_ST_CLAUSE_45, MSCA_OP_ADDR_CYCLE, phy, mmd, reg)?;
+            mdio.command(MSCA_ST_CLAUSE_45, MSCA_OP_READ, phy, mmd, 0)?;
+            Ok(mdio.read_data())
+        })
+    }
+
+    fn write_c45(&mut self, phy: u8, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str> {
+        self.with_phy_sync(|mdio| {
+            mdio.write_data(value);
+            mdio.command(MSCA_ST_CLAUSE_45, MSCA_OP_ADDR_CYCLE, phy, mmd, reg)?;
+            mdio.command(MSCA_ST_CLAUSE_45, MSCA_OP_WRITE, phy, mmd, 0)
+        })
+    }
+}
+
+/// Number of registers a [`SimulatedMdioBus`] can hold.
+const SIMULATED_MDIO_REGISTERS: usize = 64;
+
+/// An MDIO bus with PHYs modelled as sparse register maps. Registers that were never set read as 0xFFFF,
+/// like the pulled up data line of an address without a PHY.
+///
+/// Reset bits (bit 15 of control register 1 of an MMD, or of the Clause 22 control register) clear themselves.
+pub struct SimulatedMdioBus {
+    /// (phy, mmd, reg, value); Clause 22 registers use MMD 0.
+    registers: [Option<(u8, u8, u16, u16)>; SIMULATED_MDIO_REGISTERS],
+}
+
+impl Default for SimulatedMdioBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedMdioBus {
+    pub fn new() -> SimulatedMdioBus {
+        SimulatedMdioBus { registers: [None; SIMULATED_MDIO_REGISTERS] }
+    }
+
+    /// Sets a Clause 45 register, as the PHY itself would.
+    pub fn set_c45(&mut self, phy: u8, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str> {
+        self.set(phy, mmd, reg, value)
+    }
+
+    /// Sets a Clause 22 register, as the PHY itself would.
+    pub fn set_c22(&mut self, phy: u8, reg: u8, value: u16) -> Result<(), &'static str> {
+        self.set(phy, 0, reg as u16, value)
+    }
+
+    /// Adds a Clause 45 PHY with the given ID to every MMD the abstraction uses, with the link down.
+    pub fn add_c45_phy(&mut self, phy: u8, id: u32) -> Result<(), &'static str> {
+        for mmd in [MDIO_MMD_PMA_PMD, MDIO_MMD_PCS, MDIO_MMD_PHY_XS, MDIO_MMD_AN] {
+            self.set(phy, mmd, MDIO_DEVID1, (id >> 16) as u16)?;
+            self.set(phy, mmd, MDIO_DEVID2, id as u16)?;
+            self.set(phy, mmd, MDIO_STAT1, 0)?;
+        }
+        self.set(phy, MDIO_MMD_PMA_PMD, MDIO_CTRL1, PhySpeed::Speed10G.speed_selection())
+    }
+
+    fn get(&self, phy: u8, mmd: u8, reg: u16) -> u16 {
+        self.registers.iter().flatten()
+            .find(|&&(p, m, r, _)| (p, m, r) == (phy, mmd, reg))
+            .map_or(0xFFFF, |&(_, _, _, value)| value)
+    }
+
+    fn set(&mut self, phy: u8, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str> {
+        let index = self.registers.iter()
+            .position(|entry| entry.is_some_and(|(p, m, r, _)| (p, m, r) == (phy, mmd, reg)))
+            .or_else(|| self.registers.iter().position(|entry| entry.is_none()))
+            .ok_or("simulated MDIO bus is out of registers")?;
+        self.registers[index] = Some((phy, mmd, reg, value));
+        Ok(())
+    }
+
+    fn write(&mut self, phy: u8, mmd: u8, reg: u16, mut value: u16) -> Result<(), &'static str> {
+        if reg == MDIO_CTRL1 {
+            value.set_bit(MDIO_CTRL1_RESET, false);
+        }
+        self.set(phy, mmd, reg, value)
+    }
+}
+
+impl MdioBus for SimulatedMdioBus {
+    fn read_c22(&mut self, phy: u8, reg: u8) -> Result<u16, &'static str> {
+        Ok(self.get(phy, 0, reg as u16))
+    }
+
+    fn write_c22(&mut self, phy: u8, reg: u8, value: u16) -> Result<(), &'static str> {
+        self.write(phy, 0, reg as u16, value)
+    }
+
+    fn read_c45(&mut self, phy: u8, mmd: u8, reg: u16) -> Result<u16, &'static str> {
+        Ok(self.get(phy, mmd, reg))
+    }
+
+    fn write_c45(&mut self, phy: u8, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str> {
+        self.write(phy, mmd, reg, value)
+    }
+}
+
+/// Known external PHYs, by the model bits of their ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhyModel {
+    /// Teranetics TN1010 10GBASE-T PHY.
+    Tn1010,
+    /// AMCC QT2022 10G PHY.
+    Qt2022,
+    /// Atheros AR8021 1G PHY.
+    Ar8021,
+    Unknown(u32),
+}
+
+impl PhyModel {
+    fn from_id(id: u32) -> PhyModel {
+        match id & !PHY_ID_REVISION_MASK {
+            0x00A19410 => PhyModel::Tn1010,
+            0x0043A400 => PhyModel::Qt2022,
+            0x03429050 => PhyModel::Ar8021,
+            model => PhyModel::Unknown(model),
+        }
+    }
+}
+
+/// A speed that can be selected in the PMA/PMD control register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhySpeed {
+    Speed100M,
+    Speed1G,
+    Speed10G,
+}
+
+impl PhySpeed {
+    /// Returns the speed selection bits of PMA/PMD control 1 for this speed.
+    fn speed_selection(self) -> u16 {
+        let mut ctrl = 0;
+        match self {
+            PhySpeed::Speed100M => ctrl.set_bit(MDIO_CTRL1_SPEED_SEL_MSB, true),
+            PhySpeed::Speed1G => ctrl.set_bit(MDIO_CTRL1_SPEED_SEL_LSB, true),
+            PhySpeed::Speed10G => ctrl
+                .set_bit(MDIO_CTRL1_SPEED_SEL_MSB, true)
+                .set_bit(MDIO_CTRL1_SPEED_SEL_LSB, true)
+                .set_bits(MDIO_CTRL1_SPEED_SEL_EXT, MDIO_CTRL1_SPEED_10G),
+        };
+        ctrl
+    }
+
+    fn from_control(ctrl: u16) -> Option<PhySpeed> {
+        match (ctrl.get_bit(MDIO_CTRL1_SPEED_SEL_MSB), ctrl.get_bit(MDIO_CTRL1_SPEED_SEL_LSB)) {
+            (true, false) => Some(PhySpeed::Speed100M),
+            (false, true) => Some(PhySpeed::Speed1G),
+            (true, true) if ctrl.get_bits(MDIO_CTRL1_SPEED_SEL_EXT) == MDIO_CTRL1_SPEED_10G => Some(PhySpeed::Speed10G),
+            _ => None,
+        }
+    }
+}
+
+/// The link state reported by a PHY.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhyLinkStatus {
+    pub up: bool,
+    pub speed: Option<PhySpeed>,
+}
+
+/// An external Clause 45 PHY, such as a 10GBASE-T PHY.
+pub struct Phy<B: MdioBus> {
+    bus: B,
+    address: u8,
+    id: u32,
+}
+
+impl<B: MdioBus> Phy<B> {
+    /// Finds the first PHY on the bus that answers with a valid PMA/PMD ID.
+    pub fn probe(mut bus: B) -> Result<Phy<B>, &'static str> {
+        for address in 0..MDIO_NUM_PHY_ADDRESSES {
+            let id = read_id(&mut bus, address)?;
+            if id != 0 && id != 0xFFFF_FFFF {
+                return Ok(Phy { bus, address, id });
+            }
+        }
+        Err("no PHY found on the MDIO bus")
+    }
+
+    /// Uses the PHY at `address`, which has to answer with a valid ID.
+    pub fn new(mut bus: B, address: u8) -> Result<Phy<B>, &'static str> {
+        let id = read_id(&mut bus, address)?;
+        if id == 0 || id == 0xFFFF_FFFF {
+            return Err("no PHY at the MDIO address");
+        }
+        Ok(Phy { bus, address, id })
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// The PHY ID, including the revision bits.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn model(&self) -> PhyModel {
+        PhyModel::from_id(self.id)
+    }
+
+    pub fn bus(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    pub fn into_bus(self) -> B {
+        self.bus
+    }
+
+    pub fn read(&mut self, mmd: u8, reg: u16) -> Result<u16, &'static str> {
+        self.bus.read_c45(self.address, mmd, reg)
+    }
+
+    pub fn write(&mut self, mmd: u8, reg: u16, value: u16) -> Result<(), &'static str> {
+        self.bus.write_c45(self.address, mmd, reg, value)
+    }
+
+    /// Returns whether the PCS has link, and the speed selected in the PMA/PMD.
+    pub fn link_status(&mut self) -> Result<PhyLinkStatus, &'static str> {
+        // the link status bit latches low, so the first read returns whether the link went down since the last read
+        self.read(MDIO_MMD_PCS, MDIO_STAT1)?;
+        let up = self.read(MDIO_MMD_PCS, MDIO_STAT1)?.get_bit(MDIO_STAT1_LINK);
+        let speed = PhySpeed::from_control(self.read(MDIO_MMD_PMA_PMD, MDIO_CTRL1)?);
+        Ok(PhyLinkStatus { up, speed })
+    }
+
+    /// Forces the speed of the PMA/PMD.
+    pub fn set_speed(&mut self, speed: PhySpeed) -> Result<(), &'static str> {
+        let mut ctrl = self.read(MDIO_MMD_PMA_PMD, MDIO_CTRL1)?;
+        ctrl.set_bit(MDIO_CTRL1_SPEED_SEL_MSB, false);
+        ctrl.set_bit(MDIO_CTRL1_SPEED_SEL_LSB, false);
+        ctrl.set_bits(MDIO_CTRL1_SPEED_SEL_EXT, 0);
+        self.write(MDIO_MMD_PMA_PMD, MDIO_CTRL1, ctrl | speed.speed_selection())
+    }
+
+    /// Resets the whole PHY through its PHY XS MMD, and waits for the reset bit to clear.
+    pub fn reset(&mut self) -> Result<(), &'static str> {
+        let mut ctrl = self.read(MDIO_MMD_PHY_XS, MDIO_CTRL1)?;
+        ctrl.set_bit(MDIO_CTRL1_RESET, true);
+        self.write(MDIO_MMD_PHY_XS, MDIO_CTRL1, ctrl)?;
+
+        for _ in 0..PHY_RESET_POLL_ATTEMPTS {
+            if !self.read(MDIO_MMD_PHY_XS, MDIO_CTRL1)?.get_bit(MDIO_CTRL1_RESET) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("PHY reset timed out")
+    }
+}
+
+/// Reads the PMA/PMD ID of the PHY at `address`.
+fn read_id<B: MdioBus>(bus: &mut B, address: u8) -> Result<u32, &'static str> {
+    let high = bus.read_c45(address, MDIO_MMD_PMA_PMD, MDIO_DEVID1)?;
+    let low = bus.read_c45(address, MDIO_MMD_PMA_PMD, MDIO_DEVID2)?;
+    Ok(((high as u32) << 16) | low as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TN1010_ID: u32 = 0x00A1_9413;
+
+    #[test]
+    fn probe_skips_empty_addresses() {
+        let mut bus = SimulatedMdioBus::new();
+        bus.add_c45_phy(5, TN1010_ID).unwrap();
+        let phy = Phy::probe(bus).unwrap();
+        assert_eq!(phy.address(), 5);
+        assert_eq!(phy.id(), TN1010_ID);
+        assert_eq!(phy.model(), PhyModel::Tn1010);
+        assert!(Phy::new(phy.into_bus(), 4).is_err());
+    }
+
+    #[test]
+    fn speed_selection_round_trips() {
+        let mut bus = SimulatedMdioBus::new();
+        bus.add_c45_phy(0, TN1010_ID).unwrap();
+        let mut phy = Phy::new(bus, 0).unwrap();
+        for speed in [PhySpeed::Speed100M, PhySpeed::Speed1G, PhySpeed::Speed10G] {
+            phy.set_speed(speed).unwrap();
+            assert_eq!(phy.link_status().unwrap(), PhyLinkStatus { up: false, speed: Some(speed) });
+        }
+    }
+
+    #[test]
+    fn link_status_reads_the_pcs() {
+        let mut bus = SimulatedMdioBus::new();
+        bus.add_c45_phy(0, TN1010_ID).unwrap();
+        bus.set_c45(0, MDIO_MMD_PCS, MDIO_STAT1, 1 << MDIO_STAT1_LINK).unwrap();
+        let mut phy = Phy::new(bus, 0).unwrap();
+        assert!(phy.link_status().unwrap().up);
+    }
+
+    #[test]
+    fn reset_completes_when_the_bit_clears() {
+        let mut bus = SimulatedMdioBus::new();
+        bus.add_c45_phy(0, TN1010_ID).unwrap();
+        bus.set_c45(0, MDIO_MMD_PHY_XS, MDIO_CTRL1, 0).unwrap();
+        let mut phy = Phy::new(bus, 0).unwrap();
+        phy.reset().unwrap();
+        assert_eq!(phy.read(MDIO_MMD_PHY_XS, MDIO_CTRL1), Ok(0));
+    }
+
+    #[test]
+    fn clause_22_registers_are_separate_from_clause_45() {
+        let mut bus = SimulatedMdioBus::new();
+        bus.write_c22(1, 2, 0x1234).unwrap();
+        assert_eq!(bus.read_c22(1, 2), Ok(0x1234));
+        assert_eq!(bus.read_c45(1, MDIO_MMD_PMA_PMD, 2), Ok(0xFFFF));
+    }
+
+    #[test]
+    fn mdio_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::RX_REGISTERS2_OFFSET;
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, mdi_single_command_and_address), 0x425C);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, mdi_single_read_and_write_data), 0x4260);
+    }
+}
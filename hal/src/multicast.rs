@@ -0,0 +1,169 @@
+//! Multicast filtering over the Multicast Table Array (MTA) and MCSTCTRL.
+//!
+//! Multicast packets that don't hit a receive address entry are accepted when the bit indexed by the 12-bit hash
+//! of their destination address is set in the 4096-bit MTA. MCSTCTRL.MO selects which address bits form the hash,
+//! and the hash is computed on the host by [`address_hash`], which matches the hardware.
+//!
+//! The table is built in memory and written in one pass with the filter disabled, so the hardware never
+//! filters against a partly written table. When more groups are joined than the configured limit,
+//! the hash would accept most multicast traffic anyway, so all-multicast mode is used instead.
+
+use bit_field::BitField;
+use crate::IntelIxgbeRxRegisters2;
+use crate::mac_filter::{address_hash, HashOffset, HASH_TABLE_BITS};
+
+/// Number of MTA registers.
+pub const MTA_REGISTERS: usize = HASH_TABLE_BITS / 32;
+/// A default limit of groups above which all multicast packets are accepted.
+pub const DEFAULT_MAX_MULTICAST_GROUPS: usize = 512;
+
+// Multicast Control Register
+const MCSTCTRL_MO: core::ops::Range<usize> = 0..2;
+const MCSTCTRL_MFE: usize = 2;
+
+// Filter Control Register
+const FCTRL_MPE: usize = 8;
+
+/// Returns the MTA register and the bit in it that the hash of an address selects.
+pub fn mta_position(address: &[u8; 6], offset: HashOffset) -> (usize, usize) {
+    let hash = address_hash(address, offset) as usize;
+    (hash >> 5, hash & 0x1F)
+}
+
+/// Builds the MTA contents that accept the given groups.
+pub fn build_table(addresses: &[[u8; 6]], offset: HashOffset) -> Result<[u32; MTA_REGISTERS], &'static str> {
+    if addresses.iter().any(|address| !address[0].get_bit(0)) {
+        return Err("address isn't a multicast address");
+    }
+    let mut table = [0u32; MTA_REGISTERS];
+    for address in addresses {
+        let (register, bit) = mta_position(address, offset);
+        table[register].set_bit(bit, true);
+    }
+    Ok(table)
+}
+
+/// The multicast filter of the port.
+pub struct MulticastFilter {
+    offset: HashOffset,
+    max_groups: usize,
+    table: [u32; MTA_REGISTERS],
+    /// All-multicast mode was requested, rather than caused by too many groups.
+    all_multicast_requested: bool,
+    overflowed: bool,
+    /// FCTRL.MPE was set by this filter.
+    mpe: bool,
+}
+
+impl MulticastFilter {
+    /// Selects the hash offset, clears the table and enables the filter.
+    /// More than `max_groups` groups turn on all-multicast mode.
+    pub fn new(rx_regs2: &mut IntelIxgbeRxRegisters2, offset: HashOffset, max_groups: usize) -> MulticastFilter {
+        let mut filter = MulticastFilter {
+            offset,
+            max_groups,
+            table: [0; MTA_REGISTERS],
+            all_multicast_requested: false,
+            overflowed: false,
+            mpe: false,
+        };
+        filter.program(rx_regs2);
+        filter
+    }
+
+    /// Replaces the joined groups with `addresses`.
+    /// Returns whether all multicast packets are accepted because there are too many groups.
+    pub fn set_addresses(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, addresses: &[[u8; 6]]) -> Result<bool, &'static str> {
+        self.table = build_table(addresses, self.offset)?;
+        self.overflowed = addresses.len() > self.max_groups;
+        self.program(rx_regs2);
+        Ok(self.overflowed)
+    }
+
+    /// Accepts all multicast packets, regardless of the joined groups.
+    pub fn set_all_multicast(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, enable: bool) {
+        self.all_multicast_requested = enable;
+        self.update_mpe(rx_regs2);
+    }
+
+    pub fn all_multicast(&self) -> bool {
+        self.all_multicast_requested || self.overflowed
+    }
+
+    /// Returns whether the filter accepts packets sent to the address.
+    /// Addresses that share a hash with a joined group are accepted as well.
+    pub fn accepts(&self, address: &[u8; 6]) -> bool {
+        if self.all_multicast() {
+            return true;
+        }
+        let (register, bit) = mta_position(address, self.offset);
+        self.table[register].get_bit(bit)
+    }
+
+    pub fn offset(&self) -> HashOffset {
+        self.offset
+    }
+
+    /// The MTA contents, as last written to the device.
+    pub fn table(&self) -> &[u32; MTA_REGISTERS] {
+        &self.table
+    }
+
+    fn program(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2) {
+        rx_regs2.multicast_control_register.update(|mcstctrl| { mcstctrl.set_bit(MCSTCTRL_MFE, false); });
+        for (mta, value) in rx_regs2.multicast_table_array.iter_mut().zip(self.table.iter()) {
+            mta.write(*value);
+        }
+        self.update_mpe(rx_regs2);
+        rx_regs2.multicast_control_register.update(|mcstctrl| {
+            mcstctrl.set_bits(MCSTCTRL_MO, self.offset as u32);
+            mcstctrl.set_bit(MCSTCTRL_MFE, true);
+        });
+    }
+    /// Changes FCTRL.MPE only when all-multicast mode turns on or off,
+    /// so that a promiscuous setting made elsewhere is kept.
+    fn update_mpe(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2) {
+        let mpe = self.all_multicast();
+        if mpe != self.mpe {
+            rx_regs2.filter_control_register.update(|fctrl| { fctrl.set_bit(FCTRL_MPE, mpe); });
+            self.mpe = mpe;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP: [u8; 6] = [0x01, 0x00, 0x5E, 0x00, 0x00, 0xFB];
+
+    #[test]
+    fn table_sets_the_bit_of_each_group() {
+        // the hash of 01:00:5E:00:00:FB with bits 47:36 is 0xFB0, which is bit 16 of MTA[125]
+        assert_eq!(mta_position(&GROUP, HashOffset::Bits47To36), (125, 16));
+        let table = build_table(&[GROUP], HashOffset::Bits47To36).unwrap();
+        assert_eq!(table[125], 1 << 16);
+        assert_eq!(table.iter().filter(|&&value| value != 0).count(), 1);
+    }
+
+    #[test]
+    fn groups_sharing_a_hash_share_a_bit() {
+        let other = [0x01, 0x00, 0x5E, 0x7F, 0x00, 0xFB];
+        let table = build_table(&[GROUP, other], HashOffset::Bits47To36).unwrap();
+        assert_eq!(table.iter().map(|value| value.count_ones()).sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn unicast_addresses_are_rejected() {
+        assert!(build_table(&[[0x00, 0x1B, 0x21, 0x00, 0x00, 0x01]], HashOffset::Bits47To36).is_err());
+    }
+
+    #[test]
+    fn multicast_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::RX_REGISTERS2_OFFSET;
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, filter_control_register), 0x5080);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, multicast_control_register), 0x5090);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, multicast_table_array), 0x5200);
+    }
+}
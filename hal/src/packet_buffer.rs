@@ -0,0 +1,254 @@
+//! Partitioning of the on-chip Rx and Tx packet buffers over RXPBSIZE, TXPBSIZE and TXPBTHRESH.
+//!
+//! The 512 KB Rx and 160 KB Tx packet buffers are split into up to 8 packet buffers, one per traffic class.
+//! Without DCB only the first buffer is used and has to get all the space.
+//! When Flow Director is enabled, its filter table is kept at the end of the Rx packet buffer,
+//! so the space given by FDIRCTRL.PBALLOC has to be left out of the Rx split.
+//!
+//! The Tx threshold of a buffer is where the Tx DMA stops fetching packets into it,
+//! which has to leave room for one packet of the largest size.
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::flow_director::FdirPballoc;
+
+/// Number of packet buffers, one per traffic class.
+pub const MAX_PACKET_BUFFERS: usize = 8;
+/// Size of the Rx packet buffer in KB.
+pub const RX_PACKET_BUFFER_SIZE_KB: u32 = 512;
+/// Size of the Tx packet buffer in KB.
+pub const TX_PACKET_BUFFER_SIZE_KB: u32 = 160;
+/// Largest Tx packet in KB, which has to fit in a Tx packet buffer above its threshold.
+pub const TX_MAX_PACKET_SIZE_KB: u32 = 10;
+
+// Packet buffer sizes, in KB
+const RXPBSIZE_SIZE: Range<usize> = 10..20;
+const TXPBSIZE_SIZE: Range<usize> = 10..20;
+const TXPBTHRESH_THRESH: Range<usize> = 0..10;
+
+/// How the space is divided between the used packet buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferSplit<'a> {
+    Equal,
+    /// Each buffer gets a share of the space in proportion to its weight.
+    Weighted(&'a [u8]),
+}
+
+/// The sizes of the Rx and Tx packet buffers, in KB. Unused buffers have a size of 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketBufferLayout {
+    rx_kb: [u32; MAX_PACKET_BUFFERS],
+    tx_kb: [u32; MAX_PACKET_BUFFERS],
+    fdir: Option<FdirPballoc>,
+}
+
+impl PacketBufferLayout {
+    /// Splits the packet buffers between `num_buffers` buffers.
+    /// The Rx space reserved for Flow Director, if any, is left out.
+    /// Whatever a weighted split leaves over due to rounding goes to the first buffer.
+    pub fn split(num_buffers: usize, split: BufferSplit, fdir: Option<FdirPballoc>) -> Result<PacketBufferLayout, &'static str> {
+        if num_buffers == 0 || num_buffers > MAX_PACKET_BUFFERS {
+            return Err("number of packet buffers must be between 1 and 8");
+        }
+        let mut weights = [1u32; MAX_PACKET_BUFFERS];
+        if let BufferSplit::Weighted(split_weights) = split {
+            if split_weights.len() != num_buffers {
+                return Err("there must be one weight per packet buffer");
+            }
+            if split_weights.contains(&0) {
+                return Err("packet buffer weights must not be 0");
+            }
+            for (weight, &split_weight) in weights.iter_mut().zip(split_weights) {
+                *weight = split_weight as u32;
+            }
+        }
+
+        let layout = PacketBufferLayout {
+            rx_kb: split_space(rx_space_kb(fdir), &weights[..num_buffers]),
+            tx_kb: split_space(TX_PACKET_BUFFER_SIZE_KB, &weights[..num_buffers]),
+            fdir,
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    /// A layout with explicit sizes, which is validated.
+    pub fn from_sizes(
+        rx_kb: [u32; MAX_PACKET_BUFFERS],
+        tx_kb: [u32; MAX_PACKET_BUFFERS],
+        fdir: Option<FdirPballoc>,
+    ) -> Result<PacketBufferLayout, &'static str> {
+        let layout = PacketBufferLayout { rx_kb, tx_kb, fdir };
+        layout.validate()?;
+        Ok(layout)
+    }
+
+    /// Reads the layout programmed in the hardware. The Flow Director reservation isn't known from these registers.
+    pub fn read(rx_regs2: &IntelIxgbeRxRegisters2, mac_regs: &IntelIxgbeMacRegisters) -> PacketBufferLayout {
+        let mut layout = PacketBufferLayout { rx_kb: [0; MAX_PACKET_BUFFERS], tx_kb: [0; MAX_PACKET_BUFFERS], fdir: None };
+        for buffer in 0..MAX_PACKET_BUFFERS {
+            layout.rx_kb[buffer] = rx_buffer_size_kb(rx_regs2, buffer);
+            layout.tx_kb[buffer] = mac_regs.transmit_packet_buffer_size[buffer].read().get_bits(TXPBSIZE_SIZE);
+        }
+        layout
+    }
+
+    /// Checks that:
+    /// - the used buffers come first, with both an Rx and a Tx size
+    /// - the Rx sizes fit next to the Flow Director table and the Tx sizes fit in the Tx packet buffer
+    /// - every used Tx buffer can hold a packet of the largest size above its threshold
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let num_buffers = self.num_buffers();
+        if num_buffers == 0 {
+            return Err("at least one packet buffer must be used");
+        }
+        for buffer in 0..MAX_PACKET_BUFFERS {
+            let used = buffer < num_buffers;
+            if used != (self.rx_kb[buffer] > 0) || used != (self.tx_kb[buffer] > 0) {
+                return Err("used packet buffers must come first and have both an Rx and a Tx size");
+            }
+            if used && self.tx_kb[buffer] <= TX_MAX_PACKET_SIZE_KB {
+                return Err("Tx packet buffer is too small for the largest packet");
+            }
+        }
+        if self.rx_kb.iter().sum::<u32>() > rx_space_kb(self.fdir) {
+            return Err("Rx packet buffers don't fit in the Rx packet buffer");
+        }
+        if self.tx_kb.iter().sum::<u32>() > TX_PACKET_BUFFER_SIZE_KB {
+            return Err("Tx packet buffers don't fit in the Tx packet buffer");
+        }
+        Ok(())
+    }
+
+    /// The number of used buffers, which are the ones with an Rx size.
+    pub fn num_buffers(&self) -> usize {
+        self.rx_kb.iter().take_while(|&&size| size > 0).count()
+    }
+
+    pub fn rx_kb(&self) -> &[u32; MAX_PACKET_BUFFERS] {
+        &self.rx_kb
+    }
+
+    pub fn tx_kb(&self) -> &[u32; MAX_PACKET_BUFFERS] {
+        &self.tx_kb
+    }
+
+    /// The Tx threshold of a buffer in KB, which is 0 for unused buffers.
+    pub fn tx_threshold_kb(&self, buffer: usize) -> u32 {
+        self.tx_kb.get(buffer).map_or(0, |size| size.saturating_sub(TX_MAX_PACKET_SIZE_KB))
+    }
+
+    pub fn fdir(&self) -> Option<FdirPballoc> {
+        self.fdir
+    }
+
+    /// Writes the sizes and Tx thresholds of every buffer, after validating them again.
+    pub fn write(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<(), &'static str> {
+        self.validate()?;
+        for buffer in 0..MAX_PACKET_BUFFERS {
+            let mut rxpbsize = 0;
+            rxpbsize.set_bits(RXPBSIZE_SIZE, self.rx_kb[buffer]);
+            rx_regs2.receive_packet_buffer_size[buffer].write(rxpbsize);
+
+            let mut txpbsize = 0;
+            txpbsize.set_bits(TXPBSIZE_SIZE, self.tx_kb[buffer]);
+            mac_regs.transmit_packet_buffer_size[buffer].write(txpbsize);
+
+            let mut txpbthresh = 0;
+            txpbthresh.set_bits(TXPBTHRESH_THRESH, self.tx_threshold_kb(buffer));
+            rx_regs2.tx_packet_buffer_threshold[buffer].write(txpbthresh);
+        }
+        Ok(())
+    }
+}
+
+/// The size of an Rx packet buffer in KB, as programmed in RXPBSIZE.
+pub(crate) fn rx_buffer_size_kb(rx_regs2: &IntelIxgbeRxRegisters2, buffer: usize) -> u32 {
+    rx_regs2.receive_packet_buffer_size[buffer].read().get_bits(RXPBSIZE_SIZE)
+}
+
+/// The Rx space left for the packet buffers next to the Flow Director table.
+fn rx_space_kb(fdir: Option<FdirPballoc>) -> u32 {
+    RX_PACKET_BUFFER_SIZE_KB - fdir.map_or(0, |pballoc| pballoc.size_kb())
+}
+
+/// Splits `space_kb` in proportion to the weights, giving the rounding leftover to the first buffer.
+fn split_space(space_kb: u32, weights: &[u32]) -> [u32; MAX_PACKET_BUFFERS] {
+    let total_weight: u32 = weights.iter().sum();
+    let mut sizes = [0; MAX_PACKET_BUFFERS];
+    for (size, &weight) in sizes.iter_mut().zip(weights) {
+        *size = space_kb * weight / total_weight;
+    }
+    sizes[0] += space_kb - sizes.iter().sum::<u32>();
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_splits_share_the_buffers() {
+        let layout = PacketBufferLayout::split(1, BufferSplit::Equal, None).unwrap();
+        assert_eq!(layout.rx_kb(), &[512, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(layout.tx_kb(), &[160, 0, 0, 0, 0, 0, 0, 0]);
+
+        let layout = PacketBufferLayout::split(8, BufferSplit::Equal, None).unwrap();
+        assert_eq!(layout.rx_kb(), &[64; MAX_PACKET_BUFFERS]);
+        assert_eq!(layout.tx_kb(), &[20; MAX_PACKET_BUFFERS]);
+        assert_eq!(layout.tx_threshold_kb(7), 10);
+        assert_eq!(layout.num_buffers(), 8);
+    }
+
+    #[test]
+    fn weighted_splits_give_the_leftover_to_the_first_buffer() {
+        let layout = PacketBufferLayout::split(3, BufferSplit::Weighted(&[3, 1, 1]), None).unwrap();
+        assert_eq!(layout.rx_kb()[..4], [308, 102, 102, 0]);
+        assert_eq!(layout.tx_kb()[..4], [96, 32, 32, 0]);
+        assert_eq!(layout.tx_threshold_kb(0), 86);
+        assert_eq!(layout.tx_threshold_kb(3), 0);
+        assert_eq!(layout.tx_threshold_kb(MAX_PACKET_BUFFERS), 0);
+    }
+
+    #[test]
+    fn flow_director_space_is_left_out_of_the_rx_split() {
+        let layout = PacketBufferLayout::split(2, BufferSplit::Equal, Some(FdirPballoc::Size64K)).unwrap();
+        assert_eq!(layout.rx_kb()[..2], [224, 224]);
+        assert_eq!(layout.tx_kb()[..2], [80, 80]);
+        assert_eq!(layout.fdir(), Some(FdirPballoc::Size64K));
+
+        let mut rx_kb = [0; MAX_PACKET_BUFFERS];
+        rx_kb[0] = RX_PACKET_BUFFER_SIZE_KB;
+        let mut tx_kb = [0; MAX_PACKET_BUFFERS];
+        tx_kb[0] = TX_PACKET_BUFFER_SIZE_KB;
+        assert!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, None).is_ok());
+        assert!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, Some(FdirPballoc::Size64K)).is_err());
+    }
+
+    #[test]
+    fn invalid_splits_are_rejected() {
+        assert!(PacketBufferLayout::split(0, BufferSplit::Equal, None).is_err());
+        assert!(PacketBufferLayout::split(MAX_PACKET_BUFFERS + 1, BufferSplit::Equal, None).is_err());
+        assert!(PacketBufferLayout::split(2, BufferSplit::Weighted(&[1]), None).is_err());
+        assert!(PacketBufferLayout::split(2, BufferSplit::Weighted(&[1, 0]), None).is_err());
+        // the second Tx buffer would get 10 KB, which leaves no room above its threshold
+        assert!(PacketBufferLayout::split(2, BufferSplit::Weighted(&[15, 1]), None).is_err());
+    }
+
+    #[test]
+    fn used_buffers_must_come_first_with_both_sizes() {
+        let mut rx_kb = [0; MAX_PACKET_BUFFERS];
+        let mut tx_kb = [0; MAX_PACKET_BUFFERS];
+        assert!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, None).is_err());
+        rx_kb[0] = 256;
+        assert!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, None).is_err());
+        tx_kb[0] = 80;
+        rx_kb[2] = 256;
+        tx_kb[2] = 80;
+        assert!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, None).is_err());
+        rx_kb.swap(1, 2);
+        tx_kb.swap(1, 2);
+        assert_eq!(PacketBufferLayout::from_sizes(rx_kb, tx_kb, None).map(|layout| layout.num_buffers()), Ok(2));
+    }
+}
@@ -0,0 +1,794 @@
+//! IEEE 1588 (PTP) hardware clock over the TimeSync registers.
+//!
+//! The 82599 keeps a 64-bit system time (SYSTIM) that is incremented by TIMINCA.INCVALUE
+//! on every period of the DMA clock, and latches SYSTIM when a PTP event message is received or sent.
+//! SYSTIM doesn't count nanoseconds directly: the increment is scaled up by `shift` bits so that the
+//! frequency can be tuned finely, and the driver converts SYSTIM values to nanoseconds.
+//! Since SYSTIM wraps every few hours, [`PtpClock`] extends it to a 64-bit nanosecond clock in software.
+//!
+//! All register accesses go through the [`TimeSyncRegisters`] trait, which is implemented for the device
+//! by [`IxgbeTimeSync`] and for tests by [`SimulatedTimeSync`].
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::link::LinkSpeed;
+use crate::queue_filter::{EtqfSlots, EtqfUser, ETH_P_1588, NUM_ETQF_FILTERS};
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// The largest frequency adjustment the increment value allows, in parts per billion.
+pub const PTP_MAX_ADJ_PPB: i64 = 250_000_000;
+
+// Increment values for the DMA clock period of each link speed, with the matching SYSTIM shift.
+// SYSTIM is incremented every DMA clock cycle (incperiod of 1).
+const INCVAL_10G: u32 = 0x66666666 >> INCVAL_SHIFT_82599;
+const INCVAL_1G: u32 = 0x40000000 >> INCVAL_SHIFT_82599;
+const INCVAL_100M: u32 = 0x50000000 >> INCVAL_SHIFT_82599;
+const INCVAL_SHIFT_10G: u32 = 28 - INCVAL_SHIFT_82599;
+const INCVAL_SHIFT_1G: u32 = 24 - INCVAL_SHIFT_82599;
+const INCVAL_SHIFT_100M: u32 = 21 - INCVAL_SHIFT_82599;
+/// The 82599 only has 24 bits of increment value, so the increments are scaled down by this many bits.
+const INCVAL_SHIFT_82599: u32 = 7;
+
+// Increment Attributes Register
+const TIMINCA_INCVALUE: core::ops::Range<usize> = 0..24;
+const TIMINCA_INCPERIOD: core::ops::Range<usize> = 24..32;
+
+// Time Adjustment Offset Register High
+const TIMADJH_SIGN: usize = 31;
+
+// Rx Time Sync Control Register
+const TSYNCRXCTL_VALID: usize = 0;
+const TSYNCRXCTL_TYPE: core::ops::Range<usize> = 1..4;
+const TSYNCRXCTL_EN: usize = 4;
+const TSYNCRXCTL_TYPE_L4_V1: u32 = 0x1;
+const TSYNCRXCTL_TYPE_EVENT_V2: u32 = 0x5;
+const RXMTRL_V1_SYNC_MSG: u32 = 0x00;
+const RXMTRL_V1_DELAY_REQ_MSG: u32 = 0x01;
+
+// Tx Time Sync Control Register
+const TSYNCTXCTL_VALID: usize = 0;
+const TSYNCTXCTL_EN: usize = 4;
+
+// TimeSync Auxiliary Control Register
+const TSAUXC_EN_TT0: usize = 0;
+const TSAUXC_EN_CLK0: usize = 2;
+const TSAUXC_EN_TS0: usize = 8;
+const TSAUXC_AUTT0: usize = 9;
+
+// Time Sync SDP Configuration Register
+const TSSDP_AUX_SDP_SEL_SHIFT: usize = 0;
+const TSSDP_AUX_TS_SDP_EN_SHIFT: usize = 2;
+const TSSDP_TS_SDP_SEL_SHIFT: usize = 6;
+const TSSDP_TS_SDP_EN_SHIFT: usize = 8;
+const TSSDP_TS_SDP_SEL_CLK0: u32 = 0b10;
+
+// Extended SDP Control
+const ESDP_SDP_IODIR_SHIFT: usize = 8;
+const ESDP_SDP_NATIVE_SHIFT: usize = 16;
+
+const ETQF_ETHER_TYPE: core::ops::Range<usize> = 0..16;
+const ETQF_1588: usize = 30;
+const ETQF_FILTER_EN: usize = 31;
+
+/// Number of software definable pins that can be routed to TimeSync functions.
+pub const NUM_TIMESYNC_SDPS: u8 = 4;
+
+/// The TimeSync registers, as accessed through [`TimeSyncRegisters`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSyncRegister {
+    Tsyncrxctl,
+    Rxmtrl,
+    Rxstmpl,
+    Rxstmph,
+    Rxsatrl,
+    Rxsatrh,
+    Tsynctxctl,
+    Txstmpl,
+    Txstmph,
+    Systiml,
+    Systimh,
+    Timinca,
+    Timadjl,
+    Timadjh,
+    Tsauxc,
+    Trgttiml0,
+    Trgttimh0,
+    Trgttiml1,
+    Trgttimh1,
+    Freqout0,
+    Freqout1,
+    Auxstmpl0,
+    Auxstmph0,
+    Auxstmpl1,
+    Auxstmph1,
+    Tssdp,
+    Esdp,
+}
+
+const NUM_TIMESYNC_REGISTERS: usize = TimeSyncRegister::Esdp as usize + 1;
+
+/// Access to the TimeSync registers.
+///
+/// Reads take `&mut self` because several of them have side effects:
+/// reading SYSTIML latches SYSTIMH, and reading the high half of a timestamp releases it.
+/// The EtherType filter that recognizes layer 2 PTP packets is allocated from [`EtqfSlots`],
+/// so it's accessed by index.
+pub trait TimeSyncRegisters {
+    fn read(&mut self, reg: TimeSyncRegister) -> u32;
+    fn write(&mut self, reg: TimeSyncRegister, value: u32);
+    fn read_etqf(&mut self, index: usize) -> u32;
+    fn write_etqf(&mut self, index: usize, value: u32);
+}
+
+/// The TimeSync registers of the device, which are spread across three register regions.
+pub struct IxgbeTimeSync<'a> {
+    pub regs1: &'a mut IntelIxgbeRegisters1,
+    pub rx_regs2: &'a mut IntelIxgbeRxRegisters2,
+    pub mac_regs: &'a mut IntelIxgbeMacRegisters,
+}
+
+impl TimeSyncRegisters for IxgbeTimeSync<'_> {
+    fn read(&mut self, reg: TimeSyncRegister) -> u32 {
+        use TimeSyncRegister::*;
+        match reg {
+            Tsyncrxctl => self.rx_regs2.rx_time_sync_control_register.read(),
+            Rxmtrl => self.rx_regs2.rx_message_type_register_low.read(),
+            Rxstmpl => self.rx_regs2.rx_timestamp_low.read(),
+            Rxstmph => self.rx_regs2.rx_timestamp_high.read(),
+            Rxsatrl => self.rx_regs2.rx_timestamp_attributes_low.read(),
+            Rxsatrh => self.rx_regs2.rx_timestamp_attributes_high.read(),
+            Tsynctxctl => self.mac_regs.tx_time_sync_control_register.read(),
+            Txstmpl => self.mac_regs.tx_timestamp_value_low.read(),
+            Txstmph => self.mac_regs.tx_timestamp_value_high.read(),
+            Systiml => self.mac_regs.system_time_register_low.read(),
+            Systimh => self.mac_regs.system_time_register_high.read(),
+            Timinca => self.mac_regs.increment_attributes_register.read(),
+            Timadjl => self.mac_regs.time_adjustment_offset_register_low.read(),
+            Timadjh => self.mac_regs.time_adjustment_offset_register_high.read(),
+            Tsauxc => self.mac_regs.timesync_auxiliary_control_register.read(),
+            Trgttiml0 => self.mac_regs.target_time_register_0_low.read(),
+            Trgttimh0 => self.mac_regs.target_time_register_0_high.read(),
+            Trgttiml1 => self.mac_regs.target_time_register_1_low.read(),
+            Trgttimh1 => self.mac_regs.target_time_register_1_high.read(),
+            Freqout0 => self.mac_regs.frequency_out_0_control_register.read(),
+            Freqout1 => self.mac_regs.frequency_out_1_control_register.read(),
+            Auxstmpl0 => self.mac_regs.auxiliary_time_stamp_0_register_low.read(),
+            Auxstmph0 => self.mac_regs.auxiliary_time_stamp_0_register_high.read(),
+            Auxstmpl1 => self.mac_regs.auxiliary_time_stamp_1_register_low.read(),
+            Auxstmph1 => self.mac_regs.auxiliary_time_stamp_1_register_high.read(),
+            Tssdp => self.regs1.time_sync_sdp_configuration_register.read(),
+            Esdp => self.regs1.extended_sdp_control.read(),
+        }
+    }
+
+    fn write(&mut self, reg: TimeSyncRegister, value: u32) {
+        use TimeSyncRegister::*;
+        match reg {
+            Tsyncrxctl => self.rx_regs2.rx_time_sync_control_register.write(value),
+            Rxmtrl => self.rx_regs2.rx_message_type_register_low.write(value),
+            Tsynctxctl => self.mac_regs.tx_time_sync_control_register.write(value),
+            Systiml => self.mac_regs.system_time_register_low.write(value),
+            Systimh => self.mac_regs.system_time_register_high.write(value),
+            Timinca => self.mac_regs.increment_attributes_register.write(value),
+            Timadjl => self.mac_regs.time_adjustment_offset_register_low.write(value),
+            Timadjh => self.mac_regs.time_adjustment_offset_register_high.write(value),
+            Tsauxc => self.mac_regs.timesync_auxiliary_control_register.write(value),
+            Trgttiml0 => self.mac_regs.target_time_register_0_low.write(value),
+            Trgttimh0 => self.mac_regs.target_time_register_0_high.write(value),
+            Trgttiml1 => self.mac_regs.target_time_register_1_low.write(value),
+            Trgttimh1 => self.mac_regs.target_time_register_1_high.write(value),
+            Freqout0 => self.mac_regs.frequency_out_0_control_register.write(value),
+            Freqout1 => self.mac_regs.frequency_out_1_control_register.write(value),
+            Tssdp => self.regs1.time_sync_sdp_configuration_register.write(value),
+            Esdp => self.regs1.extended_sdp_control.write(value),
+            // the timestamp registers are read-only
+            Rxstmpl | Rxstmph | Rxsatrl | Rxsatrh | Txstmpl | Txstmph | Auxstmpl0 | Auxstmph0 | Auxstmpl1 | Auxstmph1 => {}
+        }
+    }
+
+    fn read_etqf(&mut self, index: usize) -> u32 {
+        self.rx_regs2.etq_filter[index].read()
+    }
+
+    fn write_etqf(&mut self, index: usize, value: u32) {
+        self.rx_regs2.etq_filter[index].write(value)
+    }
+}
+
+/// Which received PTP messages are timestamped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxTimestampFilter {
+    None,
+    /// PTPv1 Sync messages over UDP.
+    V1L4Sync,
+    /// PTPv1 Delay_Req messages over UDP.
+    V1L4DelayReq,
+    /// PTPv2 event messages over UDP or layer 2.
+    V2Event,
+}
+
+/// The Rx timestamp of a PTP event message, along with the fields that identify the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxTimestamp {
+    pub ns: u64,
+    pub sequence_id: u16,
+    pub source_uuid: u64,
+}
+
+/// One of the two target time registers, which trigger an output on an SDP when SYSTIM reaches them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetTime {
+    Target0,
+    Target1,
+}
+
+/// One of the two auxiliary timestamps, which latch SYSTIM on an edge of an SDP input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxTimestamp {
+    Aux0,
+    Aux1,
+}
+
+/// A PTP hardware clock.
+///
+/// SYSTIM values are converted to nanoseconds by keeping the nanosecond time of the last SYSTIM value
+/// that was read, in the manner of a timecounter. The clock has to be read at least once every half
+/// SYSTIM wrap period (over an hour at 10 Gb/s) for the conversion to stay correct.
+pub struct PtpClock<R: TimeSyncRegisters> {
+    regs: R,
+    base_incval: u32,
+    shift: u32,
+    /// The last SYSTIM value that was read, and its time in nanoseconds.
+    systim_last: u64,
+    ns_last: u64,
+    /// The fraction of a nanosecond of `systim_last` that isn't accounted for in `ns_last`, in SYSTIM units.
+    frac: u64,
+    /// The EtherType filter of layer 2 PTP packets, while they're timestamped.
+    etqf_index: Option<usize>,
+}
+
+impl<R: TimeSyncRegisters> PtpClock<R> {
+    /// Starts SYSTIM counting at the rate for the given link speed, which sets the DMA clock period,
+    /// and sets the clock to `ns`.
+    pub fn new(regs: R, speed: LinkSpeed, ns: u64) -> PtpClock<R> {
+        let (base_incval, shift) = match speed {
+            LinkSpeed::Speed100M => (INCVAL_100M, INCVAL_SHIFT_100M),
+            LinkSpeed::Speed1G => (INCVAL_1G, INCVAL_SHIFT_1G),
+            LinkSpeed::Speed10G => (INCVAL_10G, INCVAL_SHIFT_10G),
+        };
+        let mut clock = PtpClock { regs, base_incval, shift, systim_last: 0, ns_last: 0, frac: 0, etqf_index: None };
+        clock.write_timinca(base_incval);
+        clock.set_time(ns);
+        clock
+    }
+
+    /// Returns the register interface, e.g. to advance a simulated clock.
+    pub fn registers(&mut self) -> &mut R {
+        &mut self.regs
+    }
+
+    /// Returns the current time in nanoseconds.
+    pub fn now(&mut self) -> u64 {
+        let systim = self.read_systim();
+        let elapsed = systim.wrapping_sub(self.systim_last) as u128 + self.frac as u128;
+        self.ns_last = self.ns_last.wrapping_add((elapsed >> self.shift) as u64);
+        self.frac = (elapsed & ((1 << self.shift) - 1)) as u64;
+        self.systim_last = systim;
+        self.ns_last
+    }
+
+    /// Sets the clock to `ns`, by writing SYSTIM.
+    pub fn set_time(&mut self, ns: u64) {
+        let systim = ns << self.shift;
+        self.regs.write(TimeSyncRegister::Systiml, systim as u32);
+        self.regs.write(TimeSyncRegister::Systimh, (systim >> 32) as u32);
+        self.systim_last = systim;
+        self.ns_last = ns;
+        self.frac = 0;
+    }
+
+    /// Steps the clock by `delta_ns` through the time adjustment registers,
+    /// which the hardware adds to SYSTIM on its next increment.
+    pub fn adjust_time(&mut self, delta_ns: i64) {
+        self.now();
+        let magnitude = delta_ns.unsigned_abs() << self.shift;
+        let mut timadjh = (magnitude >> 32) as u32;
+        timadjh.set_bit(TIMADJH_SIGN, delta_ns < 0);
+        self.regs.write(TimeSyncRegister::Timadjl, magnitude as u32);
+        self.regs.write(TimeSyncRegister::Timadjh, timadjh);
+
+        // account for the step as if it had been read back, so it isn't mistaken for elapsed time
+        if delta_ns < 0 {
+            self.systim_last = self.systim_last.wrapping_sub(magnitude);
+            self.ns_last = self.ns_last.wrapping_sub(delta_ns.unsigned_abs());
+        } else {
+            self.systim_last = self.systim_last.wrapping_add(magnitude);
+            self.ns_last = self.ns_last.wrapping_add(delta_ns as u64);
+        }
+    }
+
+    /// Speeds up or slows down the clock by `ppb` parts per billion, relative to the nominal rate.
+    pub fn adjust_frequency(&mut self, ppb: i64) -> Result<(), &'static str> {
+        if ppb.abs() > PTP_MAX_ADJ_PPB {
+            return Err("PTP frequency adjustment is out of range");
+        }
+        let diff = (self.base_incval as u64 * ppb.unsigned_abs() / NS_PER_SEC) as u32;
+        let incval = if ppb < 0 { self.base_incval - diff } else { self.base_incval + diff };
+        // changes to the rate only affect SYSTIM from now on, which is already accounted for
+        self.now();
+        self.write_timinca(incval);
+        Ok(())
+    }
+
+    /// Enables timestamping of received PTP messages that pass `filter`, and of transmitted packets
+    /// whose descriptor requests a timestamp.
+    ///
+    /// Layer 2 PTP messages are recognized by the EtherType filter of PTP, which is acquired from `slots`
+    /// and shared with queue steering of PTP packets. When those messages stop being timestamped,
+    /// only the timestamping bit of the filter is cleared, unless nothing else uses the filter.
+    pub fn enable_timestamping(&mut self, slots: &mut EtqfSlots, filter: RxTimestampFilter, tx: bool) -> Result<(), &'static str> {
+        let etqf_index = match (filter, self.etqf_index) {
+            (RxTimestampFilter::V2Event, None) => Some(slots.acquire(ETH_P_1588, EtqfUser::Timestamping)?),
+            (RxTimestampFilter::V2Event, index) => index,
+            (_, Some(index)) => {
+                let (_, free) = slots.release(ETH_P_1588, EtqfUser::Timestamping)?;
+                let mut etqf = self.regs.read_etqf(index);
+                etqf.set_bit(ETQF_1588, false);
+                self.regs.write_etqf(index, if free { 0 } else { etqf });
+                None
+            }
+            (_, None) => None,
+        };
+        self.etqf_index = etqf_index;
+
+        let mut tsyncrxctl = self.regs.read(TimeSyncRegister::Tsyncrxctl);
+        let mut rxmtrl = 0;
+        tsyncrxctl.set_bits(TSYNCRXCTL_TYPE, 0);
+        tsyncrxctl.set_bit(TSYNCRXCTL_EN, filter != RxTimestampFilter::None);
+        match filter {
+            RxTimestampFilter::None => {}
+            RxTimestampFilter::V1L4Sync => {
+                tsyncrxctl.set_bits(TSYNCRXCTL_TYPE, TSYNCRXCTL_TYPE_L4_V1);
+                rxmtrl = RXMTRL_V1_SYNC_MSG;
+            }
+            RxTimestampFilter::V1L4DelayReq => {
+                tsyncrxctl.set_bits(TSYNCRXCTL_TYPE, TSYNCRXCTL_TYPE_L4_V1);
+                rxmtrl = RXMTRL_V1_DELAY_REQ_MSG;
+            }
+            RxTimestampFilter::V2Event => {
+                tsyncrxctl.set_bits(TSYNCRXCTL_TYPE, TSYNCRXCTL_TYPE_EVENT_V2);
+            }
+        }
+        if let Some(index) = etqf_index {
+            let mut etqf = self.regs.read_etqf(index);
+            etqf.set_bits(ETQF_ETHER_TYPE, ETH_P_1588 as u32);
+            etqf.set_bit(ETQF_1588, true);
+            etqf.set_bit(ETQF_FILTER_EN, true);
+            self.regs.write_etqf(index, etqf);
+        }
+        self.regs.write(TimeSyncRegister::Rxmtrl, rxmtrl);
+        self.regs.write(TimeSyncRegister::Tsyncrxctl, tsyncrxctl);
+
+        let mut tsynctxctl = self.regs.read(TimeSyncRegister::Tsynctxctl);
+        tsynctxctl.set_bit(TSYNCTXCTL_EN, tx);
+        self.regs.write(TimeSyncRegister::Tsynctxctl, tsynctxctl);
+
+        // clear any stale timestamps, which would otherwise block new ones from being latched
+        self.rx_timestamp();
+        self.tx_timestamp();
+        Ok(())
+    }
+
+    /// Returns the timestamp of the last timestamped PTP message that was received, if there is one.
+    /// Reading it frees the registers to latch the next one.
+    pub fn rx_timestamp(&mut self) -> Option<RxTimestamp> {
+        if !self.regs.read(TimeSyncRegister::Tsyncrxctl).get_bit(TSYNCRXCTL_VALID) {
+            return None;
+        }
+        let source_uuid_low = self.regs.read(TimeSyncRegister::Rxsatrl);
+        let rxsatrh = self.regs.read(TimeSyncRegister::Rxsatrh);
+        let low = self.regs.read(TimeSyncRegister::Rxstmpl);
+        let high = self.regs.read(TimeSyncRegister::Rxstmph);
+        Some(RxTimestamp {
+            ns: self.systim_to_ns(((high as u64) << 32) | low as u64),
+            sequence_id: rxsatrh.get_bits(16..32) as u16,
+            source_uuid: ((rxsatrh.get_bits(0..16) as u64) << 32) | source_uuid_low as u64,
+        })
+    }
+
+    /// Returns the timestamp of the last timestamped packet that was sent, if there is one.
+    /// Reading it frees the registers to latch the next one.
+    pub fn tx_timestamp(&mut self) -> Option<u64> {
+        if !self.regs.read(TimeSyncRegister::Tsynctxctl).get_bit(TSYNCTXCTL_VALID) {
+            return None;
+        }
+        let low = self.regs.read(TimeSyncRegister::Txstmpl);
+        let high = self.regs.read(TimeSyncRegister::Txstmph);
+        Some(self.systim_to_ns(((high as u64) << 32) | low as u64))
+    }
+
+    /// Arms `target` to fire when the clock reaches `ns`.
+    pub fn set_target_time(&mut self, target: TargetTime, ns: u64) {
+        let systim = self.ns_to_systim(ns);
+        let (low, high, enable) = match target {
+            TargetTime::Target0 => (TimeSyncRegister::Trgttiml0, TimeSyncRegister::Trgttimh0, TSAUXC_EN_TT0),
+            TargetTime::Target1 => (TimeSyncRegister::Trgttiml1, TimeSyncRegister::Trgttimh1, TSAUXC_EN_TT0 + 1),
+        };
+        self.regs.write(low, systim as u32);
+        self.regs.write(high, (systim >> 32) as u32);
+        let mut tsauxc = self.regs.read(TimeSyncRegister::Tsauxc);
+        tsauxc.set_bit(enable, true);
+        self.regs.write(TimeSyncRegister::Tsauxc, tsauxc);
+    }
+
+    /// Outputs a pulse-per-second square wave on `sdp`, with its rising edges on whole seconds.
+    ///
+    /// The wave comes from frequency clock 0, which is started on the next whole second by target time 0,
+    /// so target time 0 can't be used for anything else while the output is enabled.
+    pub fn enable_pps(&mut self, sdp: u8) -> Result<(), &'static str> {
+        if sdp >= NUM_TIMESYNC_SDPS {
+            return Err("SDP doesn't support TimeSync outputs");
+        }
+        let sdp = sdp as usize;
+        self.disable_pps();
+
+        let now = self.now();
+        let next_second = (now / NS_PER_SEC + 1) * NS_PER_SEC;
+        self.regs.write(TimeSyncRegister::Freqout0, (NS_PER_SEC / 2) as u32);
+        self.set_target_time(TargetTime::Target0, next_second);
+
+        let mut tssdp = self.regs.read(TimeSyncRegister::Tssdp);
+        let sel = TSSDP_TS_SDP_SEL_SHIFT + 3 * sdp;
+        tssdp.set_bits(sel..sel + 2, TSSDP_TS_SDP_SEL_CLK0);
+        tssdp.set_bit(TSSDP_TS_SDP_EN_SHIFT + 3 * sdp, true);
+        self.regs.write(TimeSyncRegister::Tssdp, tssdp);
+
+        let mut esdp = self.regs.read(TimeSyncRegister::Esdp);
+        esdp.set_bit(ESDP_SDP_IODIR_SHIFT + sdp, true);
+        esdp.set_bit(ESDP_SDP_NATIVE_SHIFT + sdp, true);
+        self.regs.write(TimeSyncRegister::Esdp, esdp);
+
+        let mut tsauxc = self.regs.read(TimeSyncRegister::Tsauxc);
+        tsauxc.set_bit(TSAUXC_EN_CLK0, true);
+        self.regs.write(TimeSyncRegister::Tsauxc, tsauxc);
+        Ok(())
+    }
+
+    /// Stops the pulse-per-second output and disarms target time 0.
+    pub fn disable_pps(&mut self) {
+        let mut tsauxc = self.regs.read(TimeSyncRegister::Tsauxc);
+        tsauxc.set_bit(TSAUXC_EN_CLK0, false);
+        tsauxc.set_bit(TSAUXC_EN_TT0, false);
+        self.regs.write(TimeSyncRegister::Tsauxc, tsauxc);
+    }
+
+    /// Latches the clock into `aux` on every edge of the `sdp` input.
+    pub fn enable_aux_timestamp(&mut self, aux: AuxTimestamp, sdp: u8) -> Result<(), &'static str> {
+        if sdp >= NUM_TIMESYNC_SDPS {
+            return Err("SDP doesn't support TimeSync inputs");
+        }
+        let index = aux as usize;
+        let mut tssdp = self.regs.read(TimeSyncRegister::Tssdp);
+        let sel = TSSDP_AUX_SDP_SEL_SHIFT + 3 * index;
+        tssdp.set_bits(sel..sel + 2, sdp as u32);
+        tssdp.set_bit(TSSDP_AUX_TS_SDP_EN_SHIFT + 3 * index, true);
+        self.regs.write(TimeSyncRegister::Tssdp, tssdp);
+
+        let mut tsauxc = self.regs.read(TimeSyncRegister::Tsauxc);
+        tsauxc.set_bit(TSAUXC_EN_TS0 + 2 * index, true);
+        self.regs.write(TimeSyncRegister::Tsauxc, tsauxc);
+        Ok(())
+    }
+
+    /// Returns the time latched in `aux`, if an edge has occurred since it was last read.
+    pub fn aux_timestamp(&mut self, aux: AuxTimestamp) -> Option<u64> {
+        let index = aux as usize;
+        if !self.regs.read(TimeSyncRegister::Tsauxc).get_bit(TSAUXC_AUTT0 + 2 * index) {
+            return None;
+        }
+        let (low, high) = match aux {
+            AuxTimestamp::Aux0 => (TimeSyncRegister::Auxstmpl0, TimeSyncRegister::Auxstmph0),
+            AuxTimestamp::Aux1 => (TimeSyncRegister::Auxstmpl1, TimeSyncRegister::Auxstmph1),
+        };
+        let low = self.regs.read(low);
+        let high = self.regs.read(high);
+        Some(self.systim_to_ns(((high as u64) << 32) | low as u64))
+    }
+
+    /// Reads SYSTIM. Reading the low register latches the high register.
+    fn read_systim(&mut self) -> u64 {
+        let low = self.regs.read(TimeSyncRegister::Systiml);
+        let high = self.regs.read(TimeSyncRegister::Systimh);
+        ((high as u64) << 32) | low as u64
+    }
+
+    fn write_timinca(&mut self, incval: u32) {
+        let mut timinca = 0;
+        timinca.set_bits(TIMINCA_INCPERIOD, 1);
+        timinca.set_bits(TIMINCA_INCVALUE, incval);
+        self.regs.write(TimeSyncRegister::Timinca, timinca);
+    }
+
+    /// Converts a SYSTIM value that is close to the last one read to nanoseconds.
+    /// Values up to half a wrap period before the last read are treated as being in the past.
+    fn systim_to_ns(&self, systim: u64) -> u64 {
+        let delta = systim.wrapping_sub(self.systim_last);
+        if delta > u64::MAX / 2 {
+            let before = self.systim_last.wrapping_sub(systim).saturating_sub(self.frac);
+            self.ns_last.wrapping_sub(before >> self.shift)
+        } else {
+            self.ns_last.wrapping_add(((delta as u128 + self.frac as u128) >> self.shift) as u64)
+        }
+    }
+
+    /// Converts a time in nanoseconds that is close to the last read one to a SYSTIM value.
+    fn ns_to_systim(&self, ns: u64) -> u64 {
+        let delta = (ns.wrapping_sub(self.ns_last) << self.shift).wrapping_sub(self.frac);
+        self.systim_last.wrapping_add(delta)
+    }
+}
+
+/// A software model of the TimeSync registers, which advances SYSTIM as the device would.
+///
+/// Timestamps are latched by calling [`SimulatedTimeSync::receive_event`],
+/// [`SimulatedTimeSync::transmit_event`] and [`SimulatedTimeSync::sdp_edge`].
+pub struct SimulatedTimeSync {
+    regs: [u32; NUM_TIMESYNC_REGISTERS],
+    etqf: [u32; NUM_ETQF_FILTERS],
+    systim: u64,
+    latched_systimh: u32,
+    /// DMA clock cycles since SYSTIM was last incremented.
+    cycles: u32,
+}
+
+impl Default for SimulatedTimeSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedTimeSync {
+    pub fn new() -> SimulatedTimeSync {
+        SimulatedTimeSync {
+            regs: [0; NUM_TIMESYNC_REGISTERS],
+            etqf: [0; NUM_ETQF_FILTERS],
+            systim: 0,
+            latched_systimh: 0,
+            cycles: 0,
+        }
+    }
+
+    /// Runs the clock for `cycles` DMA clock cycles.
+    pub fn advance(&mut self, cycles: u64) {
+        let timinca = self.regs[TimeSyncRegister::Timinca as usize];
+        let incperiod = timinca.get_bits(TIMINCA_INCPERIOD).max(1);
+        let incvalue = timinca.get_bits(TIMINCA_INCVALUE) as u64;
+        let cycles = self.cycles as u64 + cycles;
+        let increments = cycles / incperiod as u64;
+        self.cycles = (cycles % incperiod as u64) as u32;
+        if increments > 0 {
+            self.systim = self.systim.wrapping_add(incvalue.wrapping_mul(increments));
+            self.apply_adjustment();
+        }
+    }
+
+    /// Latches SYSTIM as the Rx timestamp of a PTP message, unless a timestamp is already pending.
+    pub fn receive_event(&mut self, sequence_id: u16, source_uuid: u64) {
+        let tsyncrxctl = &mut self.regs[TimeSyncRegister::Tsyncrxctl as usize];
+        if !tsyncrxctl.get_bit(TSYNCRXCTL_EN) || tsyncrxctl.get_bit(TSYNCRXCTL_VALID) {
+            return;
+        }
+        tsyncrxctl.set_bit(TSYNCRXCTL_VALID, true);
+        self.regs[TimeSyncRegister::Rxstmpl as usize] = self.systim as u32;
+        self.regs[TimeSyncRegister::Rxstmph as usize] = (self.systim >> 32) as u32;
+        self.regs[TimeSyncRegister::Rxsatrl as usize] = source_uuid as u32;
+        self.regs[TimeSyncRegister::Rxsatrh as usize] = ((sequence_id as u32) << 16) | (source_uuid >> 32) as u16 as u32;
+    }
+
+    /// Latches SYSTIM as the Tx timestamp of a packet, unless a timestamp is already pending.
+    pub fn transmit_event(&mut self) {
+        let tsynctxctl = &mut self.regs[TimeSyncRegister::Tsynctxctl as usize];
+        if !tsynctxctl.get_bit(TSYNCTXCTL_EN) || tsynctxctl.get_bit(TSYNCTXCTL_VALID) {
+            return;
+        }
+        tsynctxctl.set_bit(TSYNCTXCTL_VALID, true);
+        self.regs[TimeSyncRegister::Txstmpl as usize] = self.systim as u32;
+        self.regs[TimeSyncRegister::Txstmph as usize] = (self.systim >> 32) as u32;
+    }
+
+    /// Latches SYSTIM into the auxiliary timestamps that are enabled on `sdp`.
+    pub fn sdp_edge(&mut self, sdp: u8) {
+        let tssdp = self.regs[TimeSyncRegister::Tssdp as usize];
+        for (index, (low, high)) in [
+            (TimeSyncRegister::Auxstmpl0, TimeSyncRegister::Auxstmph0),
+            (TimeSyncRegister::Auxstmpl1, TimeSyncRegister::Auxstmph1),
+        ].into_iter().enumerate() {
+            let sel = TSSDP_AUX_SDP_SEL_SHIFT + 3 * index;
+            let tsauxc = &mut self.regs[TimeSyncRegister::Tsauxc as usize];
+            if !tssdp.get_bit(TSSDP_AUX_TS_SDP_EN_SHIFT + 3 * index)
+                || tssdp.get_bits(sel..sel + 2) != sdp as u32
+                || !tsauxc.get_bit(TSAUXC_EN_TS0 + 2 * index)
+                || tsauxc.get_bit(TSAUXC_AUTT0 + 2 * index)
+            {
+                continue;
+            }
+            tsauxc.set_bit(TSAUXC_AUTT0 + 2 * index, true);
+            self.regs[low as usize] = self.systim as u32;
+            self.regs[high as usize] = (self.systim >> 32) as u32;
+        }
+    }
+
+    /// Adds a pending TIMADJ offset to SYSTIM, as the device does on the increment after it's written.
+    fn apply_adjustment(&mut self) {
+        let timadjl = self.regs[TimeSyncRegister::Timadjl as usize];
+        let timadjh = self.regs[TimeSyncRegister::Timadjh as usize];
+        if timadjl == 0 && timadjh == 0 {
+            return;
+        }
+        let magnitude = ((timadjh.get_bits(0..31) as u64) << 32) | timadjl as u64;
+        self.systim = if timadjh.get_bit(TIMADJH_SIGN) {
+            self.systim.wrapping_sub(magnitude)
+        } else {
+            self.systim.wrapping_add(magnitude)
+        };
+        self.regs[TimeSyncRegister::Timadjl as usize] = 0;
+        self.regs[TimeSyncRegister::Timadjh as usize] = 0;
+    }
+}
+
+impl TimeSyncRegisters for SimulatedTimeSync {
+    fn read(&mut self, reg: TimeSyncRegister) -> u32 {
+        use TimeSyncRegister::*;
+        match reg {
+            Systiml => {
+                self.latched_systimh = (self.systim >> 32) as u32;
+                self.systim as u32
+            }
+            Systimh => self.latched_systimh,
+            Rxstmph => {
+                self.regs[Tsyncrxctl as usize].set_bit(TSYNCRXCTL_VALID, false);
+                self.regs[Rxstmph as usize]
+            }
+            Txstmph => {
+                self.regs[Tsynctxctl as usize].set_bit(TSYNCTXCTL_VALID, false);
+                self.regs[Txstmph as usize]
+            }
+            Auxstmph0 | Auxstmph1 => {
+                let autt = TSAUXC_AUTT0 + if reg == Auxstmph0 { 0 } else { 2 };
+                self.regs[Tsauxc as usize].set_bit(autt, false);
+                self.regs[reg as usize]
+            }
+            _ => self.regs[reg as usize],
+        }
+    }
+
+    fn write(&mut self, reg: TimeSyncRegister, value: u32) {
+        use TimeSyncRegister::*;
+        match reg {
+            Systiml => self.systim = (self.systim & !0xFFFF_FFFF) | value as u64,
+            Systimh => self.systim = (self.systim & 0xFFFF_FFFF) | ((value as u64) << 32),
+            // the valid bits are read-only
+            Tsyncrxctl => {
+                let valid = self.regs[Tsyncrxctl as usize].get_bit(TSYNCRXCTL_VALID);
+                self.regs[Tsyncrxctl as usize] = *value.clone().set_bit(TSYNCRXCTL_VALID, valid);
+            }
+            Tsynctxctl => {
+                let valid = self.regs[Tsynctxctl as usize].get_bit(TSYNCTXCTL_VALID);
+                self.regs[Tsynctxctl as usize] = *value.clone().set_bit(TSYNCTXCTL_VALID, valid);
+            }
+            Tsauxc => {
+                let latched = self.regs[Tsauxc as usize] & ((1 << TSAUXC_AUTT0) | (1 << (TSAUXC_AUTT0 + 2)));
+                self.regs[Tsauxc as usize] = (value & !((1 << TSAUXC_AUTT0) | (1 << (TSAUXC_AUTT0 + 2)))) | latched;
+            }
+            Rxstmpl | Rxstmph | Rxsatrl | Rxsatrh | Txstmpl | Txstmph | Auxstmpl0 | Auxstmph0 | Auxstmpl1 | Auxstmph1 => {}
+            _ => self.regs[reg as usize] = value,
+        }
+    }
+
+    fn read_etqf(&mut self, index: usize) -> u32 {
+        self.etqf[index]
+    }
+
+    fn write_etqf(&mut self, index: usize, value: u32) {
+        self.etqf[index] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock() -> PtpClock<SimulatedTimeSync> {
+        PtpClock::new(SimulatedTimeSync::new(), LinkSpeed::Speed10G, 1_000)
+    }
+
+    #[test]
+    fn clock_runs_at_6_4_ns_per_cycle_at_10g() {
+        let mut clock = clock();
+        assert_eq!(clock.now(), 1_000);
+        clock.registers().advance(1_000_000);
+        assert_eq!(clock.now(), 1_000 + 6_399_999);
+        // the fraction of a nanosecond carries over to the next read
+        clock.registers().advance(1);
+        assert_eq!(clock.now(), 1_000 + 6_400_006);
+    }
+
+    #[test]
+    fn advance_counts_increment_periods() {
+        let mut regs = SimulatedTimeSync::new();
+        regs.write(TimeSyncRegister::Timinca, (3 << 24) | 10);
+        regs.advance(7);
+        regs.advance(1 << 40);
+        // (7 + 2^40) / 3 increments of 10
+        let low = regs.read(TimeSyncRegister::Systiml) as u64;
+        let high = regs.read(TimeSyncRegister::Systimh) as u64;
+        assert_eq!((high << 32) | low, ((7 + (1 << 40)) / 3) * 10);
+    }
+
+    #[test]
+    fn time_adjustments_apply_on_the_next_increment() {
+        let mut clock = clock();
+        clock.adjust_time(-500);
+        clock.registers().advance(1_000_000);
+        assert_eq!(clock.now(), 500 + 6_399_999);
+    }
+
+    #[test]
+    fn rx_timestamps_are_latched_once() {
+        let mut clock = clock();
+        clock.enable_timestamping(&mut EtqfSlots::new(), RxTimestampFilter::V2Event, false).unwrap();
+        let tsyncrxctl = clock.registers().read(TimeSyncRegister::Tsyncrxctl);
+        assert_eq!(tsyncrxctl.get_bits(TSYNCRXCTL_TYPE), TSYNCRXCTL_TYPE_EVENT_V2);
+        assert!(tsyncrxctl.get_bit(TSYNCRXCTL_EN));
+
+        clock.registers().advance(1_000_000);
+        clock.registers().receive_event(7, 0x1234_5678_9ABC);
+        clock.registers().advance(1_000_000);
+        assert_eq!(clock.rx_timestamp(), Some(RxTimestamp { ns: 1_000 + 6_399_999, sequence_id: 7, source_uuid: 0x1234_5678_9ABC }));
+        assert_eq!(clock.rx_timestamp(), None);
+    }
+
+    #[test]
+    fn disabling_timestamping_keeps_the_filter_while_ptp_is_steered() {
+        let mut clock = clock();
+        let mut slots = EtqfSlots::new();
+        slots.acquire(0x88CC, EtqfUser::Steering).unwrap();
+        clock.enable_timestamping(&mut slots, RxTimestampFilter::V2Event, true).unwrap();
+        let index = slots.index(ETH_P_1588).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(slots.acquire(ETH_P_1588, EtqfUser::Steering), Ok(index));
+
+        clock.enable_timestamping(&mut slots, RxTimestampFilter::None, false).unwrap();
+        let etqf = clock.registers().read_etqf(index);
+        assert!(!etqf.get_bit(ETQF_1588));
+        assert!(etqf.get_bit(ETQF_FILTER_EN));
+        assert_eq!(etqf.get_bits(ETQF_ETHER_TYPE), ETH_P_1588 as u32);
+        assert!(slots.is_used_by(index, EtqfUser::Steering));
+        assert!(!slots.is_used_by(index, EtqfUser::Timestamping));
+    }
+
+    #[test]
+    fn disabling_timestamping_frees_an_unshared_filter() {
+        let mut clock = clock();
+        let mut slots = EtqfSlots::new();
+        clock.enable_timestamping(&mut slots, RxTimestampFilter::V2Event, false).unwrap();
+        // enabling again keeps the same filter
+        clock.enable_timestamping(&mut slots, RxTimestampFilter::V2Event, false).unwrap();
+        clock.enable_timestamping(&mut slots, RxTimestampFilter::V1L4Sync, false).unwrap();
+        assert_eq!(slots.index(ETH_P_1588), None);
+        assert_eq!(clock.registers().read_etqf(0), 0);
+    }
+
+    #[test]
+    fn time_sync_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::{REGISTERS1_OFFSET, RX_REGISTERS2_OFFSET, MAC_REGISTERS_OFFSET};
+        assert_eq!(REGISTERS1_OFFSET + offset_of!(IntelIxgbeRegisters1, time_sync_sdp_configuration_register), 0x3C);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, rx_time_sync_control_register), 0x5188);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, tx_time_sync_control_register), 0x8C00);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, system_time_register_low), 0x8C0C);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, increment_attributes_register), 0x8C14);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, timesync_auxiliary_control_register), 0x8C20);
+    }
+}
@@ -0,0 +1,462 @@
+//! Steering of received packets to Rx queues by EtherType (ETQF/ETQS), TCP SYN (SYNQF)
+//! and L3/L4 5-tuples (FTQF/SAQF/DAQF/SDPQF/L34TIMIR).
+//!
+//! These filters take precedence over RSS. A packet that matches several 5-tuple filters goes to the queue of
+//! the one with the highest priority, and of the lowest index among equal priorities. The SYN filter is
+//! checked before or after the 5-tuple filters depending on its priority bit.
+//!
+//! The EtherType filters are also used by other offloads: PTP timestamping recognizes layer 2 PTP packets
+//! with one, and FCoE owns the FCoE and FIP filters. [`EtqfSlots`] hands out the filters to all of them,
+//! so that every EtherType has a single filter. Steering PTP packets shares the filter used for timestamping,
+//! while the FCoE EtherTypes can't be steered here.
+//!
+//! The addresses and ports of 5-tuple filters are stored in network byte order,
+//! so the first byte on the wire is in the low byte of the register.
+
+use core::net::Ipv4Addr;
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::rss::L4Protocol;
+
+/// Number of EtherType filters.
+pub const NUM_ETQF_FILTERS: usize = 8;
+/// Number of 5-tuple filters.
+pub const NUM_FIVE_TUPLE_FILTERS: usize = 128;
+/// The highest 5-tuple filter priority. Priority 0 isn't valid.
+pub const MAX_FIVE_TUPLE_PRIORITY: u8 = 7;
+/// Number of pools a 5-tuple filter can be restricted to.
+const NUM_POOLS: u8 = 64;
+/// Number of Rx queues a packet can be steered to.
+const NUM_RX_QUEUES: u8 = 128;
+
+/// The EtherType of LLDP packets.
+pub const ETH_P_LLDP: u16 = 0x88CC;
+/// The EtherType of layer 2 PTP packets.
+pub const ETH_P_1588: u16 = 0x88F7;
+/// The EtherType of FCoE packets.
+pub const ETH_P_FCOE: u16 = 0x8906;
+/// The EtherType of FCoE Initialization Protocol packets.
+pub const ETH_P_FIP: u16 = 0x8914;
+
+// ETQ Filter
+const ETQF_ETHER_TYPE: Range<usize> = 0..16;
+const ETQF_FILTER_EN: usize = 31;
+
+// EType Queue Select
+const ETQS_RX_QUEUE: Range<usize> = 16..23;
+const ETQS_QUEUE_EN: usize = 31;
+
+// SYN Packet Queue Filter
+const SYNQF_QUEUE_EN: usize = 0;
+const SYNQF_RX_QUEUE: Range<usize> = 1..8;
+const SYNQF_SYNQFP: usize = 31;
+
+// Five Tuple Queue Filter
+const FTQF_PROTOCOL: Range<usize> = 0..2;
+const FTQF_PRIORITY: Range<usize> = 2..5;
+const FTQF_POOL: Range<usize> = 8..14;
+const FTQF_SOURCE_ADDRESS_MASK: usize = 25;
+const FTQF_DESTINATION_ADDRESS_MASK: usize = 26;
+const FTQF_SOURCE_PORT_MASK: usize = 27;
+const FTQF_DESTINATION_PORT_MASK: usize = 28;
+const FTQF_PROTOCOL_MASK: usize = 29;
+const FTQF_POOL_MASK: usize = 30;
+const FTQF_QUEUE_ENABLE: usize = 31;
+
+// Source Destination Port Queue Filter
+const SDPQF_SOURCE_PORT: Range<usize> = 0..16;
+const SDPQF_DESTINATION_PORT: Range<usize> = 16..32;
+
+// L3 L4 Tuples Immediate Interrupt
+/// Bits 13 to 19 are reserved and must be written as 1000000b.
+const L34TIMIR_RESERVED: usize = 19;
+const L34TIMIR_RX_QUEUE: Range<usize> = 21..28;
+
+/// What an EtherType filter is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EtqfUser {
+    /// Queue steering through ETQS, by [`QueueFilter`].
+    Steering,
+    /// PTP timestamping, by [`crate::ptp::PtpClock`].
+    Timestamping,
+    /// FCoE offload, by [`crate::fcoe::FcoeOffload`].
+    Fcoe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EtqfSlot {
+    ether_type: u16,
+    /// Bitmap of the users, indexed by [`EtqfUser`].
+    users: u8,
+}
+
+/// The allocation of the EtherType filters between their users.
+///
+/// Each EtherType gets one filter, which every user of that EtherType shares.
+/// The filter is free again once its last user releases it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EtqfSlots {
+    slots: [Option<EtqfSlot>; NUM_ETQF_FILTERS],
+}
+
+impl Default for EtqfSlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EtqfSlots {
+    /// Starts with every filter free.
+    pub fn new() -> EtqfSlots {
+        EtqfSlots { slots: [None; NUM_ETQF_FILTERS] }
+    }
+
+    /// Adds `user` to the filter of `ether_type`, taking the first free filter if the EtherType has none,
+    /// and returns the index of the filter. It's a conflict if `user` already uses that filter.
+    pub fn acquire(&mut self, ether_type: u16, user: EtqfUser) -> Result<usize, &'static str> {
+        let index = match self.index(ether_type) {
+            Some(index) => index,
+            None => self.slots.iter().position(Option::is_none).ok_or("EtherType filters are all in use")?,
+        };
+        let slot = self.slots[index].get_or_insert(EtqfSlot { ether_type, users: 0 });
+        if slot.users.get_bit(user as usize) {
+            return Err("EtherType filter is already in use");
+        }
+        slot.users.set_bit(user as usize, true);
+        Ok(index)
+    }
+
+    /// Removes `user` from the filter of `ether_type`, and returns the index of the filter
+    /// and whether it's now free, in which case the caller should disable it.
+    pub fn release(&mut self, ether_type: u16, user: EtqfUser) -> Result<(usize, bool), &'static str> {
+        let index = self.index(ether_type).ok_or("EtherType has no filter")?;
+        let slot = self.slots[index].as_mut().ok_or("EtherType has no filter")?;
+        if !slot.users.get_bit(user as usize) {
+            return Err("EtherType filter isn't in use");
+        }
+        slot.users.set_bit(user as usize, false);
+        let free = slot.users == 0;
+        if free {
+            self.slots[index] = None;
+        }
+        Ok((index, free))
+    }
+
+    /// The index of the filter of an EtherType.
+    pub fn index(&self, ether_type: u16) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.is_some_and(|slot| slot.ether_type == ether_type))
+    }
+
+    /// Whether `user` uses the filter at `index`.
+    pub fn is_used_by(&self, index: usize, user: EtqfUser) -> bool {
+        self.slots.get(index).copied().flatten().is_some_and(|slot| slot.users.get_bit(user as usize))
+    }
+}
+
+/// Steers packets of an EtherType to a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EtherTypeFilter {
+    pub ether_type: u16,
+    pub queue: u8,
+}
+
+/// Steers TCP packets with the SYN flag set to a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SynFilter {
+    pub queue: u8,
+    /// The SYN filter is checked before the 5-tuple filters rather than after them.
+    pub before_five_tuple: bool,
+}
+
+/// Steers IPv4 packets matching a 5-tuple to a queue. Fields that are `None` match any value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FiveTupleFilter {
+    pub source_address: Option<Ipv4Addr>,
+    pub destination_address: Option<Ipv4Addr>,
+    pub source_port: Option<u16>,
+    pub destination_port: Option<u16>,
+    pub protocol: Option<L4Protocol>,
+    /// Only match packets received by this pool.
+    pub pool: Option<u8>,
+    /// From 1 to 7, where the highest priority wins when several filters match.
+    pub priority: u8,
+    pub queue: u8,
+}
+
+impl FiveTupleFilter {
+    /// Whether the filters match the same packets, regardless of their priority and queue.
+    fn same_match(&self, other: &FiveTupleFilter) -> bool {
+        self.source_address == other.source_address
+            && self.destination_address == other.destination_address
+            && self.source_port == other.source_port
+            && self.destination_port == other.destination_port
+            && self.protocol == other.protocol
+            && self.pool == other.pool
+    }
+}
+
+fn check_queue(queue: u8) -> Result<(), &'static str> {
+    if queue >= NUM_RX_QUEUES {
+        return Err("Rx queue is out of range");
+    }
+    Ok(())
+}
+
+/// The EtherType, SYN and 5-tuple queue filters, with the slots that are in use.
+pub struct QueueFilter {
+    ether_types: [Option<EtherTypeFilter>; NUM_ETQF_FILTERS],
+    syn: Option<SynFilter>,
+    five_tuples: [Option<FiveTupleFilter>; NUM_FIVE_TUPLE_FILTERS],
+}
+
+impl Default for QueueFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueueFilter {
+    /// Starts with every slot free. The filters should be cleared with [`QueueFilter::clear`] if they may have been used before.
+    pub fn new() -> QueueFilter {
+        QueueFilter { ether_types: [None; NUM_ETQF_FILTERS], syn: None, five_tuples: [None; NUM_FIVE_TUPLE_FILTERS] }
+    }
+
+    /// Disables every filter owned by this struct. EtherType filters that are shared with other users
+    /// are left to them, only their queue steering is removed.
+    pub fn clear(&mut self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, slots: &mut EtqfSlots) {
+        for filter in self.ether_types.into_iter().flatten() {
+            self.remove_ether_type(rx_regs2, mac_regs, slots, filter.ether_type).ok();
+        }
+        self.remove_syn_filter(mac_regs).ok();
+        for index in 0..NUM_FIVE_TUPLE_FILTERS {
+            self.remove_five_tuple(mac_regs, index).ok();
+        }
+    }
+
+    /// Steers packets of an EtherType to a queue, and returns the index of the ETQF filter used.
+    /// An EtherType that already has a filter for another user, such as PTP timestamping, shares it
+    /// and keeps its other settings. It's a conflict to steer an EtherType that is already steered.
+    pub fn add_ether_type(
+        &mut self,
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        slots: &mut EtqfSlots,
+        filter: EtherTypeFilter,
+    ) -> Result<usize, &'static str> {
+        check_queue(filter.queue)?;
+        if filter.ether_type == ETH_P_FCOE || filter.ether_type == ETH_P_FIP {
+            return Err("FCoE EtherType filters are owned by FCoE offload");
+        }
+        let index = slots.acquire(filter.ether_type, EtqfUser::Steering)?;
+
+        rx_regs2.etq_filter[index].update(|etqf| {
+            etqf.set_bits(ETQF_ETHER_TYPE, filter.ether_type as u32);
+            etqf.set_bit(ETQF_FILTER_EN, true);
+        });
+        let mut etqs = 0;
+        etqs.set_bits(ETQS_RX_QUEUE, filter.queue as u32);
+        etqs.set_bit(ETQS_QUEUE_EN, true);
+        mac_regs.e_type_queue_select[index].write(etqs);
+
+        self.ether_types[index] = Some(filter);
+        Ok(index)
+    }
+
+    /// Stops steering packets of an EtherType. The filter itself is only disabled if it has no other user.
+    pub fn remove_ether_type(
+        &mut self,
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        slots: &mut EtqfSlots,
+        ether_type: u16,
+    ) -> Result<(), &'static str> {
+        if self.ether_type_index(ether_type).is_none() {
+            return Err("EtherType has no filter");
+        }
+        let (index, free) = slots.release(ether_type, EtqfUser::Steering)?;
+        mac_regs.e_type_queue_select[index].write(0);
+        if free {
+            rx_regs2.etq_filter[index].write(0);
+        }
+        self.ether_types[index] = None;
+        Ok(())
+    }
+
+    /// The index of the ETQF filter of an EtherType.
+    pub fn ether_type_index(&self, ether_type: u16) -> Option<usize> {
+        self.ether_types.iter().position(|filter| filter.is_some_and(|filter| filter.ether_type == ether_type))
+    }
+
+    pub fn ether_type_filters(&self) -> &[Option<EtherTypeFilter>; NUM_ETQF_FILTERS] {
+        &self.ether_types
+    }
+
+    /// Programs the SYN filter. There is only one, so it's a conflict if it's already in use.
+    pub fn add_syn_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, filter: SynFilter) -> Result<(), &'static str> {
+        check_queue(filter.queue)?;
+        if self.syn.is_some() {
+            return Err("SYN filter is already in use");
+        }
+        let mut synqf = 0;
+        synqf.set_bit(SYNQF_QUEUE_EN, true);
+        synqf.set_bits(SYNQF_RX_QUEUE, filter.queue as u32);
+        synqf.set_bit(SYNQF_SYNQFP, filter.before_five_tuple);
+        mac_regs.syn_packet_queue_filter.write(synqf);
+        self.syn = Some(filter);
+        Ok(())
+    }
+
+    /// Disables the SYN filter and returns it.
+    pub fn remove_syn_filter(&mut self, mac_regs: &mut IntelIxgbeMacRegisters) -> Result<SynFilter, &'static str> {
+        let filter = self.syn.take().ok_or("SYN filter isn't in use")?;
+        mac_regs.syn_packet_queue_filter.write(0);
+        Ok(filter)
+    }
+
+    pub fn syn_filter(&self) -> Option<SynFilter> {
+        self.syn
+    }
+
+    /// Programs a 5-tuple filter in the first free slot and returns its index.
+    /// It's a conflict to add a filter that matches the same packets as an existing one,
+    /// since only one of them would ever be used.
+    pub fn add_five_tuple(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, filter: FiveTupleFilter) -> Result<usize, &'static str> {
+        check_queue(filter.queue)?;
+        if filter.priority == 0 || filter.priority > MAX_FIVE_TUPLE_PRIORITY {
+            return Err("5-tuple filter priority must be between 1 and 7");
+        }
+        if filter.pool.is_some_and(|pool| pool >= NUM_POOLS) {
+            return Err("pool is out of range");
+        }
+        if self.conflicting_five_tuple(&filter).is_some() {
+            return Err("a 5-tuple filter already matches the same packets");
+        }
+        let index = self.five_tuples.iter().position(Option::is_none).ok_or("5-tuple filters are all in use")?;
+
+        let address = |address: Option<Ipv4Addr>| address.map_or(0, |address| u32::from_le_bytes(address.octets()));
+        mac_regs.source_address_queue_filter[index].write(address(filter.source_address));
+        mac_regs.destination_address_queue_filter[index].write(address(filter.destination_address));
+
+        let port = |port: Option<u16>| port.map_or(0, |port| u16::from_le_bytes(port.to_be_bytes())) as u32;
+        let mut sdpqf = 0;
+        sdpqf.set_bits(SDPQF_SOURCE_PORT, port(filter.source_port));
+        sdpqf.set_bits(SDPQF_DESTINATION_PORT, port(filter.destination_port));
+        mac_regs.source_destination_port_queue_filter[index].write(sdpqf);
+
+        let mut l34timir = 0;
+        l34timir.set_bit(L34TIMIR_RESERVED, true);
+        l34timir.set_bits(L34TIMIR_RX_QUEUE, filter.queue as u32);
+        mac_regs.l3_l4_tuples_immediate_interrupt[index].write(l34timir);
+
+        let mut ftqf = 0;
+        let protocol = match filter.protocol {
+            Some(L4Protocol::Tcp) | None => 0b00,
+            Some(L4Protocol::Udp) => 0b01,
+            Some(L4Protocol::Sctp) => 0b10,
+            Some(L4Protocol::Other) => 0b11,
+        };
+        ftqf.set_bits(FTQF_PROTOCOL, protocol);
+        ftqf.set_bits(FTQF_PRIORITY, filter.priority as u32);
+        ftqf.set_bits(FTQF_POOL, filter.pool.unwrap_or(0) as u32);
+        // a set mask bit means the field isn't compared
+        ftqf.set_bit(FTQF_SOURCE_ADDRESS_MASK, filter.source_address.is_none());
+        ftqf.set_bit(FTQF_DESTINATION_ADDRESS_MASK, filter.destination_address.is_none());
+        ftqf.set_bit(FTQF_SOURCE_PORT_MASK, filter.source_port.is_none());
+        ftqf.set_bit(FTQF_DESTINATION_PORT_MASK, filter.destination_port.is_none());
+        ftqf.set_bit(FTQF_PROTOCOL_MASK, filter.protocol.is_none());
+        ftqf.set_bit(FTQF_POOL_MASK, filter.pool.is_none());
+        ftqf.set_bit(FTQF_QUEUE_ENABLE, true);
+        mac_regs.five_tuple_queue_filter[index].write(ftqf);
+
+        self.five_tuples[index] = Some(filter);
+        Ok(index)
+    }
+
+    /// Disables a 5-tuple filter and returns it.
+    pub fn remove_five_tuple(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, index: usize) -> Result<FiveTupleFilter, &'static str> {
+        let filter = self.five_tuples.get_mut(index).ok_or("5-tuple filter index is out of range")?
+            .take().ok_or("5-tuple filter isn't in use")?;
+        mac_regs.five_tuple_queue_filter[index].write(0);
+        mac_regs.l3_l4_tuples_immediate_interrupt[index].write(0);
+        Ok(filter)
+    }
+
+    /// The index of a filter that matches the same packets as `filter`, if there is one.
+    pub fn conflicting_five_tuple(&self, filter: &FiveTupleFilter) -> Option<usize> {
+        self.five_tuples.iter().position(|existing| existing.is_some_and(|existing| existing.same_match(filter)))
+    }
+
+    pub fn five_tuple(&self, index: usize) -> Option<&FiveTupleFilter> {
+        self.five_tuples.get(index)?.as_ref()
+    }
+
+    /// The number of 5-tuple filters in use.
+    pub fn num_five_tuples(&self) -> usize {
+        self.five_tuples.iter().filter(|filter| filter.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ether_types_get_the_first_free_filter() {
+        let mut slots = EtqfSlots::new();
+        assert_eq!(slots.acquire(ETH_P_FCOE, EtqfUser::Fcoe), Ok(0));
+        assert_eq!(slots.acquire(ETH_P_LLDP, EtqfUser::Steering), Ok(1));
+        assert_eq!(slots.release(ETH_P_FCOE, EtqfUser::Fcoe), Ok((0, true)));
+        assert_eq!(slots.acquire(ETH_P_1588, EtqfUser::Timestamping), Ok(0));
+        assert_eq!(slots.index(ETH_P_LLDP), Some(1));
+    }
+
+    #[test]
+    fn users_of_an_ether_type_share_its_filter() {
+        let mut slots = EtqfSlots::new();
+        let index = slots.acquire(ETH_P_1588, EtqfUser::Timestamping).unwrap();
+        assert_eq!(slots.acquire(ETH_P_1588, EtqfUser::Steering), Ok(index));
+        assert_eq!(slots.release(ETH_P_1588, EtqfUser::Timestamping), Ok((index, false)));
+        assert!(slots.is_used_by(index, EtqfUser::Steering));
+        assert_eq!(slots.release(ETH_P_1588, EtqfUser::Steering), Ok((index, true)));
+        assert_eq!(slots.index(ETH_P_1588), None);
+    }
+
+    #[test]
+    fn conflicting_ether_type_users_are_rejected() {
+        let mut slots = EtqfSlots::new();
+        slots.acquire(ETH_P_LLDP, EtqfUser::Steering).unwrap();
+        assert!(slots.acquire(ETH_P_LLDP, EtqfUser::Steering).is_err());
+        assert!(slots.release(ETH_P_LLDP, EtqfUser::Timestamping).is_err());
+        assert!(slots.release(ETH_P_FIP, EtqfUser::Fcoe).is_err());
+    }
+
+    #[test]
+    fn ether_type_filters_run_out() {
+        let mut slots = EtqfSlots::new();
+        for ether_type in 0..NUM_ETQF_FILTERS as u16 {
+            slots.acquire(0x9000 + ether_type, EtqfUser::Steering).unwrap();
+        }
+        assert!(slots.acquire(ETH_P_LLDP, EtqfUser::Steering).is_err());
+        // an EtherType that already has a filter can still gain users
+        assert_eq!(slots.acquire(0x9003, EtqfUser::Timestamping), Ok(3));
+    }
+
+    #[test]
+    fn five_tuples_conflict_regardless_of_priority_and_queue() {
+        let filter = FiveTupleFilter {
+            source_address: None,
+            destination_address: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            source_port: None,
+            destination_port: Some(80),
+            protocol: Some(L4Protocol::Tcp),
+            pool: None,
+            priority: 1,
+            queue: 4,
+        };
+        let mut queue_filter = QueueFilter::new();
+        queue_filter.five_tuples[7] = Some(filter);
+        assert_eq!(queue_filter.conflicting_five_tuple(&FiveTupleFilter { priority: 7, queue: 9, ..filter }), Some(7));
+        assert_eq!(queue_filter.conflicting_five_tuple(&FiveTupleFilter { destination_port: Some(443), ..filter }), None);
+    }
+}
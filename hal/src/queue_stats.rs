@@ -0,0 +1,229 @@
+//! Mapping of Rx and Tx queues to the 16 per-queue statistics counter sets.
+//!
+//! The 82599 has 128 Rx and 128 Tx queues but only 16 sets of queue counters (QPRC, QBRC, QPRDC, QPTC, QBTC).
+//! Each RQSMR and TQSM register holds the counter set of 4 consecutive queues, one byte per queue.
+//! Grouping the queues of a tenant into one counter set gives per-tenant traffic accounting.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::stats::{IxgbeStats, NUM_QUEUE_STAT_COUNTERS};
+
+/// Number of Rx queues, and also of Tx queues.
+pub const NUM_QUEUES: usize = 128;
+/// Number of queues whose counter set is held in one RQSMR or TQSM register.
+const QUEUES_PER_REGISTER: usize = 4;
+/// Number of RQSMR registers, and also of TQSM registers.
+pub const NUM_MAPPING_REGISTERS: usize = NUM_QUEUES / QUEUES_PER_REGISTER;
+/// The counter set that queues belong to until they're assigned to a group.
+pub const DEFAULT_COUNTER_SET: u8 = 0;
+
+/// The packet, octet and drop counts of a group of queues that share a counter set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueGroupStats {
+    pub packets_received: u64,
+    pub octets_received: u64,
+    pub packets_received_dropped: u64,
+    pub packets_transmitted: u64,
+    pub octets_transmitted: u64,
+}
+
+impl QueueGroupStats {
+    /// Extracts the counts of `counter_set` from a statistics snapshot.
+    pub fn from_stats(stats: &IxgbeStats, counter_set: u8) -> Result<QueueGroupStats, &'static str> {
+        let i = counter_set as usize;
+        if i >= NUM_QUEUE_STAT_COUNTERS {
+            return Err("queue statistics counter set is out of range");
+        }
+        Ok(QueueGroupStats {
+            packets_received: stats.queue_packets_received[i],
+            octets_received: stats.queue_octets_received[i],
+            packets_received_dropped: stats.queue_packets_received_dropped[i],
+            packets_transmitted: stats.queue_packets_transmitted[i],
+            octets_transmitted: stats.queue_octets_transmitted[i],
+        })
+    }
+}
+
+/// The counter set of every Rx and Tx queue, and which counter sets have been handed out to groups.
+///
+/// Counter set 0 is the default for queues that aren't part of a group, so 15 groups can be allocated.
+/// The counters aren't cleared when queues move between counter sets,
+/// so a statistics snapshot should be taken before a group is allocated or released.
+pub struct QueueStatsMapping {
+    rx: [u8; NUM_QUEUES],
+    tx: [u8; NUM_QUEUES],
+    allocated: u16,
+}
+
+impl Default for QueueStatsMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QueueStatsMapping {
+    /// Creates a mapping where all queues use the default counter set.
+    pub fn new() -> QueueStatsMapping {
+        let mut allocated = 0;
+        allocated.set_bit(DEFAULT_COUNTER_SET as usize, true);
+        QueueStatsMapping {
+            rx: [DEFAULT_COUNTER_SET; NUM_QUEUES],
+            tx: [DEFAULT_COUNTER_SET; NUM_QUEUES],
+            allocated,
+        }
+    }
+
+    /// Assigns the given Rx and Tx queues to a free counter set and returns it.
+    /// The queues must not already belong to another group, and there has to be at least one of them.
+    pub fn allocate_group(&mut self, rx_queues: &[u8], tx_queues: &[u8]) -> Result<u8, &'static str> {
+        if rx_queues.is_empty() && tx_queues.is_empty() {
+            return Err("queue statistics group has no queues");
+        }
+        let counter_set = (0..NUM_QUEUE_STAT_COUNTERS)
+            .find(|&i| !self.allocated.get_bit(i))
+            .ok_or("all queue statistics counter sets are in use")? as u8;
+
+        for &queue in rx_queues {
+            if queue as usize >= NUM_QUEUES {
+                return Err("Rx queue is out of range");
+            }
+            if self.rx[queue as usize] != DEFAULT_COUNTER_SET {
+                return Err("Rx queue already belongs to a statistics group");
+            }
+        }
+        for &queue in tx_queues {
+            if queue as usize >= NUM_QUEUES {
+                return Err("Tx queue is out of range");
+            }
+            if self.tx[queue as usize] != DEFAULT_COUNTER_SET {
+                return Err("Tx queue already belongs to a statistics group");
+            }
+        }
+
+        for &queue in rx_queues {
+            self.rx[queue as usize] = counter_set;
+        }
+        for &queue in tx_queues {
+            self.tx[queue as usize] = counter_set;
+        }
+        self.allocated.set_bit(counter_set as usize, true);
+        Ok(counter_set)
+    }
+
+    /// Moves the queues of a group back to the default counter set and frees its counter set.
+    pub fn release_group(&mut self, counter_set: u8) -> Result<(), &'static str> {
+        if counter_set == DEFAULT_COUNTER_SET || counter_set as usize >= NUM_QUEUE_STAT_COUNTERS {
+            return Err("not an allocatable queue statistics counter set");
+        }
+        if !self.allocated.get_bit(counter_set as usize) {
+            return Err("queue statistics counter set is not allocated");
+        }
+        for entry in self.rx.iter_mut().chain(self.tx.iter_mut()) {
+            if *entry == counter_set {
+                *entry = DEFAULT_COUNTER_SET;
+            }
+        }
+        self.allocated.set_bit(counter_set as usize, false);
+        Ok(())
+    }
+
+    pub fn rx_counter_set(&self, queue: u8) -> Result<u8, &'static str> {
+        self.rx.get(queue as usize).copied().ok_or("Rx queue is out of range")
+    }
+
+    pub fn tx_counter_set(&self, queue: u8) -> Result<u8, &'static str> {
+        self.tx.get(queue as usize).copied().ok_or("Tx queue is out of range")
+    }
+
+    /// Returns the value of RQSMR[`index`].
+    pub fn rqsmr(&self, index: usize) -> Result<u32, &'static str> {
+        mapping_register(&self.rx, index).ok_or("RQSMR index is out of range")
+    }
+
+    /// Returns the value of TQSM[`index`].
+    pub fn tqsm(&self, index: usize) -> Result<u32, &'static str> {
+        mapping_register(&self.tx, index).ok_or("TQSM index is out of range")
+    }
+
+    /// Writes the whole mapping to the RQSMR and TQSM registers.
+    pub fn program(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) {
+        let rx = self.rx.chunks_exact(QUEUES_PER_REGISTER).map(encode_mapping);
+        let tx = self.tx.chunks_exact(QUEUES_PER_REGISTER).map(encode_mapping);
+        for (i, (rqsmr, tqsm)) in rx.zip(tx).enumerate() {
+            rx_regs2.receive_queue_statistic_mapping_registers[i].write(rqsmr);
+            mac_regs.transmit_queue_statistic_mapping_registers[i].write(tqsm);
+        }
+    }
+}
+
+/// Returns the value of the mapping register `index` of `queues`, or `None` if there's no such register.
+fn mapping_register(queues: &[u8; NUM_QUEUES], index: usize) -> Option<u32> {
+    queues.chunks_exact(QUEUES_PER_REGISTER).nth(index).map(encode_mapping)
+}
+
+/// Packs the counter sets of 4 consecutive queues, the first queue in the lowest byte.
+fn encode_mapping(counter_sets: &[u8]) -> u32 {
+    let mut value = 0;
+    for (i, &counter_set) in counter_sets.iter().enumerate() {
+        value.set_bits(i * 8..i * 8 + 4, counter_set as u32);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_get_their_own_counter_sets() {
+        let mut mapping = QueueStatsMapping::new();
+        assert_eq!(mapping.allocate_group(&[4, 5], &[4]), Ok(1));
+        assert_eq!(mapping.allocate_group(&[], &[9]), Ok(2));
+        assert_eq!(mapping.rx_counter_set(5), Ok(1));
+        assert_eq!(mapping.tx_counter_set(9), Ok(2));
+        assert_eq!(mapping.rx_counter_set(9), Ok(DEFAULT_COUNTER_SET));
+        // queues 4 to 7 share RQSMR[1], one byte per queue
+        assert_eq!(mapping.rqsmr(1), Ok(0x0000_0101));
+        assert_eq!(mapping.tqsm(2), Ok(0x0000_0200));
+    }
+
+    #[test]
+    fn released_counter_sets_are_reused() {
+        let mut mapping = QueueStatsMapping::new();
+        let group = mapping.allocate_group(&[0], &[]).unwrap();
+        mapping.release_group(group).unwrap();
+        assert_eq!(mapping.rx_counter_set(0), Ok(DEFAULT_COUNTER_SET));
+        assert_eq!(mapping.allocate_group(&[1], &[]), Ok(group));
+        assert!(mapping.release_group(DEFAULT_COUNTER_SET).is_err());
+    }
+
+    #[test]
+    fn counter_sets_run_out_after_15_groups() {
+        let mut mapping = QueueStatsMapping::new();
+        for queue in 0..15 {
+            mapping.allocate_group(&[queue], &[]).unwrap();
+        }
+        assert!(mapping.allocate_group(&[15], &[]).is_err());
+    }
+
+    #[test]
+    fn invalid_groups_are_rejected() {
+        let mut mapping = QueueStatsMapping::new();
+        assert!(mapping.allocate_group(&[], &[]).is_err());
+        assert!(mapping.allocate_group(&[128], &[]).is_err());
+        mapping.allocate_group(&[3], &[]).unwrap();
+        assert!(mapping.allocate_group(&[3], &[]).is_err());
+        // a rejected group doesn't use up a counter set
+        assert_eq!(mapping.allocate_group(&[2], &[]), Ok(2));
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let mapping = QueueStatsMapping::new();
+        assert!(mapping.rx_counter_set(128).is_err());
+        assert!(mapping.tx_counter_set(200).is_err());
+        assert!(mapping.rqsmr(NUM_MAPPING_REGISTERS).is_err());
+        assert!(mapping.tqsm(NUM_MAPPING_REGISTERS).is_err());
+        assert!(QueueGroupStats::from_stats(&IxgbeStats::default(), 16).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Indices into register arrays that carry the size of the array in their type.
+//!
+//! Multi-dimensional register tables such as LSECRXKEY[n,m] are addressed by one index per dimension,
+//! each with its own stride and bound. Their accessors take a [`RegisterIndex`] per dimension,
+//! so an index can't be out of range or passed for the wrong dimension once it's been constructed.
+
+/// An index that is less than `N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RegisterIndex<const N: usize>(usize);
+
+impl<const N: usize> RegisterIndex<N> {
+    /// Returns `None` if `index` isn't less than `N`.
+    pub const fn new(index: usize) -> Option<RegisterIndex<N>> {
+        if index < N { Some(RegisterIndex(index)) } else { None }
+    }
+
+    /// An index that is checked at compile time.
+    pub const fn at<const I: usize>() -> RegisterIndex<N> {
+        const { assert!(I < N, "register index is out of range") };
+        RegisterIndex(I)
+    }
+
+    /// All indices, in increasing order.
+    pub fn all() -> impl Iterator<Item = RegisterIndex<N>> {
+        (0..N).map(RegisterIndex)
+    }
+
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
@@ -0,0 +1,367 @@
+//! Receive Side Scaling (RSS) configuration over the RETA, RSSRK and MRQC registers.
+//!
+//! The 82599 hashes the selected header fields of a received packet with a Toeplitz function
+//! keyed by the 40-byte RSS Random Key. The 7 LSBs of the hash index the 128-entry Redirection Table,
+//! whose entry is the Rx queue the packet is placed on.
+//!
+//! [`toeplitz_hash`] is a software copy of the hardware hash, so that [`Rss::queue_for`] can predict
+//! the queue a given flow lands on without sending any traffic.
+
+use core::net::IpAddr;
+use bit_field::BitField;
+use crate::{IntelIxgbeMacRegisters, IntelIxgbeRxRegisters2};
+
+/// Size of the RSS hash key in bytes, held in the 10 RSSRK registers.
+pub const RSS_KEY_SIZE: usize = 40;
+/// Number of entries in the Redirection Table, packed 4 per RETA register.
+pub const RETA_ENTRIES: usize = 128;
+/// Number of Rx queues that a RETA entry can address.
+pub const RSS_MAX_QUEUES: u8 = 16;
+
+/// MRQC.MRQE value that enables RSS without DCB or virtualization.
+pub const MRQC_MRQE_RSS: u32 = 0x1;
+const MRQC_MRQE_MASK: u32 = 0xF;
+
+// MRQC field enables: each bit selects a packet type for which a hash is computed.
+pub const MRQC_RSS_FIELD_IPV4_TCP: u32 = 1 << 16;
+pub const MRQC_RSS_FIELD_IPV4: u32 = 1 << 17;
+pub const MRQC_RSS_FIELD_IPV6_EX_TCP: u32 = 1 << 18;
+pub const MRQC_RSS_FIELD_IPV6_EX: u32 = 1 << 19;
+pub const MRQC_RSS_FIELD_IPV6: u32 = 1 << 20;
+pub const MRQC_RSS_FIELD_IPV6_TCP: u32 = 1 << 21;
+pub const MRQC_RSS_FIELD_IPV4_UDP: u32 = 1 << 22;
+pub const MRQC_RSS_FIELD_IPV6_UDP: u32 = 1 << 23;
+pub const MRQC_RSS_FIELD_IPV6_EX_UDP: u32 = 1 << 24;
+const MRQC_RSS_FIELD_MASK: u32 = 0x01FF_0000;
+
+/// RXCSUM.PCSD: write the RSS hash instead of the fragment checksum in the Rx descriptor.
+const RXCSUM_PCSD: usize = 13;
+
+/// The transport protocol of a flow, which decides which header fields are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L4Protocol {
+    Tcp,
+    Udp,
+    Sctp,
+    Other,
+}
+
+/// The addresses and ports that identify a flow.
+/// Ports are ignored for protocols that the hardware doesn't hash on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowTuple {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: L4Protocol,
+}
+
+/// How the Redirection Table entries are spread across Rx queues.
+#[derive(Debug, Clone, Copy)]
+pub enum RetaPolicy<'a> {
+    /// Entry `i` points to queue `i % num_queues`.
+    RoundRobin { num_queues: u8 },
+    /// Each `(queue, weight)` pair receives a share of the table proportional to its weight.
+    /// Entries of different queues are interleaved rather than placed in contiguous runs.
+    Weighted(&'a [(u8, u8)]),
+    /// Every entry points to the same queue.
+    Single(u8),
+}
+
+/// Computes the Toeplitz hash of `input` the same way the 82599 does.
+///
+/// For every set bit of the input, starting at the MSB of the first byte,
+/// the 32-bit window of the key that starts at that bit position is XORed into the result.
+pub fn toeplitz_hash(key: &[u8; RSS_KEY_SIZE], input: &[u8]) -> u32 {
+    let mut result = 0;
+    let mut window = u32::from_be_bytes([key[0], key[1], key[2], key[3]]);
+
+    for (next_key_byte, &byte) in (4..).zip(input.iter()) {
+        for bit in (0..8).rev() {
+            if byte.get_bit(bit) {
+                result ^= window;
+            }
+            let next_bit = key.get(next_key_byte).is_some_and(|k| k.get_bit(bit));
+            window = (window << 1) | next_bit as u32;
+        }
+    }
+    result
+}
+
+/// A software copy of the RSS configuration of the device.
+///
+/// The key, table and hash fields are kept so that they can be (re)written to the device
+/// and used to predict the queue of a flow with [`Rss::queue_for`].
+pub struct Rss {
+    key: [u8; RSS_KEY_SIZE],
+    reta: [u8; RETA_ENTRIES],
+    hash_fields: u32,
+}
+
+impl Rss {
+    /// Creates an RSS configuration with the given key, queue spread policy and MRQC field enables.
+    /// Nothing is written to the device until [`Rss::program`] is called.
+    pub fn new(key: [u8; RSS_KEY_SIZE], policy: RetaPolicy, hash_fields: u32) -> Result<Rss, &'static str> {
+        if hash_fields & !MRQC_RSS_FIELD_MASK != 0 {
+            return Err("invalid RSS hash field enables");
+        }
+        Ok(Rss {
+            key,
+            reta: build_reta(policy)?,
+            hash_fields,
+        })
+    }
+
+    /// Writes the key, the Redirection Table and the hash fields to the device and enables RSS.
+    pub fn program(&self, rx_regs: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) {
+        // enable RSS writeback in the header field of the receive descriptor
+        rx_regs.receive_checksum_control.update(|v| {
+            v.set_bit(RXCSUM_PCSD, true);
+        });
+        self.write_key(mac_regs);
+        self.write_reta(mac_regs);
+        self.write_mrqc(mac_regs);
+    }
+
+    /// Disables RSS, so that all packets are placed on queue 0.
+    pub fn disable(mac_regs: &mut IntelIxgbeMacRegisters) {
+        mac_regs.multiple_receive_queues_command_register.update(|v| *v &= !(MRQC_MRQE_MASK | MRQC_RSS_FIELD_MASK));
+    }
+
+    /// Replaces the RSS key and writes it to the RSSRK registers.
+    pub fn set_key(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, key: [u8; RSS_KEY_SIZE]) {
+        self.key = key;
+        self.write_key(mac_regs);
+    }
+
+    /// Refills the Redirection Table according to `policy` and writes it to the RETA registers.
+    pub fn set_reta(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, policy: RetaPolicy) -> Result<(), &'static str> {
+        self.reta = build_reta(policy)?;
+        self.write_reta(mac_regs);
+        Ok(())
+    }
+
+    /// Selects the packet types that are hashed, as an OR of the `MRQC_RSS_FIELD_*` values.
+    pub fn set_hash_fields(&mut self, mac_regs: &mut IntelIxgbeMacRegisters, hash_fields: u32) -> Result<(), &'static str> {
+        if hash_fields & !MRQC_RSS_FIELD_MASK != 0 {
+            return Err("invalid RSS hash field enables");
+        }
+        self.hash_fields = hash_fields;
+        self.write_mrqc(mac_regs);
+        Ok(())
+    }
+
+    pub fn key(&self) -> &[u8; RSS_KEY_SIZE] {
+        &self.key
+    }
+
+    pub fn reta(&self) -> &[u8; RETA_ENTRIES] {
+        &self.reta
+    }
+
+    /// Returns the hash the device computes for `flow`,
+    /// or `None` if the enabled hash fields don't cover the flow's packet type.
+    pub fn hash(&self, flow: &FlowTuple) -> Option<u32> {
+        let (ipv4, ipv4_l4, ipv6, ipv6_l4) = match flow.protocol {
+            L4Protocol::Tcp => (MRQC_RSS_FIELD_IPV4, MRQC_RSS_FIELD_IPV4_TCP, MRQC_RSS_FIELD_IPV6, MRQC_RSS_FIELD_IPV6_TCP),
+            L4Protocol::Udp => (MRQC_RSS_FIELD_IPV4, MRQC_RSS_FIELD_IPV4_UDP, MRQC_RSS_FIELD_IPV6, MRQC_RSS_FIELD_IPV6_UDP),
+            L4Protocol::Sctp | L4Protocol::Other => (MRQC_RSS_FIELD_IPV4, 0, MRQC_RSS_FIELD_IPV6, 0),
+        };
+
+        let mut input = [0u8; 36];
+        let (addr_len, l4_enabled, l3_enabled) = match (flow.src_ip, flow.dst_ip) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                input[0..4].copy_from_slice(&src.octets());
+                input[4..8].copy_from_slice(&dst.octets());
+                (8, self.hash_fields & ipv4_l4 != 0, self.hash_fields & ipv4 != 0)
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                input[0..16].copy_from_slice(&src.octets());
+                input[16..32].copy_from_slice(&dst.octets());
+                (32, self.hash_fields & ipv6_l4 != 0, self.hash_fields & ipv6 != 0)
+            }
+            _ => return None,
+        };
+
+        if l4_enabled {
+            input[addr_len..addr_len + 2].copy_from_slice(&flow.src_port.to_be_bytes());
+            input[addr_len + 2..addr_len + 4].copy_from_slice(&flow.dst_port.to_be_bytes());
+            Some(toeplitz_hash(&self.key, &input[..addr_len + 4]))
+        } else if l3_enabled {
+            Some(toeplitz_hash(&self.key, &input[..addr_len]))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the Rx queue the device places packets of `flow` on.
+    pub fn queue_for(&self, flow: &FlowTuple) -> u8 {
+        match self.hash(flow) {
+            Some(hash) => self.reta[hash as usize % RETA_ENTRIES],
+            None => 0,
+        }
+    }
+
+    fn write_key(&self, mac_regs: &mut IntelIxgbeMacRegisters) {
+        for (i, chunk) in self.key.chunks_exact(4).enumerate() {
+            mac_regs.rss_random_key_register[i].write(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        }
+    }
+
+    fn write_reta(&self, mac_regs: &mut IntelIxgbeMacRegisters) {
+        for (i, entries) in self.reta.chunks_exact(4).enumerate() {
+            mac_regs.redirection_table[i].write(u32::from_le_bytes([entries[0], entries[1], entries[2], entries[3]]));
+        }
+    }
+
+    fn write_mrqc(&self, mac_regs: &mut IntelIxgbeMacRegisters) {
+        mac_regs.multiple_receive_queues_command_register.update(|v| {
+            *v &= !(MRQC_MRQE_MASK | MRQC_RSS_FIELD_MASK);
+            *v |= MRQC_MRQE_RSS | self.hash_fields;
+        });
+    }
+}
+
+fn build_reta(policy: RetaPolicy) -> Result<[u8; RETA_ENTRIES], &'static str> {
+    let mut reta = [0u8; RETA_ENTRIES];
+    match policy {
+        RetaPolicy::RoundRobin { num_queues } => {
+            if num_queues == 0 || num_queues > RSS_MAX_QUEUES {
+                return Err("RSS supports 1 to 16 queues");
+            }
+            for (i, entry) in reta.iter_mut().enumerate() {
+                *entry = (i % num_queues as usize) as u8;
+            }
+        }
+        RetaPolicy::Single(queue) => {
+            if queue >= RSS_MAX_QUEUES {
+                return Err("RSS queue index is out of range");
+            }
+            reta = [queue; RETA_ENTRIES];
+        }
+        RetaPolicy::Weighted(weights) => {
+            if weights.is_empty() || weights.len() > RSS_MAX_QUEUES as usize {
+                return Err("RSS supports 1 to 16 queues");
+            }
+            if weights.iter().any(|&(queue, weight)| queue >= RSS_MAX_QUEUES || weight == 0) {
+                return Err("RSS weights must name a valid queue with a non-zero weight");
+            }
+            // smooth weighted round-robin, which interleaves the queues instead of grouping them
+            let total: i32 = weights.iter().map(|&(_, weight)| weight as i32).sum();
+            let mut current = [0i32; RSS_MAX_QUEUES as usize];
+            for entry in reta.iter_mut() {
+                let mut best = 0;
+                for (i, &(_, weight)) in weights.iter().enumerate() {
+                    current[i] += weight as i32;
+                    if current[i] > current[best] {
+                        best = i;
+                    }
+                }
+                current[best] -= total;
+                *entry = weights[best].0;
+            }
+        }
+    }
+    Ok(reta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    /// The key of the Microsoft RSS verification suite.
+    const KEY: [u8; RSS_KEY_SIZE] = [
+        0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+        0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+        0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+    ];
+
+    fn ipv4_flow(src: [u8; 4], src_port: u16, dst: [u8; 4], dst_port: u16) -> FlowTuple {
+        FlowTuple {
+            src_ip: IpAddr::V4(Ipv4Addr::from(src)),
+            dst_ip: IpAddr::V4(Ipv4Addr::from(dst)),
+            src_port,
+            dst_port,
+            protocol: L4Protocol::Tcp,
+        }
+    }
+
+    #[test]
+    fn ipv4_hashes_match_the_verification_suite() {
+        let tcp = Rss::new(KEY, RetaPolicy::Single(0), MRQC_RSS_FIELD_IPV4 | MRQC_RSS_FIELD_IPV4_TCP).unwrap();
+        let ip_only = Rss::new(KEY, RetaPolicy::Single(0), MRQC_RSS_FIELD_IPV4).unwrap();
+        let vectors = [
+            (ipv4_flow([66, 9, 149, 187], 2794, [161, 142, 100, 80], 1766), 0x323e8fc2, 0x51ccc178),
+            (ipv4_flow([199, 92, 111, 2], 14230, [65, 69, 140, 83], 4739), 0xd718262a, 0xc626b0ea),
+            (ipv4_flow([24, 19, 198, 95], 12898, [12, 22, 207, 184], 38024), 0xd2d0a5de, 0x5c2b394a),
+            (ipv4_flow([38, 27, 205, 30], 48228, [209, 142, 163, 6], 2217), 0x82989176, 0xafc7327f),
+            (ipv4_flow([153, 39, 163, 191], 44251, [202, 188, 127, 2], 1303), 0x5d1809c5, 0x10e828a2),
+        ];
+        for (flow, ip_hash, tcp_hash) in vectors {
+            assert_eq!(ip_only.hash(&flow), Some(ip_hash));
+            assert_eq!(tcp.hash(&flow), Some(tcp_hash));
+        }
+    }
+
+    #[test]
+    fn ipv6_hashes_match_the_verification_suite() {
+        let rss = Rss::new(KEY, RetaPolicy::Single(0), MRQC_RSS_FIELD_IPV6 | MRQC_RSS_FIELD_IPV6_TCP).unwrap();
+        let mut flow = FlowTuple {
+            src_ip: IpAddr::V6(Ipv6Addr::new(0x3ffe, 0x2501, 0x200, 0x1fff, 0, 0, 0, 7)),
+            dst_ip: IpAddr::V6(Ipv6Addr::new(0x3ffe, 0x2501, 0x200, 0x3, 0, 0, 0, 1)),
+            src_port: 2794,
+            dst_port: 1766,
+            protocol: L4Protocol::Tcp,
+        };
+        assert_eq!(rss.hash(&flow), Some(0x40207d3d));
+        // ports aren't hashed for protocols without an L4 hash field
+        flow.protocol = L4Protocol::Other;
+        assert_eq!(rss.hash(&flow), Some(0x2cc18cd5));
+    }
+
+    #[test]
+    fn unhashed_flows_go_to_queue_zero() {
+        let rss = Rss::new(KEY, RetaPolicy::Single(5), MRQC_RSS_FIELD_IPV6).unwrap();
+        let flow = ipv4_flow([66, 9, 149, 187], 2794, [161, 142, 100, 80], 1766);
+        assert_eq!(rss.hash(&flow), None);
+        assert_eq!(rss.queue_for(&flow), 0);
+        assert!(Rss::new(KEY, RetaPolicy::Single(0), 1 << 25).is_err());
+    }
+
+    #[test]
+    fn queues_are_looked_up_by_the_low_bits_of_the_hash() {
+        let rss = Rss::new(KEY, RetaPolicy::RoundRobin { num_queues: 16 }, MRQC_RSS_FIELD_IPV4_TCP).unwrap();
+        // 0x51ccc178 selects entry 0x78
+        let flow = ipv4_flow([66, 9, 149, 187], 2794, [161, 142, 100, 80], 1766);
+        assert_eq!(rss.queue_for(&flow), 0x78 % 16);
+    }
+
+    #[test]
+    fn weighted_tables_interleave_queues_in_proportion() {
+        let reta = build_reta(RetaPolicy::Weighted(&[(2, 3), (7, 1)])).unwrap();
+        assert_eq!(reta.iter().filter(|&&queue| queue == 2).count(), 96);
+        assert_eq!(reta.iter().filter(|&&queue| queue == 7).count(), 32);
+        assert_eq!(reta[..4], [2, 2, 7, 2]);
+    }
+
+    #[test]
+    fn invalid_table_policies_are_rejected() {
+        assert!(build_reta(RetaPolicy::RoundRobin { num_queues: 0 }).is_err());
+        assert!(build_reta(RetaPolicy::RoundRobin { num_queues: RSS_MAX_QUEUES + 1 }).is_err());
+        assert!(build_reta(RetaPolicy::Single(RSS_MAX_QUEUES)).is_err());
+        assert!(build_reta(RetaPolicy::Weighted(&[])).is_err());
+        assert!(build_reta(RetaPolicy::Weighted(&[(1, 0)])).is_err());
+    }
+
+    #[test]
+    fn rss_registers_are_at_their_datasheet_offsets() {
+        use core::mem::offset_of;
+        use crate::{RX_REGISTERS2_OFFSET, MAC_REGISTERS_OFFSET};
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, receive_checksum_control), 0x5000);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, redirection_table), 0xEB00);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, rss_random_key_register), 0xEB80);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, multiple_receive_queues_command_register), 0xEC80);
+    }
+}
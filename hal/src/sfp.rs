@@ -0,0 +1,405 @@
+//! Identification and digital diagnostics of SFP/SFP+ modules, read over I2C.
+//!
+//! A module answers at two addresses: A0h holds the SFF-8079 identification fields (vendor, part number,
+//! compliance codes) and A2h holds the SFF-8472 diagnostic monitoring values and their calibration constants.
+//! The reader works over any `embedded-hal` I2C bus, such as [`crate::i2c::IxgbeI2c`].
+
+use bit_field::BitField;
+use embedded_hal::i2c::I2c;
+
+/// The 7-bit I2C address of the identification page (A0h).
+pub const SFP_ID_ADDRESS: u8 = 0x50;
+/// The 7-bit I2C address of the diagnostics page (A2h).
+pub const SFP_DIAG_ADDRESS: u8 = 0x51;
+
+/// Number of bytes of the A0h page that hold the base and extended identification fields.
+pub const SFP_ID_LEN: usize = 96;
+/// Number of bytes of the A2h page up to the end of the real-time diagnostic values.
+pub const SFP_DIAG_LEN: usize = 106;
+
+// A0h fields
+const SFP_IDENTIFIER: usize = 0;
+const SFP_CONNECTOR: usize = 2;
+const SFP_10G_COMPLIANCE: usize = 3;
+const SFP_1G_COMPLIANCE: usize = 6;
+const SFP_CABLE_TECHNOLOGY: usize = 8;
+const SFP_BITRATE_NOMINAL: usize = 12;
+const SFP_VENDOR_NAME: core::ops::Range<usize> = 20..36;
+const SFP_VENDOR_OUI: core::ops::Range<usize> = 37..40;
+const SFP_VENDOR_PN: core::ops::Range<usize> = 40..56;
+const SFP_VENDOR_REV: core::ops::Range<usize> = 56..60;
+const SFP_WAVELENGTH: usize = 60;
+const SFP_CC_BASE: usize = 63;
+const SFP_VENDOR_SN: core::ops::Range<usize> = 68..84;
+const SFP_DATE_CODE: core::ops::Range<usize> = 84..92;
+const SFP_DIAG_MONITORING_TYPE: usize = 92;
+const SFP_CC_EXT: usize = 95;
+
+// Compliance code bits
+const SFP_10GBASE_SR: usize = 4;
+const SFP_10GBASE_LR: usize = 5;
+const SFP_10GBASE_LRM: usize = 6;
+const SFP_10GBASE_ER: usize = 7;
+const SFP_1000BASE_SX: usize = 0;
+const SFP_1000BASE_LX: usize = 1;
+const SFP_1000BASE_CX: usize = 2;
+const SFP_1000BASE_T: usize = 3;
+const SFP_CABLE_PASSIVE: usize = 2;
+const SFP_CABLE_ACTIVE: usize = 3;
+
+// Diagnostic monitoring type bits
+const SFP_DDM_IMPLEMENTED: usize = 6;
+const SFP_DDM_EXTERNALLY_CALIBRATED: usize = 4;
+
+/// Errors reading a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SfpError<E> {
+    /// The I2C transfer failed.
+    I2c(E),
+    /// The module's pages are invalid or describe an unsupported module.
+    Module(&'static str),
+}
+
+impl<E> From<&'static str> for SfpError<E> {
+    fn from(message: &'static str) -> SfpError<E> {
+        SfpError::Module(message)
+    }
+}
+
+/// The identifier byte value of SFP and SFP+ modules.
+pub const SFP_IDENTIFIER_SFP: u8 = 0x03;
+
+// A2h fields
+const DIAG_RX_PWR_4: usize = 56;
+const DIAG_TX_I_SLOPE: usize = 76;
+const DIAG_TX_PWR_SLOPE: usize = 80;
+const DIAG_T_SLOPE: usize = 84;
+const DIAG_V_SLOPE: usize = 88;
+const DIAG_TEMPERATURE: usize = 96;
+const DIAG_VCC: usize = 98;
+const DIAG_TX_BIAS: usize = 100;
+const DIAG_TX_POWER: usize = 102;
+const DIAG_RX_POWER: usize = 104;
+
+/// The media of a module, from its compliance codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SfpModuleType {
+    /// Passive direct attach copper cable.
+    DirectAttachPassive,
+    /// Active direct attach cable.
+    DirectAttachActive,
+    Sr10G,
+    Lr10G,
+    Lrm10G,
+    Er10G,
+    Sx1G,
+    Lx1G,
+    Cx1G,
+    T1G,
+    Unknown,
+}
+
+/// The identification fields of a module. Text fields are space padded ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SfpIdentification {
+    pub identifier: u8,
+    pub connector: u8,
+    pub module_type: SfpModuleType,
+    /// The nominal signalling rate, in MBd.
+    pub nominal_bitrate_mbd: u32,
+    /// The laser wavelength of optical modules, in nm.
+    pub wavelength_nm: Option<u16>,
+    pub vendor_name: [u8; 16],
+    pub vendor_oui: [u8; 3],
+    pub part_number: [u8; 16],
+    pub revision: [u8; 4],
+    pub serial_number: [u8; 16],
+    /// The manufacturing date as YYMMDD, followed by a lot code.
+    pub date_code: [u8; 8],
+    /// The module implements digital diagnostic monitoring.
+    pub diagnostics: bool,
+    /// The diagnostic values have to be scaled by the calibration constants in the A2h page.
+    pub externally_calibrated: bool,
+}
+
+impl SfpIdentification {
+    /// Parses the A0h page, checking both of its checksums.
+    pub fn parse(page: &[u8; SFP_ID_LEN]) -> Result<SfpIdentification, &'static str> {
+        if checksum(&page[..SFP_CC_BASE]) != page[SFP_CC_BASE] {
+            return Err("SFP base ID checksum is invalid");
+        }
+        if checksum(&page[SFP_CC_BASE + 1..SFP_CC_EXT]) != page[SFP_CC_EXT] {
+            return Err("SFP extended ID checksum is invalid");
+        }
+
+        let module_type = module_type(page);
+        let is_cable = matches!(module_type, SfpModuleType::DirectAttachPassive | SfpModuleType::DirectAttachActive);
+        let wavelength = u16::from_be_bytes([page[SFP_WAVELENGTH], page[SFP_WAVELENGTH + 1]]);
+        let monitoring = page[SFP_DIAG_MONITORING_TYPE];
+        Ok(SfpIdentification {
+            identifier: page[SFP_IDENTIFIER],
+            connector: page[SFP_CONNECTOR],
+            module_type,
+            nominal_bitrate_mbd: page[SFP_BITRATE_NOMINAL] as u32 * 100,
+            // cables use these bytes for their compliance instead
+            wavelength_nm: if is_cable || wavelength == 0 { None } else { Some(wavelength) },
+            vendor_name: page[SFP_VENDOR_NAME].try_into().unwrap(),
+            vendor_oui: page[SFP_VENDOR_OUI].try_into().unwrap(),
+            part_number: page[SFP_VENDOR_PN].try_into().unwrap(),
+            revision: page[SFP_VENDOR_REV].try_into().unwrap(),
+            serial_number: page[SFP_VENDOR_SN].try_into().unwrap(),
+            date_code: page[SFP_DATE_CODE].try_into().unwrap(),
+            diagnostics: monitoring.get_bit(SFP_DDM_IMPLEMENTED),
+            externally_calibrated: monitoring.get_bit(SFP_DDM_EXTERNALLY_CALIBRATED),
+        })
+    }
+
+    pub fn vendor_name(&self) -> &str {
+        ascii_field(&self.vendor_name)
+    }
+
+    pub fn part_number(&self) -> &str {
+        ascii_field(&self.part_number)
+    }
+
+    pub fn revision(&self) -> &str {
+        ascii_field(&self.revision)
+    }
+
+    pub fn serial_number(&self) -> &str {
+        ascii_field(&self.serial_number)
+    }
+}
+
+/// Classifies a module the same way the ixgbe drivers do: cable technology first, then 10G and 1G codes.
+fn module_type(page: &[u8; SFP_ID_LEN]) -> SfpModuleType {
+    let cable = page[SFP_CABLE_TECHNOLOGY];
+    let codes_10g = page[SFP_10G_COMPLIANCE];
+    let codes_1g = page[SFP_1G_COMPLIANCE];
+    if cable.get_bit(SFP_CABLE_PASSIVE) {
+        SfpModuleType::DirectAttachPassive
+    } else if cable.get_bit(SFP_CABLE_ACTIVE) {
+        SfpModuleType::DirectAttachActive
+    } else if codes_10g.get_bit(SFP_10GBASE_SR) {
+        SfpModuleType::Sr10G
+    } else if codes_10g.get_bit(SFP_10GBASE_LR) {
+        SfpModuleType::Lr10G
+    } else if codes_10g.get_bit(SFP_10GBASE_LRM) {
+        SfpModuleType::Lrm10G
+    } else if codes_10g.get_bit(SFP_10GBASE_ER) {
+        SfpModuleType::Er10G
+    } else if codes_1g.get_bit(SFP_1000BASE_SX) {
+        SfpModuleType::Sx1G
+    } else if codes_1g.get_bit(SFP_1000BASE_LX) {
+        SfpModuleType::Lx1G
+    } else if codes_1g.get_bit(SFP_1000BASE_CX) {
+        SfpModuleType::Cx1G
+    } else if codes_1g.get_bit(SFP_1000BASE_T) {
+        SfpModuleType::T1G
+    } else {
+        SfpModuleType::Unknown
+    }
+}
+
+/// The low 8 bits of the sum of `bytes`.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Returns a text field without its padding, or an empty string if it isn't ASCII.
+fn ascii_field(field: &[u8]) -> &str {
+    core::str::from_utf8(field).map(|s| s.trim_end_matches([' ', '\0'])).unwrap_or("")
+}
+
+/// The real-time diagnostic values of a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SfpDiagnostics {
+    pub temperature_millicelsius: i32,
+    pub vcc_microvolts: u32,
+    pub tx_bias_microamps: u32,
+    pub tx_power_nanowatts: u32,
+    pub rx_power_nanowatts: u32,
+}
+
+impl SfpDiagnostics {
+    /// Parses the A2h page, applying its calibration constants if the module is externally calibrated.
+    pub fn parse(page: &[u8; SFP_DIAG_LEN], externally_calibrated: bool) -> SfpDiagnostics {
+        let word = |offset: usize| u16::from_be_bytes([page[offset], page[offset + 1]]);
+        let mut temperature = word(DIAG_TEMPERATURE) as i16 as i64;
+        let mut vcc = word(DIAG_VCC) as u32;
+        let mut tx_bias = word(DIAG_TX_BIAS) as u32;
+        let mut tx_power = word(DIAG_TX_POWER) as u32;
+        let mut rx_power = word(DIAG_RX_POWER) as u32;
+
+        if externally_calibrated {
+            // value = slope * raw + offset, with an unsigned 8.8 fixed point slope and a signed offset.
+            // Garbage constants can't overflow, since the products are computed in 64 bits and then clamped.
+            let calibrate = |raw: i64, slope_offset: usize| {
+                let slope = word(slope_offset) as i64;
+                let offset = word(slope_offset + 2) as i16 as i64;
+                ((slope * raw) >> 8) + offset
+            };
+            let clamp_unsigned = |value: i64| value.clamp(0, u32::MAX as i64) as u32;
+            temperature = calibrate(temperature, DIAG_T_SLOPE);
+            vcc = clamp_unsigned(calibrate(vcc as i64, DIAG_V_SLOPE));
+            tx_bias = clamp_unsigned(calibrate(tx_bias as i64, DIAG_TX_I_SLOPE));
+            tx_power = clamp_unsigned(calibrate(tx_power as i64, DIAG_TX_PWR_SLOPE));
+
+            // Rx power is a 4th order polynomial with IEEE 754 coefficients, from Rx_PWR(4) down to Rx_PWR(0).
+            // The conversion saturates, and maps NaN to 0.
+            let coefficient = |i: usize| f32::from_be_bytes(page[DIAG_RX_PWR_4 + 4 * i..][..4].try_into().unwrap());
+            let raw = rx_power as f32;
+            let calibrated = (0..5).fold(0.0, |sum, i| sum * raw + coefficient(i));
+            rx_power = calibrated as u32;
+        }
+
+        SfpDiagnostics {
+            // temperature is in 1/256 degrees, Vcc in 100 uV, bias in 2 uA and power in 0.1 uW
+            temperature_millicelsius: (temperature * 1000 / 256).clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            vcc_microvolts: vcc.saturating_mul(100),
+            tx_bias_microamps: tx_bias.saturating_mul(2),
+            tx_power_nanowatts: tx_power.saturating_mul(100),
+            rx_power_nanowatts: rx_power.saturating_mul(100),
+        }
+    }
+}
+
+/// An SFP/SFP+ module on an I2C bus.
+pub struct SfpModule<I: I2c> {
+    i2c: I,
+}
+
+impl<I: I2c> SfpModule<I> {
+    pub fn new(i2c: I) -> SfpModule<I> {
+        SfpModule { i2c }
+    }
+
+    pub fn into_inner(self) -> I {
+        self.i2c
+    }
+
+    /// Reads `buf.len()` bytes of a page, starting at `offset`.
+    pub fn read(&mut self, address: u8, offset: u8, buf: &mut [u8]) -> Result<(), I::Error> {
+        self.i2c.write_read(address, &[offset], buf)
+    }
+
+    /// Reads and parses the identification page.
+    pub fn identify(&mut self) -> Result<SfpIdentification, SfpError<I::Error>> {
+        let mut page = [0; SFP_ID_LEN];
+        self.read(SFP_ID_ADDRESS, 0, &mut page).map_err(SfpError::I2c)?;
+        if page[SFP_IDENTIFIER] != SFP_IDENTIFIER_SFP {
+            return Err(SfpError::Module("module isn't an SFP or SFP+ module"));
+        }
+        Ok(SfpIdentification::parse(&page)?)
+    }
+
+    /// Reads the diagnostic values of a module that implements diagnostic monitoring.
+    pub fn diagnostics(&mut self, id: &SfpIdentification) -> Result<SfpDiagnostics, SfpError<I::Error>> {
+        if !id.diagnostics {
+            return Err(SfpError::Module("module doesn't implement diagnostic monitoring"));
+        }
+        let mut page = [0; SFP_DIAG_LEN];
+        self.read(SFP_DIAG_ADDRESS, 0, &mut page).map_err(SfpError::I2c)?;
+        Ok(SfpDiagnostics::parse(&page, id.externally_calibrated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+
+    /// The A0h page of a 10GBASE-SR module with diagnostics, and valid checksums.
+    fn id_page() -> [u8; SFP_ID_LEN] {
+        let mut page = [0; SFP_ID_LEN];
+        page[SFP_IDENTIFIER] = SFP_IDENTIFIER_SFP;
+        page[SFP_CONNECTOR] = 0x07;
+        page[SFP_10G_COMPLIANCE] = 1 << SFP_10GBASE_SR;
+        page[SFP_BITRATE_NOMINAL] = 103;
+        page[SFP_VENDOR_NAME].copy_from_slice(b"ACME OPTICS     ");
+        page[SFP_VENDOR_PN].copy_from_slice(b"SR-10G          ");
+        page[SFP_WAVELENGTH..SFP_WAVELENGTH + 2].copy_from_slice(&850u16.to_be_bytes());
+        page[SFP_CC_BASE] = checksum(&page[..SFP_CC_BASE]);
+        page[SFP_VENDOR_SN].copy_from_slice(b"SN0001          ");
+        page[SFP_DIAG_MONITORING_TYPE] = 1 << SFP_DDM_IMPLEMENTED;
+        page[SFP_CC_EXT] = checksum(&page[SFP_CC_BASE + 1..SFP_CC_EXT]);
+        page
+    }
+
+    #[test]
+    fn parses_the_identification_page() {
+        let id = SfpIdentification::parse(&id_page()).unwrap();
+        assert_eq!(id.module_type, SfpModuleType::Sr10G);
+        assert_eq!(id.nominal_bitrate_mbd, 10_300);
+        assert_eq!(id.wavelength_nm, Some(850));
+        assert_eq!(id.vendor_name(), "ACME OPTICS");
+        assert_eq!(id.part_number(), "SR-10G");
+        assert_eq!(id.serial_number(), "SN0001");
+        assert!(id.diagnostics && !id.externally_calibrated);
+    }
+
+    #[test]
+    fn bad_checksums_are_rejected() {
+        let mut page = id_page();
+        page[SFP_VENDOR_NAME.start] ^= 1;
+        assert!(SfpIdentification::parse(&page).is_err());
+
+        let mut page = id_page();
+        page[SFP_VENDOR_SN.start] ^= 1;
+        assert!(SfpIdentification::parse(&page).is_err());
+    }
+
+    #[test]
+    fn internally_calibrated_diagnostics_are_scaled() {
+        let mut page = [0; SFP_DIAG_LEN];
+        page[DIAG_TEMPERATURE..][..2].copy_from_slice(&(25 * 256i16).to_be_bytes());
+        page[DIAG_VCC..][..2].copy_from_slice(&33_000u16.to_be_bytes());
+        page[DIAG_TX_BIAS..][..2].copy_from_slice(&3_000u16.to_be_bytes());
+        page[DIAG_TX_POWER..][..2].copy_from_slice(&5_000u16.to_be_bytes());
+        page[DIAG_RX_POWER..][..2].copy_from_slice(&4_000u16.to_be_bytes());
+        let diagnostics = SfpDiagnostics::parse(&page, false);
+        assert_eq!(diagnostics, SfpDiagnostics {
+            temperature_millicelsius: 25_000,
+            vcc_microvolts: 3_300_000,
+            tx_bias_microamps: 6_000,
+            tx_power_nanowatts: 500_000,
+            rx_power_nanowatts: 400_000,
+        });
+    }
+
+    #[test]
+    fn garbage_calibration_constants_dont_overflow() {
+        let mut page = [0xFF; SFP_DIAG_LEN];
+        for slope in [DIAG_T_SLOPE, DIAG_V_SLOPE, DIAG_TX_I_SLOPE, DIAG_TX_PWR_SLOPE] {
+            page[slope..][..4].copy_from_slice(&[0xFF, 0xFF, 0x7F, 0xFF]);
+        }
+        for i in 0..5 {
+            page[DIAG_RX_PWR_4 + 4 * i..][..4].copy_from_slice(&f32::MAX.to_be_bytes());
+        }
+        page[DIAG_TEMPERATURE..][..2].copy_from_slice(&i16::MAX.to_be_bytes());
+        let diagnostics = SfpDiagnostics::parse(&page, true);
+        // 0xFFFF * 0xFFFF would overflow an i32 before the 8.8 slope is scaled down
+        assert_eq!(diagnostics.vcc_microvolts, 1_680_947_100);
+        assert_eq!(diagnostics.temperature_millicelsius, 32_894_496);
+        assert_eq!(diagnostics.rx_power_nanowatts, u32::MAX);
+    }
+
+    /// A bus on which every transfer fails.
+    struct FailingBus;
+
+    impl ErrorType for FailingBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FailingBus {
+        fn transaction(&mut self, _address: u8, _operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            Err(ErrorKind::Bus)
+        }
+    }
+
+    #[test]
+    fn i2c_errors_are_propagated() {
+        let mut module = SfpModule::new(FailingBus);
+        assert_eq!(module.identify(), Err(SfpError::I2c(ErrorKind::Bus)));
+    }
+}
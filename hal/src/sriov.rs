@@ -0,0 +1,295 @@
+//! PF-side SR-IOV management: pool mode, VF queue ranges, VF Rx/Tx enable, default VLAN, anti-spoofing,
+//! per-pool L2 filter flags and VM-to-VM loopback.
+//!
+//! In virtualized mode the 128 Rx and Tx queues are split evenly between 16, 32 or 64 pools.
+//! VF `n` owns pool `n` and the PF owns the pool after the last VF, which is also the default pool
+//! that receives packets not matched to any other pool.
+//!
+//! The 82599 only provides 8 queues per pool as 8 DCB traffic classes, so the 16-pool mode also enables DCB
+//! in the Rx and Tx queue selection; the 32 and 64-pool modes spread each pool's packets over its queues with RSS.
+
+use core::ops::Range;
+use bit_field::BitField;
+use crate::{Volatile, IntelIxgbeRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+
+/// Number of Rx and Tx queues split between the pools.
+pub const NUM_QUEUES: u8 = 128;
+
+// General Purpose Interrupt Enable
+const GPIE_VT_MODE: Range<usize> = 14..16;
+
+// Multiple Receive Queues Command Register
+const MRQC_MRQE: Range<usize> = 0..4;
+const MRQC_MRQE_VMDQ_RSS_32: u32 = 0xA;
+const MRQC_MRQE_VMDQ_RSS_64: u32 = 0xB;
+const MRQC_MRQE_VMDQ_DCB_8TC: u32 = 0xC;
+
+// Multiple Transmit Queues Command Register
+const MTQC_RT_ENA: usize = 0;
+const MTQC_VT_ENA: usize = 1;
+const MTQC_NUM_TC_OR_Q: Range<usize> = 2..4;
+const MTQC_NUM_TC_OR_Q_64VF: u32 = 0b01;
+const MTQC_NUM_TC_OR_Q_32VF: u32 = 0b10;
+const MTQC_NUM_TC_OR_Q_8TC: u32 = 0b11;
+
+// DCB Transmit Descriptor Plane Control and Status, whose arbiter must be disabled while MTQC is changed
+const RTTDCS_ARBDIS: usize = 6;
+
+// PF Virtual Control Register
+const PFVTCTL_VT_ENA: usize = 0;
+const PFVTCTL_DEF_PL: Range<usize> = 7..13;
+const PFVTCTL_DIS_DEF_PL: usize = 29;
+const PFVTCTL_RPL_EN: usize = 30;
+
+// PF Queue Drop Enable Register, which is written one queue at a time
+const PFQDE_QDE: usize = 0;
+const PFQDE_QUEUE_INDEX: Range<usize> = 8..15;
+const PFQDE_WE: usize = 16;
+
+// PF DMA Tx General Switch Control
+const PFDTXGSWC_LBE: usize = 0;
+
+// PF VM VLAN Insert Register
+const PFVMVIR_PORT_VLAN_ID: Range<usize> = 0..16;
+const PFVMVIR_VLANA: Range<usize> = 30..32;
+const PFVMVIR_VLANA_DEFAULT: u32 = 0b01;
+
+// PF VF Anti Spoof Control, with 8 pools per register
+const PFVFSPOOF_MACAS: usize = 0;
+const PFVFSPOOF_VLANAS: usize = 8;
+
+// PF VM L2 Control Register
+const PFVML2FLT_AUPE: usize = 24;
+const PFVML2FLT_ROMPE: usize = 25;
+const PFVML2FLT_ROPE: usize = 26;
+const PFVML2FLT_BAM: usize = 27;
+const PFVML2FLT_MPE: usize = 28;
+
+/// The number of pools the queues are split between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolMode {
+    /// 16 pools of 8 queues, one per DCB traffic class.
+    Pools16,
+    /// 32 pools of 4 queues.
+    Pools32,
+    /// 64 pools of 2 queues.
+    Pools64,
+}
+
+impl PoolMode {
+    pub fn num_pools(self) -> u8 {
+        match self {
+            PoolMode::Pools16 => 16,
+            PoolMode::Pools32 => 32,
+            PoolMode::Pools64 => 64,
+        }
+    }
+
+    pub fn queues_per_pool(self) -> u8 {
+        NUM_QUEUES / self.num_pools()
+    }
+}
+
+/// Which packets a pool accepts beyond those sent to its exact-match unicast addresses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct L2FilterFlags {
+    /// Packets without a VLAN tag.
+    pub accept_untagged: bool,
+    /// Multicast packets that hit the multicast table array.
+    pub accept_multicast_hash: bool,
+    /// Unicast packets that hit the unicast table array.
+    pub accept_unicast_hash: bool,
+    /// Broadcast packets.
+    pub accept_broadcast: bool,
+    /// All multicast packets.
+    pub multicast_promiscuous: bool,
+}
+
+/// The SR-IOV configuration of the PF.
+pub struct SriovPf {
+    mode: PoolMode,
+    num_vfs: u8,
+}
+
+impl SriovPf {
+    /// Enables virtualization with `num_vfs` VFs in the given pool mode.
+    /// All VFs start with Rx and Tx disabled, and the PF's pool is enabled.
+    pub fn enable(
+        regs1: &mut IntelIxgbeRegisters1,
+        rx_regs2: &mut IntelIxgbeRxRegisters2,
+        mac_regs: &mut IntelIxgbeMacRegisters,
+        mode: PoolMode,
+        num_vfs: u8,
+    ) -> Result<SriovPf, &'static str> {
+        if num_vfs == 0 || num_vfs >= mode.num_pools() {
+            return Err("number of VFs must leave one pool for the PF");
+        }
+        let sriov = SriovPf { mode, num_vfs };
+
+        let (vt_mode, mrqe, num_tc_or_q) = match mode {
+            PoolMode::Pools16 => (0b01, MRQC_MRQE_VMDQ_DCB_8TC, MTQC_NUM_TC_OR_Q_8TC),
+            PoolMode::Pools32 => (0b10, MRQC_MRQE_VMDQ_RSS_32, MTQC_NUM_TC_OR_Q_32VF),
+            PoolMode::Pools64 => (0b11, MRQC_MRQE_VMDQ_RSS_64, MTQC_NUM_TC_OR_Q_64VF),
+        };
+        regs1.general_purpose_interrupt_enable.update(|gpie| { gpie.set_bits(GPIE_VT_MODE, vt_mode); });
+        mac_regs.multiple_receive_queues_command_register.update(|mrqc| { mrqc.set_bits(MRQC_MRQE, mrqe); });
+
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, true); });
+        let mut mtqc = 0;
+        mtqc.set_bit(MTQC_RT_ENA, mode == PoolMode::Pools16);
+        mtqc.set_bit(MTQC_VT_ENA, true);
+        mtqc.set_bits(MTQC_NUM_TC_OR_Q, num_tc_or_q);
+        mac_regs.multiple_transmit_queues_command_register.write(mtqc);
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, false); });
+
+        let mut pfvtctl = 0;
+        pfvtctl.set_bit(PFVTCTL_VT_ENA, true);
+        pfvtctl.set_bits(PFVTCTL_DEF_PL, sriov.pf_pool() as u32);
+        pfvtctl.set_bit(PFVTCTL_DIS_DEF_PL, false);
+        // broadcast and multicast packets are replicated to every pool that accepts them
+        pfvtctl.set_bit(PFVTCTL_RPL_EN, true);
+        rx_regs2.pf_virtual_control_register.write(pfvtctl);
+
+        for pool in 0..2 {
+            rx_regs2.pf_vf_receive_enable[pool].write(0);
+            mac_regs.pf_vf_transmit_enable[pool].write(0);
+        }
+        set_pool_bit(&mut rx_regs2.pf_vf_receive_enable, sriov.pf_pool(), true);
+        set_pool_bit(&mut mac_regs.pf_vf_transmit_enable, sriov.pf_pool(), true);
+        Ok(sriov)
+    }
+
+    /// Leaves virtualized mode. Rx queue selection is disabled in MRQC until RSS is programmed again.
+    pub fn disable(self, regs1: &mut IntelIxgbeRegisters1, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters) {
+        for pool in 0..2 {
+            rx_regs2.pf_vf_receive_enable[pool].write(0);
+            mac_regs.pf_vf_transmit_enable[pool].write(0);
+            rx_regs2.pf_vm_tx_switch_loopback_enable[pool].write(0);
+        }
+        mac_regs.pf_dma_tx_general_switch_control.update(|pfdtxgswc| { pfdtxgswc.set_bit(PFDTXGSWC_LBE, false); });
+        rx_regs2.pf_virtual_control_register.write(0);
+
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, true); });
+        mac_regs.multiple_transmit_queues_command_register.write(0);
+        rx_regs2.dcb_transmit_descriptor_plane_control_and_status.update(|rttdcs| { rttdcs.set_bit(RTTDCS_ARBDIS, false); });
+
+        mac_regs.multiple_receive_queues_command_register.update(|mrqc| { mrqc.set_bits(MRQC_MRQE, 0); });
+        regs1.general_purpose_interrupt_enable.update(|gpie| { gpie.set_bits(GPIE_VT_MODE, 0); });
+    }
+
+    pub fn mode(&self) -> PoolMode {
+        self.mode
+    }
+
+    pub fn num_vfs(&self) -> u8 {
+        self.num_vfs
+    }
+
+    /// The pool of the PF, which is also the default pool.
+    pub fn pf_pool(&self) -> u8 {
+        self.num_vfs
+    }
+
+    /// The Rx and Tx queues owned by a VF.
+    pub fn vf_queues(&self, vf: u8) -> Result<Range<u8>, &'static str> {
+        self.check_vf(vf)?;
+        Ok(pool_queues(self.mode, vf))
+    }
+
+    /// The Rx and Tx queues owned by the PF.
+    pub fn pf_queues(&self) -> Range<u8> {
+        pool_queues(self.mode, self.pf_pool())
+    }
+
+    pub fn set_vf_rx_enable(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, vf: u8, enable: bool) -> Result<(), &'static str> {
+        self.check_vf(vf)?;
+        set_pool_bit(&mut rx_regs2.pf_vf_receive_enable, vf, enable);
+        Ok(())
+    }
+
+    pub fn set_vf_tx_enable(&self, mac_regs: &mut IntelIxgbeMacRegisters, vf: u8, enable: bool) -> Result<(), &'static str> {
+        self.check_vf(vf)?;
+        set_pool_bit(&mut mac_regs.pf_vf_transmit_enable, vf, enable);
+        Ok(())
+    }
+
+    /// Makes the VF's Rx queues drop packets when they have no descriptors, instead of holding up the pool's
+    /// other packets. Used while the VF isn't running its queues.
+    pub fn set_vf_queue_drop(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, vf: u8, enable: bool) -> Result<(), &'static str> {
+        for queue in self.vf_queues(vf)? {
+            let mut pfqde = 0;
+            pfqde.set_bit(PFQDE_QDE, enable);
+            pfqde.set_bits(PFQDE_QUEUE_INDEX, queue as u32);
+            pfqde.set_bit(PFQDE_WE, true);
+            rx_regs2.pf_queue_drop_enable_register.write(pfqde);
+        }
+        Ok(())
+    }
+
+    /// Inserts `vlan` (with its priority in bits 15:13) into every packet the VF transmits, or lets the VF
+    /// choose its own tags if `None`. The VLAN must also be added to the pool's VLAN filter to receive on it.
+    pub fn set_vf_default_vlan(&self, mac_regs: &mut IntelIxgbeMacRegisters, vf: u8, vlan: Option<u16>) -> Result<(), &'static str> {
+        self.check_vf(vf)?;
+        let mut pfvmvir = 0;
+        if let Some(tag) = vlan {
+            pfvmvir.set_bits(PFVMVIR_PORT_VLAN_ID, tag as u32);
+            pfvmvir.set_bits(PFVMVIR_VLANA, PFVMVIR_VLANA_DEFAULT);
+        }
+        mac_regs.pf_vm_vlan_insert_register[vf as usize].write(pfvmvir);
+        Ok(())
+    }
+
+    /// Drops packets the VF transmits with a source MAC address that isn't one of its receive addresses.
+    pub fn set_vf_mac_anti_spoof(&self, mac_regs: &mut IntelIxgbeMacRegisters, vf: u8, enable: bool) -> Result<(), &'static str> {
+        self.check_vf(vf)?;
+        mac_regs.pf_vf_anti_spoof_control[vf as usize / 8].update(|spoof| { spoof.set_bit(PFVFSPOOF_MACAS + vf as usize % 8, enable); });
+        Ok(())
+    }
+
+    /// Drops packets the VF transmits with a VLAN tag that its pool isn't a member of.
+    pub fn set_vf_vlan_anti_spoof(&self, mac_regs: &mut IntelIxgbeMacRegisters, vf: u8, enable: bool) -> Result<(), &'static str> {
+        self.check_vf(vf)?;
+        mac_regs.pf_vf_anti_spoof_control[vf as usize / 8].update(|spoof| { spoof.set_bit(PFVFSPOOF_VLANAS + vf as usize % 8, enable); });
+        Ok(())
+    }
+
+    /// Sets the L2 filter flags of a pool, which may be a VF's or the PF's.
+    pub fn set_l2_filter(&self, mac_regs: &mut IntelIxgbeMacRegisters, pool: u8, flags: L2FilterFlags) -> Result<(), &'static str> {
+        if pool > self.pf_pool() {
+            return Err("pool is out of range");
+        }
+        mac_regs.pf_vm_l2_control_register[pool as usize].update(|vmolr| {
+            vmolr.set_bit(PFVML2FLT_AUPE, flags.accept_untagged);
+            vmolr.set_bit(PFVML2FLT_ROMPE, flags.accept_multicast_hash);
+            vmolr.set_bit(PFVML2FLT_ROPE, flags.accept_unicast_hash);
+            vmolr.set_bit(PFVML2FLT_BAM, flags.accept_broadcast);
+            vmolr.set_bit(PFVML2FLT_MPE, flags.multicast_promiscuous);
+        });
+        Ok(())
+    }
+
+    /// Enables or disables switching of packets between pools inside the device, so VMs on the port can reach each other.
+    pub fn set_loopback(&self, rx_regs2: &mut IntelIxgbeRxRegisters2, mac_regs: &mut IntelIxgbeMacRegisters, enable: bool) {
+        for pool in 0..=self.pf_pool() {
+            set_pool_bit(&mut rx_regs2.pf_vm_tx_switch_loopback_enable, pool, enable);
+        }
+        mac_regs.pf_dma_tx_general_switch_control.update(|pfdtxgswc| { pfdtxgswc.set_bit(PFDTXGSWC_LBE, enable); });
+    }
+
+    fn check_vf(&self, vf: u8) -> Result<(), &'static str> {
+        if vf >= self.num_vfs {
+            return Err("VF is out of range");
+        }
+        Ok(())
+    }
+}
+
+fn pool_queues(mode: PoolMode, pool: u8) -> Range<u8> {
+    let queues = mode.queues_per_pool();
+    pool * queues..(pool + 1) * queues
+}
+
+/// Sets the bit of a pool in a pair of registers that hold one bit per pool.
+fn set_pool_bit(regs: &mut [Volatile<u32>; 2], pool: u8, value: bool) {
+    regs[pool as usize / 32].update(|bits| { bits.set_bit(pool as usize % 32, value); });
+}
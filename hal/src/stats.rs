@@ -0,0 +1,286 @@
+//! Accumulation of the hardware statistics counters into 64-bit totals.
+//!
+//! The 82599 counters are 32 bits wide, except for the octet counters which are 36 bits wide
+//! and split across a low and a high register. The counters are cleared when read,
+//! so every read returns the count since the previous read and is added to the running total.
+
+use crate::hal::RC;
+use crate::{IntelIxgbeRxRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::register_index::RegisterIndex;
+
+/// Number of per-queue statistics counter sets (QPRC, QBRC, QPRDC, QPTC, QBTC).
+pub const NUM_QUEUE_STAT_COUNTERS: usize = 16;
+
+/// Only bits 3:0 of the high register of a 36-bit counter are valid.
+const HIGH_COUNTER_MASK: u32 = 0xF;
+
+/// A snapshot of the hardware statistics as 64-bit totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IxgbeStats {
+    // Rx MAC statistics
+    pub total_octets_received: u64,
+    pub total_packets_received: u64,
+    pub packets_received_64: u64,
+    pub packets_received_65_127: u64,
+    pub packets_received_128_255: u64,
+    pub packets_received_256_511: u64,
+    pub packets_received_512_1023: u64,
+    pub packets_received_1024_max: u64,
+    pub receive_fragments: u64,
+    pub receive_oversize: u64,
+    pub receive_jabbers: u64,
+    pub illegal_byte_errors: u64,
+    pub error_bytes: u64,
+    pub mac_short_packet_discards: u64,
+    pub mac_local_faults: u64,
+    pub mac_remote_faults: u64,
+    pub xsum_errors: u64,
+    pub management_packets_received: u64,
+    pub management_packets_dropped: u64,
+
+    // Rx DMA loopback statistics
+    pub rx_loopback_packets: u64,
+    pub rx_loopback_octets: u64,
+    pub rx_duplicated_loopback_packets: u64,
+    pub rx_duplicated_loopback_octets: u64,
+
+    // Tx MAC statistics
+    pub good_packets_transmitted: u64,
+    pub good_octets_transmitted: u64,
+    pub total_packets_transmitted: u64,
+    pub packets_transmitted_64: u64,
+    pub packets_transmitted_65_127: u64,
+    pub packets_transmitted_128_255: u64,
+    pub packets_transmitted_256_511: u64,
+    pub packets_transmitted_512_1023: u64,
+    pub packets_transmitted_1024_max: u64,
+    pub multicast_packets_transmitted: u64,
+    pub broadcast_packets_transmitted: u64,
+
+    // Tx DMA statistics
+    pub dma_good_tx_packets: u64,
+    pub dma_good_tx_octets: u64,
+
+    // Per-queue statistics, indexed by the counter set that the queues are mapped to
+    pub queue_packets_received: [u64; NUM_QUEUE_STAT_COUNTERS],
+    pub queue_octets_received: [u64; NUM_QUEUE_STAT_COUNTERS],
+    pub queue_packets_received_dropped: [u64; NUM_QUEUE_STAT_COUNTERS],
+    pub queue_packets_transmitted: [u64; NUM_QUEUE_STAT_COUNTERS],
+    pub queue_octets_transmitted: [u64; NUM_QUEUE_STAT_COUNTERS],
+}
+
+impl IxgbeStats {
+    /// Returns the counts between the `earlier` snapshot and this one.
+    pub fn delta(&self, earlier: &IxgbeStats) -> IxgbeStats {
+        self.combine(earlier, u64::wrapping_sub)
+    }
+
+    /// Applies `f` to every pair of corresponding counters of `self` and `other`.
+    fn combine(&self, other: &IxgbeStats, f: fn(u64, u64) -> u64) -> IxgbeStats {
+        let queues = |a: &[u64; NUM_QUEUE_STAT_COUNTERS], b: &[u64; NUM_QUEUE_STAT_COUNTERS]| {
+            core::array::from_fn(|i| f(a[i], b[i]))
+        };
+        IxgbeStats {
+            total_octets_received: f(self.total_octets_received, other.total_octets_received),
+            total_packets_received: f(self.total_packets_received, other.total_packets_received),
+            packets_received_64: f(self.packets_received_64, other.packets_received_64),
+            packets_received_65_127: f(self.packets_received_65_127, other.packets_received_65_127),
+            packets_received_128_255: f(self.packets_received_128_255, other.packets_received_128_255),
+            packets_received_256_511: f(self.packets_received_256_511, other.packets_received_256_511),
+            packets_received_512_1023: f(self.packets_received_512_1023, other.packets_received_512_1023),
+            packets_received_1024_max: f(self.packets_received_1024_max, other.packets_received_1024_max),
+            receive_fragments: f(self.receive_fragments, other.receive_fragments),
+            receive_oversize: f(self.receive_oversize, other.receive_oversize),
+            receive_jabbers: f(self.receive_jabbers, other.receive_jabbers),
+            illegal_byte_errors: f(self.illegal_byte_errors, other.illegal_byte_errors),
+            error_bytes: f(self.error_bytes, other.error_bytes),
+            mac_short_packet_discards: f(self.mac_short_packet_discards, other.mac_short_packet_discards),
+            mac_local_faults: f(self.mac_local_faults, other.mac_local_faults),
+            mac_remote_faults: f(self.mac_remote_faults, other.mac_remote_faults),
+            xsum_errors: f(self.xsum_errors, other.xsum_errors),
+            management_packets_received: f(self.management_packets_received, other.management_packets_received),
+            management_packets_dropped: f(self.management_packets_dropped, other.management_packets_dropped),
+            rx_loopback_packets: f(self.rx_loopback_packets, other.rx_loopback_packets),
+            rx_loopback_octets: f(self.rx_loopback_octets, other.rx_loopback_octets),
+            rx_duplicated_loopback_packets: f(self.rx_duplicated_loopback_packets, other.rx_duplicated_loopback_packets),
+            rx_duplicated_loopback_octets: f(self.rx_duplicated_loopback_octets, other.rx_duplicated_loopback_octets),
+            good_packets_transmitted: f(self.good_packets_transmitted, other.good_packets_transmitted),
+            good_octets_transmitted: f(self.good_octets_transmitted, other.good_octets_transmitted),
+            total_packets_transmitted: f(self.total_packets_transmitted, other.total_packets_transmitted),
+            packets_transmitted_64: f(self.packets_transmitted_64, other.packets_transmitted_64),
+            packets_transmitted_65_127: f(self.packets_transmitted_65_127, other.packets_transmitted_65_127),
+            packets_transmitted_128_255: f(self.packets_transmitted_128_255, other.packets_transmitted_128_255),
+            packets_transmitted_256_511: f(self.packets_transmitted_256_511, other.packets_transmitted_256_511),
+            packets_transmitted_512_1023: f(self.packets_transmitted_512_1023, other.packets_transmitted_512_1023),
+            packets_transmitted_1024_max: f(self.packets_transmitted_1024_max, other.packets_transmitted_1024_max),
+            multicast_packets_transmitted: f(self.multicast_packets_transmitted, other.multicast_packets_transmitted),
+            broadcast_packets_transmitted: f(self.broadcast_packets_transmitted, other.broadcast_packets_transmitted),
+            dma_good_tx_packets: f(self.dma_good_tx_packets, other.dma_good_tx_packets),
+            dma_good_tx_octets: f(self.dma_good_tx_octets, other.dma_good_tx_octets),
+            queue_packets_received: queues(&self.queue_packets_received, &other.queue_packets_received),
+            queue_octets_received: queues(&self.queue_octets_received, &other.queue_octets_received),
+            queue_packets_received_dropped: queues(&self.queue_packets_received_dropped, &other.queue_packets_received_dropped),
+            queue_packets_transmitted: queues(&self.queue_packets_transmitted, &other.queue_packets_transmitted),
+            queue_octets_transmitted: queues(&self.queue_octets_transmitted, &other.queue_octets_transmitted),
+        }
+    }
+}
+
+/// Accumulates the hardware counters into 64-bit totals.
+///
+/// Every counter is read exactly once per [`StatsCollector::update`]. Since reading clears most counters,
+/// nothing else should read the statistics registers while a collector is in use.
+pub struct StatsCollector {
+    totals: IxgbeStats,
+}
+
+impl StatsCollector {
+    /// Creates a collector whose totals start at zero.
+    /// The counters are read once so that counts from before this call are discarded.
+    pub fn new(rx_regs: &IntelIxgbeRxRegisters1, rx_regs2: &IntelIxgbeRxRegisters2, mac_regs: &IntelIxgbeMacRegisters) -> StatsCollector {
+        let mut collector = StatsCollector { totals: IxgbeStats::default() };
+        collector.update(rx_regs, rx_regs2, mac_regs);
+        collector.totals = IxgbeStats::default();
+        collector
+    }
+
+    /// Reads every counter, adds the counts to the totals and returns the updated totals.
+    ///
+    /// This should be called often enough that no 32-bit counter can wrap between two calls,
+    /// which at 10 Gb/s is every few seconds for the packet counters.
+    pub fn update(&mut self, rx_regs: &IntelIxgbeRxRegisters1, rx_regs2: &IntelIxgbeRxRegisters2, mac_regs: &IntelIxgbeMacRegisters) -> &IxgbeStats {
+        let delta = IxgbeStats {
+            total_octets_received: read_split_counter(&rx_regs2.total_octets_received_low, &rx_regs2.total_octets_received_high),
+            total_packets_received: rx_regs2.total_packets_received.read() as u64,
+            packets_received_64: rx_regs2.packets_received_count_1.read() as u64,
+            packets_received_65_127: rx_regs2.packets_received_count_2.read() as u64,
+            packets_received_128_255: rx_regs2.packets_received_count_3.read() as u64,
+            packets_received_256_511: rx_regs2.packets_received_count_4.read() as u64,
+            packets_received_512_1023: rx_regs2.packets_received_count_5.read() as u64,
+            packets_received_1024_max: rx_regs2.packets_received_count_6.read() as u64,
+            receive_fragments: rx_regs2.receive_fragment_count.read() as u64,
+            receive_oversize: rx_regs2.receive_oversize_count.read() as u64,
+            receive_jabbers: rx_regs2.receive_jabber_count.read() as u64,
+            illegal_byte_errors: rx_regs2.illegal_byte_error_count.read() as u64,
+            error_bytes: rx_regs2.error_byte_count.read() as u64,
+            mac_short_packet_discards: rx_regs2.mac_short_packet_discard_count.read() as u64,
+            mac_local_faults: rx_regs2.mac_local_fault_count.read() as u64,
+            mac_remote_faults: rx_regs2.mac_remote_fault_count.read() as u64,
+            xsum_errors: rx_regs2.xsum_error_count.read() as u64,
+            management_packets_received: rx_regs2.management_packets_received_count.read() as u64,
+            management_packets_dropped: rx_regs2.management_packets_dropped_count.read() as u64,
+            rx_loopback_packets: rx_regs2.dma_good_rx_lpbk_packet.read() as u64,
+            rx_loopback_octets: read_split_counter(&rx_regs2.dma_good_rx_lpbk_byte_counter_low, &rx_regs2.dma_good_rx_lpbk_byte_counter_high),
+            rx_duplicated_loopback_packets: rx_regs2.dma_duplicated_good_rx_lpbk_packet_counter.read() as u64,
+            rx_duplicated_loopback_octets: read_split_counter(
+                &rx_regs2.dma_duplicated_good_rx_lpbk_byte_counter_low,
+                &rx_regs2.dma_duplicated_good_rx_lpbk_byte_counter_high,
+            ),
+            good_packets_transmitted: rx_regs2.good_packets_transmitted_count.read() as u64,
+            good_octets_transmitted: read_split_counter(&rx_regs2.good_octets_transmitted_count_low, &rx_regs2.good_octets_transmitted_count_high),
+            total_packets_transmitted: rx_regs2.total_packets_transmitted.read() as u64,
+            packets_transmitted_64: rx_regs2.packets_transmitted_count_1.read() as u64,
+            packets_transmitted_65_127: rx_regs2.packets_transmitted_count_2.read() as u64,
+            packets_transmitted_128_255: rx_regs2.packets_transmitted_count_3.read() as u64,
+            packets_transmitted_256_511: rx_regs2.packets_transmitted_count_4.read() as u64,
+            packets_transmitted_512_1023: rx_regs2.packets_transmitted_count_5.read() as u64,
+            packets_transmitted_1024_max: rx_regs2.packets_transmitted_count_6.read() as u64,
+            multicast_packets_transmitted: rx_regs2.multicast_packets_transmitted.read() as u64,
+            broadcast_packets_transmitted: rx_regs2.broadcast_packets_transmitted.read() as u64,
+            dma_good_tx_packets: mac_regs.dma_good_tx_packet_counter.read() as u64,
+            dma_good_tx_octets: read_split_counter(&mac_regs.dma_good_tx_byte_counter_low, &mac_regs.dma_good_tx_byte_counter_high),
+            // The Rx counter sets are in the registers of the first 16 Rx queues, every 0x40 bytes
+            queue_packets_received: core::array::from_fn(|i| rx_regs.rx_regs1[i].queue_packets_received_count.read() as u64),
+            queue_octets_received: core::array::from_fn(|i| {
+                let rx_queue = &rx_regs.rx_regs1[i];
+                read_split_counter(&rx_queue.queue_bytes_received_count_low, &rx_queue.queue_bytes_received_count_high)
+            }),
+            queue_packets_received_dropped: core::array::from_fn(|i| {
+                // there are as many QPRDC registers as counter sets
+                rx_regs.qprdc(RegisterIndex::new(i).unwrap()).read() as u64
+            }),
+            queue_packets_transmitted: core::array::from_fn(|i| mac_regs.queue_packets_transmitted_count[i].read() as u64),
+            queue_octets_transmitted: core::array::from_fn(|i| {
+                let counters = &mac_regs.queue_bytes_transmitted_count[i];
+                read_split_counter(&counters.queue_bytes_transmitted_count_low, &counters.queue_bytes_transmitted_count_high)
+            }),
+        };
+
+        self.add(&delta);
+        &self.totals
+    }
+
+    fn add(&mut self, delta: &IxgbeStats) {
+        self.totals = self.totals.combine(delta, u64::wrapping_add);
+    }
+
+    /// Returns the totals as of the last update.
+    pub fn totals(&self) -> &IxgbeStats {
+        &self.totals
+    }
+}
+
+/// Reads a clear-on-read 36-bit counter.
+/// The low register has to be read first, since that latches the high register.
+fn read_split_counter(low: &RC<u32>, high: &RC<u32>) -> u64 {
+    let low = low.read();
+    split_counter(low, high.read())
+}
+
+/// Combines the low and high registers of a 36-bit counter.
+fn split_counter(low: u32, high: u32) -> u64 {
+    (((high & HIGH_COUNTER_MASK) as u64) << 32) | low as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collector() -> StatsCollector {
+        StatsCollector { totals: IxgbeStats::default() }
+    }
+
+    #[test]
+    fn split_counters_use_the_low_4_bits_of_the_high_register() {
+        assert_eq!(split_counter(0x8000_0001, 0xFFFF_FFF3), 0x3_8000_0001);
+    }
+
+    #[test]
+    fn clear_on_read_counts_accumulate_past_32_bits() {
+        let mut collector = collector();
+        let mut read = IxgbeStats { good_packets_transmitted: u32::MAX as u64, ..IxgbeStats::default() };
+        read.queue_packets_received[3] = 1_000;
+        collector.add(&read);
+        read.queue_packets_received[3] = 24;
+        collector.add(&read);
+
+        let totals = collector.totals();
+        assert_eq!(totals.good_packets_transmitted, 2 * u32::MAX as u64);
+        assert_eq!(totals.queue_packets_received[3], 1_024);
+        assert_eq!(totals.queue_packets_received[2], 0);
+    }
+
+    #[test]
+    fn delta_between_snapshots() {
+        let mut collector = collector();
+        let read = IxgbeStats { total_packets_received: 10, queue_octets_transmitted: [7; NUM_QUEUE_STAT_COUNTERS], ..IxgbeStats::default() };
+        collector.add(&read);
+        let earlier = *collector.totals();
+        collector.add(&read);
+        assert_eq!(collector.totals().delta(&earlier), read);
+    }
+
+    #[test]
+    fn statistics_registers_are_at_their_datasheet_offsets() {
+        use core::mem::{offset_of, size_of};
+        use crate::{RX_REGISTERS1_OFFSET, RX_REGISTERS2_OFFSET, MAC_REGISTERS_OFFSET, RegistersRx, QueueBytesTransmittedCount};
+        assert_eq!(RX_REGISTERS1_OFFSET + offset_of!(RegistersRx, queue_packets_received_count), 0x1030);
+        assert_eq!(RX_REGISTERS1_OFFSET + size_of::<RegistersRx>() + offset_of!(RegistersRx, queue_packets_received_count), 0x1070);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, packets_received_count_5), 0x406C);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, good_packets_transmitted_count), 0x4080);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, management_packets_received_count), 0x40B4);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, total_octets_received_low), 0x40C0);
+        assert_eq!(RX_REGISTERS2_OFFSET + offset_of!(IntelIxgbeRxRegisters2, packets_transmitted_count_6), 0x40EC);
+        assert_eq!(MAC_REGISTERS_OFFSET + offset_of!(IntelIxgbeMacRegisters, queue_bytes_transmitted_count) + size_of::<QueueBytesTransmittedCount>(), 0x8708);
+    }
+}
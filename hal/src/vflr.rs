@@ -0,0 +1,78 @@
+//! Handling of VF function level resets (VFLR).
+//!
+//! When a VF goes through an FLR, its bit is set in the VFLR events registers (PFVFLRE, at 0x600 for VFs 0 to 31
+//! and 0x1C0 for VFs 32 to 63) and the PF gets an interrupt. The VF has lost all its state, so the PF has to stop
+//! its queues, remove the filters it set up through the mailbox and forget its mailbox state
+//! before clearing the event in PFVFLREC, after which the VF can start over with a mailbox reset.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+use crate::mac_filter::MacFilterTable;
+use crate::mailbox::PfMailbox;
+use crate::sriov::{L2FilterFlags, SriovPf};
+use crate::vlan::{PfVlanTable, VlanFilter};
+
+/// Returns the bitmap of VFs that have gone through an FLR since their event was last cleared.
+pub fn vflr_events(regs1: &IntelIxgbeRegisters1) -> u64 {
+    let low = regs1.pf_vflr_events_indication_target.read() as u64;
+    let high = regs1.pf_vflr_events_indication_target_high.read() as u64;
+    (high << 32) | low
+}
+
+/// Clears the FLR event of a VF.
+pub fn clear_vflr_event(regs1: &mut IntelIxgbeRegisters1, vf: u8) {
+    let vf = vf as usize;
+    // the clear register is write 1 to clear
+    regs1.pf_vflr_events_clear_target[vf / 32].write(1 << (vf % 32));
+}
+
+/// The PF's per-VF state that an FLR has to clean up.
+pub struct VfState<'a> {
+    pub sriov: &'a SriovPf,
+    pub mac_filters: &'a mut MacFilterTable,
+    pub pf_vlans: &'a mut PfVlanTable,
+    pub mailbox: &'a mut PfMailbox,
+}
+
+/// Returns a VF to the state it had when SR-IOV was enabled: its queues are stopped and drop their packets,
+/// and its default VLAN, L2 filter flags, MAC and VLAN filters and mailbox state are cleared.
+/// Anti-spoofing is set by the PF rather than the VF, so it's kept.
+pub fn reset_vf(
+    rx_regs2: &mut IntelIxgbeRxRegisters2,
+    mac_regs: &mut IntelIxgbeMacRegisters,
+    state: &mut VfState,
+    vf: u8,
+) -> Result<(), &'static str> {
+    let sriov = state.sriov;
+    sriov.set_vf_rx_enable(rx_regs2, vf, false)?;
+    sriov.set_vf_tx_enable(mac_regs, vf, false)?;
+    sriov.set_vf_queue_drop(rx_regs2, vf, true)?;
+
+    sriov.set_vf_default_vlan(mac_regs, vf, None)?;
+    sriov.set_l2_filter(mac_regs, vf, L2FilterFlags::default())?;
+    state.mac_filters.remove_pool(rx_regs2, mac_regs, vf)?;
+    VlanFilter::new(rx_regs2, mac_regs, state.pf_vlans).remove_pool(vf)?;
+
+    state.mailbox.reset_vf(vf);
+    Ok(())
+}
+
+/// Cleans up and clears every pending FLR event. Returns the bitmap of VFs that were reset.
+/// Events of VFs beyond the enabled ones are only cleared.
+pub fn handle_vflr_events(
+    regs1: &mut IntelIxgbeRegisters1,
+    rx_regs2: &mut IntelIxgbeRxRegisters2,
+    mac_regs: &mut IntelIxgbeMacRegisters,
+    state: &mut VfState,
+) -> Result<u64, &'static str> {
+    let events = vflr_events(regs1);
+    let mut reset = 0u64;
+    for vf in (0..64).filter(|&vf| events.get_bit(vf)) {
+        if vf < state.sriov.num_vfs() as usize {
+            reset_vf(rx_regs2, mac_regs, state, vf as u8)?;
+            reset.set_bit(vf, true);
+        }
+        clear_vflr_event(regs1, vf as u8);
+    }
+    Ok(reset)
+}
@@ -0,0 +1,278 @@
+//! VLAN filtering over the VLAN Filter Table Array (VFTA), VLNCTRL and the pool VLAN filters (PFVLVF/PFVLVFB).
+//!
+//! The VFTA is a 4096-bit table with one bit per VLAN ID: when filtering is enabled in VLNCTRL,
+//! tagged packets whose VLAN ID bit is clear are dropped. In virtualized mode, a received VLAN is also looked up
+//! in the 64-entry pool VLAN filter, whose matching entry holds the bitmap of pools that are members of the VLAN.
+//! Tag stripping is done per Rx queue, in RXDCTL.
+//!
+//! A VLAN's VFTA bit is shared by the PF, which adds VLANs for itself, and by the pools that are members of it.
+//! The pools' membership is held in the pool VLAN filter, and the PF's in a [`PfVlanTable`],
+//! so that the bit is only cleared once neither the PF nor any pool uses the VLAN.
+
+use bit_field::BitField;
+use crate::{IntelIxgbeRxRegisters1, IntelIxgbeRxRegisters2, IntelIxgbeMacRegisters};
+
+/// The largest VLAN ID.
+pub const MAX_VLAN_ID: u16 = 4095;
+/// Number of entries in the pool VLAN filter table.
+pub const NUM_POOL_VLAN_FILTERS: usize = 64;
+/// Number of pools that can be members of a VLAN.
+pub const NUM_POOLS: u8 = 64;
+/// Number of VFTA registers.
+pub const VFTA_REGISTERS: usize = (MAX_VLAN_ID as usize + 1) / 32;
+
+// VLAN Control Register
+const VLNCTRL_VET: core::ops::Range<usize> = 0..16;
+const VLNCTRL_VFE: usize = 30;
+
+// Extended VLAN Ether Type
+const EXVET_VET_EXT: core::ops::Range<usize> = 16..32;
+
+// Receive Descriptor Control
+const RXDCTL_VME: usize = 30;
+
+// PF VM VLAN Pool Filter
+const PFVLVF_VLAN_ID: core::ops::Range<usize> = 0..12;
+const PFVLVF_VI_EN: usize = 31;
+
+/// Returns the VFTA register and the bit in it that hold the given VLAN ID.
+fn vfta_position(vlan: u16) -> (usize, usize) {
+    ((vlan >> 5) as usize, (vlan & 0x1F) as usize)
+}
+
+fn check_vlan(vlan: u16) -> Result<(), &'static str> {
+    if vlan > MAX_VLAN_ID {
+        return Err("VLAN ID is out of range");
+    }
+    Ok(())
+}
+
+/// The VLANs that the PF added for itself, as a bitmap laid out like the VFTA.
+/// It outlives the [`VlanFilter`] views of the registers, and is kept by the driver for as long as the port is up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PfVlanTable {
+    table: [u32; VFTA_REGISTERS],
+}
+
+impl Default for PfVlanTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PfVlanTable {
+    pub fn new() -> PfVlanTable {
+        PfVlanTable { table: [0; VFTA_REGISTERS] }
+    }
+
+    pub fn contains(&self, vlan: u16) -> bool {
+        if vlan > MAX_VLAN_ID {
+            return false;
+        }
+        let (index, bit) = vfta_position(vlan);
+        self.table[index].get_bit(bit)
+    }
+
+    fn set(&mut self, vlan: u16, member: bool) {
+        let (index, bit) = vfta_position(vlan);
+        self.table[index].set_bit(bit, member);
+    }
+}
+
+/// The VLAN filters of the port.
+pub struct VlanFilter<'a> {
+    rx_regs2: &'a mut IntelIxgbeRxRegisters2,
+    mac_regs: &'a mut IntelIxgbeMacRegisters,
+    pf_vlans: &'a mut PfVlanTable,
+}
+
+impl<'a> VlanFilter<'a> {
+    pub fn new(
+        rx_regs2: &'a mut IntelIxgbeRxRegisters2,
+        mac_regs: &'a mut IntelIxgbeMacRegisters,
+        pf_vlans: &'a mut PfVlanTable,
+    ) -> VlanFilter<'a> {
+        VlanFilter { rx_regs2, mac_regs, pf_vlans }
+    }
+
+    /// Accepts packets tagged with the VLAN ID for the PF when filtering is enabled.
+    pub fn add_vlan(&mut self, vlan: u16) -> Result<(), &'static str> {
+        check_vlan(vlan)?;
+        self.pf_vlans.set(vlan, true);
+        self.set_vfta(vlan, true);
+        Ok(())
+    }
+
+    /// Removes the PF from the VLAN. Packets tagged with the VLAN ID are dropped when filtering is enabled,
+    /// unless a pool is still a member of the VLAN.
+    pub fn remove_vlan(&mut self, vlan: u16) -> Result<(), &'static str> {
+        check_vlan(vlan)?;
+        self.pf_vlans.set(vlan, false);
+        if self.vlan_pools(vlan) == 0 {
+            self.set_vfta(vlan, false);
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, vlan: u16) -> bool {
+        if vlan > MAX_VLAN_ID {
+            return false;
+        }
+        let (index, bit) = vfta_position(vlan);
+        self.mac_regs.vlan_filter_table_array[index].read().get_bit(bit)
+    }
+
+    /// Removes all VLAN IDs from the VFTA and the PF, and clears the pool VLAN filter table.
+    pub fn clear(&mut self) {
+        *self.pf_vlans = PfVlanTable::new();
+        for vfta in self.mac_regs.vlan_filter_table_array.iter_mut() {
+            vfta.write(0);
+        }
+        for vlvf in self.mac_regs.pf_vm_vlan_pool_filter.iter_mut() {
+            vlvf.write(0);
+        }
+        for vlvfb in self.mac_regs.pf_vm_vlan_pool_filter_bitmap.iter_mut() {
+            vlvfb.write(0);
+        }
+    }
+
+    pub fn set_filtering(&mut self, enable: bool) {
+        self.rx_regs2.vlan_control_register.update(|vlnctrl| { vlnctrl.set_bit(VLNCTRL_VFE, enable); });
+    }
+
+    pub fn filtering_enabled(&self) -> bool {
+        self.rx_regs2.vlan_control_register.read().get_bit(VLNCTRL_VFE)
+    }
+
+    /// The EtherType of VLAN tags, 0x8100 by default.
+    pub fn vlan_ether_type(&self) -> u16 {
+        self.rx_regs2.vlan_control_register.read().get_bits(VLNCTRL_VET) as u16
+    }
+
+    /// Sets the EtherType of the outer VLAN tag of double tagged packets, 0x8100 by default.
+    pub fn set_extended_ether_type(&mut self, ether_type: u16) {
+        self.rx_regs2.extended_vlan_ether_type.update(|exvet| { exvet.set_bits(EXVET_VET_EXT, ether_type as u32); });
+    }
+
+    pub fn extended_ether_type(&self) -> u16 {
+        self.rx_regs2.extended_vlan_ether_type.read().get_bits(EXVET_VET_EXT) as u16
+    }
+
+    /// Enables or disables stripping of the VLAN tag from packets received on an Rx queue.
+    /// The stripped tag is reported in the Rx descriptor. Only the first 64 queues have their registers in `rx_regs`.
+    pub fn set_stripping(rx_regs: &mut IntelIxgbeRxRegisters1, queue: u8, enable: bool) -> Result<(), &'static str> {
+        let rx_queue = rx_regs.rx_regs1.get_mut(queue as usize).ok_or("Rx queue is out of range")?;
+        rx_queue.receive_descriptor_control.update(|rxdctl| { rxdctl.set_bit(RXDCTL_VME, enable); });
+        Ok(())
+    }
+
+    /// Makes a pool a member of the VLAN, using the VLAN's pool filter entry or a free one,
+    /// and adds the VLAN to the VFTA. Returns the index of the entry.
+    pub fn add_pool_vlan(&mut self, vlan: u16, pool: u8) -> Result<usize, &'static str> {
+        check_vlan(vlan)?;
+        if pool >= NUM_POOLS {
+            return Err("pool is out of range");
+        }
+        let index = match self.find_pool_vlan(vlan) {
+            Some(index) => index,
+            None => {
+                let index = (0..NUM_POOL_VLAN_FILTERS)
+                    .find(|&i| !self.mac_regs.pf_vm_vlan_pool_filter[i].read().get_bit(PFVLVF_VI_EN))
+                    .ok_or("pool VLAN filter table is full")?;
+                let mut vlvf = 0;
+                vlvf.set_bits(PFVLVF_VLAN_ID, vlan as u32);
+                vlvf.set_bit(PFVLVF_VI_EN, true);
+                self.mac_regs.pf_vm_vlan_pool_filter_bitmap[index * 2].write(0);
+                self.mac_regs.pf_vm_vlan_pool_filter_bitmap[index * 2 + 1].write(0);
+                self.mac_regs.pf_vm_vlan_pool_filter[index].write(vlvf);
+                index
+            }
+        };
+
+        let (register, bit) = (index * 2 + pool as usize / 32, pool as usize % 32);
+        self.mac_regs.pf_vm_vlan_pool_filter_bitmap[register].update(|vlvfb| { vlvfb.set_bit(bit, true); });
+        self.set_vfta(vlan, true);
+        Ok(index)
+    }
+
+    /// Removes a pool from the VLAN. When it was the last pool, the entry is freed,
+    /// and the VLAN is removed from the VFTA unless the PF added it too.
+    pub fn remove_pool_vlan(&mut self, vlan: u16, pool: u8) -> Result<(), &'static str> {
+        check_vlan(vlan)?;
+        if pool >= NUM_POOLS {
+            return Err("pool is out of range");
+        }
+        let index = self.find_pool_vlan(vlan).ok_or("VLAN has no pool filter entry")?;
+
+        let (register, bit) = (index * 2 + pool as usize / 32, pool as usize % 32);
+        self.mac_regs.pf_vm_vlan_pool_filter_bitmap[register].update(|vlvfb| { vlvfb.set_bit(bit, false); });
+        if self.pools(index) == 0 {
+            self.mac_regs.pf_vm_vlan_pool_filter[index].write(0);
+            if !self.pf_vlans.contains(vlan) {
+                self.set_vfta(vlan, false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a pool from every VLAN, freeing the entries of the VLANs it was the last pool of,
+    /// and their VFTA bits unless the PF added them too.
+    pub fn remove_pool(&mut self, pool: u8) -> Result<(), &'static str> {
+        if pool >= NUM_POOLS {
+            return Err("pool is out of range");
+        }
+        for index in 0..NUM_POOL_VLAN_FILTERS {
+            let vlvf = self.mac_regs.pf_vm_vlan_pool_filter[index].read();
+            if vlvf.get_bit(PFVLVF_VI_EN) && self.pools(index).get_bit(pool as usize) {
+                self.remove_pool_vlan(vlvf.get_bits(PFVLVF_VLAN_ID) as u16, pool)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the bitmap of pools that are members of the VLAN.
+    pub fn vlan_pools(&self, vlan: u16) -> u64 {
+        self.find_pool_vlan(vlan).map_or(0, |index| self.pools(index))
+    }
+
+    fn find_pool_vlan(&self, vlan: u16) -> Option<usize> {
+        (0..NUM_POOL_VLAN_FILTERS).find(|&i| {
+            let vlvf = self.mac_regs.pf_vm_vlan_pool_filter[i].read();
+            vlvf.get_bit(PFVLVF_VI_EN) && vlvf.get_bits(PFVLVF_VLAN_ID) == vlan as u32
+        })
+    }
+
+    fn pools(&self, index: usize) -> u64 {
+        let low = self.mac_regs.pf_vm_vlan_pool_filter_bitmap[index * 2].read() as u64;
+        let high = self.mac_regs.pf_vm_vlan_pool_filter_bitmap[index * 2 + 1].read() as u64;
+        (high << 32) | low
+    }
+
+    fn set_vfta(&mut self, vlan: u16, member: bool) {
+        let (index, bit) = vfta_position(vlan);
+        self.mac_regs.vlan_filter_table_array[index].update(|vfta| { vfta.set_bit(bit, member); });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vfta_position_splits_the_vlan_id() {
+        assert_eq!(vfta_position(0), (0, 0));
+        assert_eq!(vfta_position(100), (3, 4));
+        assert_eq!(vfta_position(MAX_VLAN_ID), (VFTA_REGISTERS - 1, 31));
+    }
+
+    #[test]
+    fn pf_vlan_table_tracks_membership() {
+        let mut table = PfVlanTable::new();
+        table.set(100, true);
+        table.set(4095, true);
+        assert!(table.contains(100) && table.contains(4095));
+        assert!(!table.contains(101) && !table.contains(4096));
+        table.set(100, false);
+        assert!(!table.contains(100) && table.contains(4095));
+    }
+}
@@ -1,16 +1,19 @@
 import csv
+import math
 import os
 import re
 from parser.register_parser import snake_case
-from parser.handle_offset import query_openai_offset_expression
+from parser.handle_offset import (
+    parse_offset_expression, array_type_declaration, accessor_code, is_contiguous, StridedArray,
+)
 
 # File paths
 registers_summary_path = './registers_summary.csv'
 registers_info_path = './register_info'
 
-def calculate_padding(offset1, offset2):
-    """Calculate the padding size between two offsets."""
-    return offset2 - offset1 - 4
+def calculate_padding(previous_end, offset):
+    """Calculate the padding size between the end of the previous register and the next offset."""
+    return offset - previous_end
 
 def parse_register_info(file_path):
     """Parse the detailed register info CSV file to determine field size."""
@@ -41,82 +44,148 @@ def extract_base_offset(offset_str):
     else:
         raise ValueError(f"Invalid offset format: {offset_str}")
 
-def handle_offset(offset_str, rw_type):
-    """Handle offset expressions or simple addresses."""
+def handle_offset(offset_str):
+    """Handle offset expressions or simple addresses, returning the offset and the array dimensions."""
     if re.match(r'^0x[0-9A-Fa-f]+$', offset_str):
         # If it's a simple address, parse it directly
-        return int(offset_str, 16), None
+        return int(offset_str, 16), []
+    parsed = parse_offset_expression(offset_str)
+    if parsed is None:
+        # Ranges such as 0x09400-0x097FC are kept as a single register at their base
+        print(f"Could not parse offset expression: {offset_str}")
+        return extract_base_offset(offset_str), []
+    return parsed
+
+def field_name(register_name):
+    """A Rust identifier from a register name, such as gbase_x_pcs_status for 10GBASE-X PCS Status."""
+    identifier = re.sub(r'[^a-z0-9]+', '_', snake_case(register_name).replace("'", ""))
+    return re.sub(r'^[0-9_]+', '', identifier).strip('_')
+
+def register_field_type(rw_type):
+    """The volatile wrapper of a register, from its access type."""
+    if rw_type == "RW":
+        return "Volatile<u32>"
+    elif rw_type == "RO":
+        return "ReadOnly<u32>"
+    elif rw_type == "WO":
+        return "WriteOnly<u32>"
+    elif rw_type == "RC":
+        return "RC<u32>"
     else:
-        match = re.match(r'^0x[0-9A-Fa-f]+', offset_str)
-        if match:
-            base_offset = int(match.group(0), 16)
-            # Call OpenAI to process the rest of the complex expression
-            return base_offset, query_openai_offset_expression(offset_str, rw_type)
-        else:
-            raise ValueError(f"Invalid offset format: {offset_str}")
+        return "Reserved<u32>"
+
+def offset_test_code(checks):
+    """A test that checks every field against the offset of its register in the summary."""
+    code = "\n#[cfg(test)]\nmod tests {\n    use super::*;\n    use core::mem::{offset_of, size_of};\n\n"
+    code += "    #[test]\n    fn registers_are_at_their_datasheet_offsets() {\n"
+    for expression, offset in checks:
+        code += f"        assert_eq!({expression}, 0x{offset:X});\n"
+    code += "    }\n}\n"
+    return code
 
 def generate_struct_code(output_rust_file):
     """Generate Rust struct code based on the register summary and detailed info."""
-    struct_code = "#[derive(FromBytes)]\n#[repr(C)]\npub struct Registers {\n"
+    struct_code = "#[derive(FromZeroes, FromBytes)]\n#[repr(C)]\npub struct Registers {\n"
     
-    previous_offset = None
+    previous_end = None
+    # Accessors of the register arrays with more than one index, and of the registers sharing a strided array
+    accessors = ""
+    # Element structs of the strided arrays, which are declared before the registers
+    element_structs = ""
+    # Pairs of an expression for the offset of a field and the offset of its register
+    offset_checks = []
+    field_names = set()
 
     # Read and sort the register summary by the base part of the Offset field
     with open(registers_summary_path, 'r') as f:
         reader = csv.DictReader(f)
         rows = sorted(reader, key=lambda row: extract_base_offset(row['Offset']))
 
+    # Registers repeated at a stride larger than 4 bytes are gathered into strided arrays first,
+    # so the registers of one array can be interleaved with those of another
+    entries = []
+    strided_arrays = []
     for idx, row in enumerate(rows):
-        offset_str = row['Offset']
-        name = snake_case(row['Name'])
-        rw_type = row['RW']
-
-        offset, field_type_declaration = handle_offset(offset_str, rw_type)
-
-        if offset is None:
-            if field_type_declaration:
-                struct_code += f"    {name}: {field_type_declaration},\n"
-            else:
-                print(f"Skipping entry with non-parsable offset: {offset_str}")
-            continue
+        name = field_name(row['Name'])
+        field_type = register_field_type(row['RW'])
+        offset, dimensions = handle_offset(row['Offset'])
+        print(f"Parsed offset for {name}: 0x{offset:X} {dimensions}")
+
+        if len(dimensions) == 1 and dimensions[0].stride > 4:
+            dimension = dimensions[0]
+            strided_array = next((s for s in strided_arrays if s.covers(offset, dimension.stride)), None)
+            if strided_array is None:
+                strided_array = StridedArray(offset, dimension.stride, row['Block'])
+                strided_arrays.append(strided_array)
+                entries.append((idx, offset, strided_array))
+            strided_array.add(name, field_type, row['Name'], row['Abbreviation'], offset, dimension.count)
         else:
-            print(f"Parsed offset for {name}: 0x{offset:X}")
-
-        # Determine field type based on RW type
-        if field_type_declaration is None:
-            if rw_type == "RW":
-                field_type = "Volatile<u32>"
-            elif rw_type == "RO":
-                field_type = "ReadOnly<u32>"
-            elif rw_type == "WO":
-                field_type = "WriteOnly<u32>"
-            else:
-                field_type = "Reserved<u32>"
-
-            register_info_file = os.path.join(registers_info_path, f"{name}.csv")
-            if os.path.exists(register_info_file):
-                fields = parse_register_info(register_info_file)
-                register_size = calculate_register_size(fields)
-                field_type_declaration = f"[{field_type}; {register_size // 4}]"
+            if dimensions and not is_contiguous(dimensions):
+                raise ValueError(f"{row['Name']} at {row['Offset']} has gaps between the registers of an inner dimension")
+            entries.append((idx, offset, (row, name, field_type, dimensions)))
+
+    for idx, offset, entry in entries:
+        if isinstance(entry, StridedArray):
+            element_name = entry.name()
+            name = snake_case(re.sub(r'(?<!^)(?=[A-Z])', ' ', element_name))
+            element_structs += entry.element_struct_code() + "\n"
+            field_type_declaration = f"[{element_name}; {entry.count}]"
+            field_size = entry.size()
+            description = f"{element_name} elements, every 0x{entry.stride:X} bytes"
+            accessors += entry.accessor_code(name)
+            offset_checks.append((f"size_of::<{element_name}>()", entry.stride))
+            for member in entry.members:
+                offset_checks.append((f"offset_of!(Registers, {name}) + offset_of!({element_name}, {member.field_name})", offset + member.slot))
+            for alias, owner in entry.aliases:
+                offset_checks.append((
+                    f"offset_of!(Registers, {name}) + {alias.first} * size_of::<{element_name}>() + offset_of!({element_name}, {owner.field_name})",
+                    alias.offset,
+                ))
+        else:
+            row, name, field_type, dimensions = entry
+            description = row['Name']
+            # Size in bytes of the field, so the padding after an array starts past its last element
+            field_size = 4
+            if dimensions:
+                field_type_declaration = array_type_declaration(field_type, dimensions)
+                field_size = 4 * math.prod(dimension.count for dimension in dimensions)
+                if len(dimensions) > 1:
+                    accessor_name = snake_case(re.sub(r'\[.*\]', '', row['Abbreviation']))
+                    accessors += accessor_code(accessor_name, name, field_type, offset, dimensions, row['Name'])
             else:
-                field_type_declaration = field_type
-
-        if previous_offset is not None:
+                register_info_file = os.path.join(registers_info_path, f"{snake_case(row['Name'])}.csv")
+                if os.path.exists(register_info_file):
+                    fields = parse_register_info(register_info_file)
+                    register_size = calculate_register_size(fields)
+                    field_type_declaration = f"[{field_type}; {register_size // 4}]"
+                    field_size = register_size
+                else:
+                    field_type_declaration = field_type
+            offset_checks.append((f"offset_of!(Registers, {name})", offset))
+
+        if name in field_names:
+            raise ValueError(f"{description} at 0x{offset:X} has the same field name as another register: {name}")
+        field_names.add(name)
+
+        if previous_end is not None:
             # Calculate the padding required
-            padding = calculate_padding(previous_offset, offset)
+            padding = calculate_padding(previous_end, offset)
             if padding > 0:
-                struct_code += f"    _padding{idx-1}: [u8; {padding}], // 0x{previous_offset + 4:X} - 0x{offset - 1:X}\n\n"
-            else:
-                print(f"Skipping 0 padding: {padding}\n")
+                struct_code += f"    _padding{idx-1}: [u8; {padding}], // 0x{previous_end:X} - 0x{offset - 1:X}\n\n"
+            elif padding < 0:
+                raise ValueError(f"{description} at 0x{offset:X} overlaps the previous register, which ends at 0x{previous_end:X}")
 
-        struct_code += f"    /// {row['Name']}\n"
+        struct_code += f"    /// {description}\n"
         struct_code += f"    {name}: {field_type_declaration}, // 0x{offset:X}\n"
-        previous_offset = offset
+        previous_end = offset + field_size
 
     struct_code += "}\n"
+    if accessors:
+        struct_code += "\nimpl Registers {\n" + accessors + "}\n"
+    struct_code += offset_test_code(offset_checks)
 
     # Write the struct code to a Rust file
     with open(output_rust_file, 'w') as rust_file:
-        rust_file.write(struct_code)
+        rust_file.write(element_structs + struct_code)
 
     print(f"Done! Rust struct has been written to {output_rust_file}")
@@ -0,0 +1,197 @@
+import re
+
+# A stride term of an offset expression, such as 0x10*n, 4*m or 4*(n-24)
+STRIDE_TERM = re.compile(r'^(0x[0-9A-Fa-f]+|\d+)\*(?:([a-z])|\(([a-z])-(\d+)\))$')
+# The range of an index variable, such as n=0...3, n=1..2 or n=[0...3]
+INDEX_RANGE = re.compile(r'^([a-z])=\[?(\d+)\.\.\.?(\d+)\]?$')
+
+
+class ArrayDimension:
+    """One index of a register array, with its own stride and bound."""
+
+    def __init__(self, index, stride, count):
+        self.index = index
+        self.stride = stride
+        self.count = count
+
+    def __repr__(self):
+        return f"{self.index}: {self.count} x 0x{self.stride:X}"
+
+
+def parse_offset_expression(offset_str):
+    """
+    Parse an offset expression like 0x08F20+0x10*n+4*m&n=0...1&m=0...3.
+    Returns the offset of the first register and its array dimensions ordered from the largest stride
+    to the smallest, so that they can be nested outermost first. Returns None if the expression isn't understood.
+    """
+    address, *ranges = offset_str.replace(' ', '').split('&')
+    base, *terms = address.split('+')
+    if not re.match(r'^0x[0-9A-Fa-f]+$', base):
+        return None
+    offset = int(base, 16)
+
+    bounds = {}
+    for index_range in ranges:
+        match = INDEX_RANGE.match(index_range)
+        if not match:
+            return None
+        bounds[match.group(1)] = (int(match.group(2)), int(match.group(3)))
+
+    dimensions = []
+    for term in terms:
+        match = STRIDE_TERM.match(term)
+        if not match:
+            return None
+        stride = int(match.group(1), 0)
+        index = match.group(2) or match.group(3)
+        # the index the base offset corresponds to, which is only non zero for terms like 4*(n-24)
+        origin = int(match.group(4) or 0)
+        if index not in bounds:
+            return None
+        first, last = bounds.pop(index)
+        if last < first:
+            return None
+        offset += stride * (first - origin)
+        dimensions.append(ArrayDimension(index, stride, last - first + 1))
+
+    if bounds:
+        # a range was given for an index that isn't used in the address
+        return None
+    dimensions.sort(key=lambda dimension: dimension.stride, reverse=True)
+    return offset, dimensions
+
+
+def array_type_declaration(field_type, dimensions):
+    """Nest the register type in one array per dimension, e.g. [[WriteOnly<u32>; 4]; 2] for LSECRXKEY[n,m]."""
+    declaration = field_type
+    for dimension in reversed(dimensions):
+        declaration = f"[{declaration}; {dimension.count}]"
+    return declaration
+
+
+def accessor_code(accessor_name, field_name, field_type, offset, dimensions, description):
+    """
+    Generate an accessor for a multi-dimensional register array that takes one bounded index per dimension,
+    so each index is checked against the bound of its own dimension.
+    """
+    parameters = ", ".join(f"{dimension.index}: RegisterIndex<{dimension.count}>" for dimension in dimensions)
+    indexing = "".join(f"[{dimension.index}.get()]" for dimension in dimensions)
+    strides = " + ".join(f"0x{dimension.stride:X} * {dimension.index}" for dimension in dimensions)
+    return (
+        f"    /// {description}, at 0x{offset:X} + {strides}.\n"
+        f"    pub fn {accessor_name}(&mut self, {parameters}) -> &mut {field_type} {{\n"
+        f"        &mut self.{field_name}{indexing}\n"
+        f"    }}\n"
+    )
+
+
+def is_contiguous(dimensions):
+    """Whether the registers of an array follow each other without gaps, so it can be declared as nested arrays."""
+    size = 4
+    for dimension in reversed(dimensions):
+        if dimension.stride != size:
+            return False
+        size *= dimension.count
+    return True
+
+
+def camel_case(s):
+    """Convert a register name such as Receive Address to a struct name such as ReceiveAddress."""
+    return "".join(word[:1].upper() + word[1:].lower() for word in re.split(r'[\s/\-]+', s) if word)
+
+
+class StridedMember:
+    """A register of a strided array, at a fixed offset inside every element from its first element on."""
+
+    def __init__(self, field_name, field_type, description, abbreviation, offset, slot, first, count):
+        self.field_name = field_name
+        self.field_type = field_type
+        self.description = description
+        self.abbreviation = abbreviation
+        self.offset = offset
+        self.slot = slot
+        self.first = first
+        self.count = count
+
+
+class StridedArray:
+    """
+    Registers repeated at a stride larger than the register, such as RAL and RAH at 0x0A200+8*n and 0x0A204+8*n.
+    Declaring them as [T; N] would pack them together, so they are laid out as an array of element structs
+    that are padded to the stride, with every register that repeats at the same stride as one of their fields.
+    """
+
+    def __init__(self, offset, stride, block):
+        self.offset = offset
+        self.stride = stride
+        self.block = block
+        self.count = 0
+        self.members = []
+        # Registers that reuse the slot of a member in the elements that member doesn't cover,
+        # such as QPRDC at 0x01430+0x40*n which is at the offset of QPRC in the elements 16 to 31
+        self.aliases = []
+
+    def covers(self, offset, stride):
+        return stride == self.stride and self.offset <= offset < self.offset + self.stride * max(self.count, 1)
+
+    def add(self, member_name, field_type, description, abbreviation, offset, count):
+        slot = (offset - self.offset) % self.stride
+        first = (offset - self.offset) // self.stride
+        if slot + 4 > self.stride:
+            raise ValueError(f"{description} at 0x{offset:X} doesn't fit in a stride of 0x{self.stride:X}")
+        member = StridedMember(member_name, field_type, description, abbreviation, offset, slot, first, count)
+        owner = next((m for m in self.members if m.slot == slot), None)
+        if owner is None:
+            self.members.append(member)
+        elif owner.first + owner.count <= first or first + count <= owner.first:
+            self.aliases.append((member, owner))
+        else:
+            raise ValueError(
+                f"{description} at 0x{offset:X} overlaps {owner.description} at 0x{owner.offset:X} in the same elements"
+            )
+        self.count = max(self.count, first + count)
+
+    def name(self):
+        """Name of the element struct, from the words the member names start with or else from their block."""
+        names = [member.description.split() for member in self.members]
+        common = []
+        for words in zip(*names):
+            if any(word != words[0] for word in words):
+                break
+            common.append(words[0])
+        if not common:
+            common = [self.block, "Registers"]
+        return camel_case(" ".join(common))
+
+    def size(self):
+        return self.stride * self.count
+
+    def element_struct_code(self):
+        """The element struct, with each member at its slot and padded to the stride."""
+        code = f"#[derive(FromZeroes, FromBytes)]\n#[repr(C)]\npub struct {self.name()} {{\n"
+        end = 0
+        for idx, member in enumerate(sorted(self.members, key=lambda member: member.slot)):
+            if member.slot > end:
+                code += f"    _padding{idx}: [u8; {member.slot - end}], // 0x{end:X} - 0x{member.slot - 1:X}\n\n"
+            code += f"    /// {member.description}\n"
+            if member.first or member.count != self.count:
+                code += f"    /// Only in the elements {member.first} to {member.first + member.count - 1}.\n"
+            code += f"    {member.field_name}: {member.field_type}, // 0x{member.slot:X}\n"
+            end = member.slot + 4
+        if end < self.stride:
+            code += f"    _padding{len(self.members)}: [u8; {self.stride - end}], // 0x{end:X} - 0x{self.stride - 1:X}\n"
+        code += "}\n"
+        return code
+
+    def accessor_code(self, field_name):
+        """Accessors of the aliases, which index the elements of their own range."""
+        code = ""
+        for alias, owner in self.aliases:
+            accessor_name = re.sub(r'[^a-z0-9_]', '', re.sub(r'\[.*\]', '', alias.abbreviation).lower())
+            code += (
+                f"    /// {alias.description}, at 0x{alias.offset:X} + 0x{self.stride:X} * n.\n"
+                f"    pub fn {accessor_name}(&mut self, n: RegisterIndex<{alias.count}>) -> &mut {alias.field_type} {{\n"
+                f"        &mut self.{field_name}[{alias.first} + n.get()].{owner.field_name}\n"
+                f"    }}\n"
+            )
+        return code
@@ -24,6 +24,7 @@ def extract_table_data(pdf_text):
         "Please remove all the spaced after commas; When parsing the Offset, you might encounter hex expressions type of 0x0E800+4*n, n=1..3.\n"
         "Since hanving comma in parsing such epxressions would ruin the CSV format, please format these addresses to 0x0E800+4*n&n=1..3 (use & instead).\n"
         "For the Nname column, make sure that there are not (...) or [...] inside. Please remove them and leave only text beyond.\n\n"
+        "If an Abbreviation contains a comma, such as LSECRXKEY[n,m], wrap it in double quotes so it stays a single column.\n\n"
         "Return only the extracted data, with no additional comments or explanations; not csv word in the bginning or anything.\n"
         "Your output should be able to completely be copied to a CSV file!\n\n"
         "The header line is not needed in the extracted data; it is already present in the file.\n\n"